@@ -0,0 +1,73 @@
+// compares drawing a second worth of points (4500) with a raqote path per
+// point against writing them straight into the pixel buffer
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use lidar::raster;
+use raqote::{AntialiasMode, DrawOptions, DrawTarget, PathBuilder, SolidSource, Source};
+
+const WIDTH: i32 = 800;
+const HEIGHT: i32 = 800;
+const POINTS: usize = 4500;
+
+// a ring of points with a bit of wobble so they land on fractional positions
+fn points() -> Vec<(f32, f32)> {
+    (0..POINTS)
+        .map(|i| {
+            let theta = (i as f32 / POINTS as f32) * std::f32::consts::TAU;
+            let r = 300.0 + 50.0 * (theta * 7.0).sin();
+            (400.0 + r * theta.cos(), 400.0 + r * theta.sin())
+        })
+        .collect()
+}
+
+fn raqote_points(dt: &mut DrawTarget, points: &[(f32, f32)], antialias: AntialiasMode) {
+    let source = Source::Solid(SolidSource {
+        r: 0x00,
+        g: 0xff,
+        b: 0x00,
+        a: 0xff,
+    });
+    let options = DrawOptions {
+        antialias,
+        ..DrawOptions::new()
+    };
+    for &(x, y) in points {
+        let mut path = PathBuilder::new();
+        path.rect(x, y, 1.0, 1.0);
+        dt.fill(&path.finish(), &source, &options);
+    }
+}
+
+fn bench_points(c: &mut Criterion) {
+    let points = points();
+    let color = raster::pack(0x00, 0xff, 0x00);
+    let mut group = c.benchmark_group("draw 4500 points");
+
+    group.bench_function("raqote path", |b| {
+        let mut dt = DrawTarget::new(WIDTH, HEIGHT);
+        b.iter(|| raqote_points(&mut dt, black_box(&points), AntialiasMode::Gray))
+    });
+    group.bench_function("raqote path, no aa", |b| {
+        let mut dt = DrawTarget::new(WIDTH, HEIGHT);
+        b.iter(|| raqote_points(&mut dt, black_box(&points), AntialiasMode::None))
+    });
+    group.bench_function("direct", |b| {
+        let mut dt = DrawTarget::new(WIDTH, HEIGHT);
+        b.iter(|| {
+            for &(x, y) in black_box(&points) {
+                raster::plot(dt.get_data_mut(), WIDTH as usize, x, y, 1.0, color);
+            }
+        })
+    });
+    group.bench_function("direct, aa", |b| {
+        let mut dt = DrawTarget::new(WIDTH, HEIGHT);
+        b.iter(|| {
+            for &(x, y) in black_box(&points) {
+                raster::plot_aa(dt.get_data_mut(), WIDTH as usize, x, y, 1.0, color);
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_points);
+criterion_main!(benches);
@@ -0,0 +1,105 @@
+// command line arguments. there's only a handful so they're parsed by hand
+use lidar::safety::FieldLevel;
+use std::path::PathBuf;
+
+pub const USAGE: &str = "\
+usage: lidar [options]
+
+options:
+  --config <file>           load a config file, see config.example.toml
+  --port <path>             serial port the ld19 is on, for sensors without
+                            a port in the config (default /dev/tty.usbserial-0001)
+  --capture <file>          write every packet received to a capture file,
+                            one per sensor named <file>-<sensor> if several
+  --compare <a> [b]         compare capture a against capture b, or against
+                            live data if b isn't given
+  --compare-sensor <n>      which sensor's live data to compare against, by
+                            its order in the config (default 0)
+  --map <file.yaml>         show a ros map_server map underneath the points
+  --simulate                drive a simulated sensor around a made up room
+                            instead of opening the serial port
+  --exit-on <level>         exit once the safety fields reach warning, slow or
+                            stop, with status 1, 2 or 3 for the level reached
+  --help                    show this message";
+
+#[derive(Debug)]
+pub struct Args {
+    pub config: Option<PathBuf>,
+    pub port: String,
+    pub capture: Option<PathBuf>,
+    pub compare: Vec<PathBuf>,
+    pub compare_sensor: u8,
+    pub map: Option<PathBuf>,
+    pub simulate: bool,
+    pub exit_on: Option<FieldLevel>,
+}
+
+impl Default for Args {
+    fn default() -> Self {
+        Self {
+            config: None,
+            port: String::from("/dev/tty.usbserial-0001"),
+            capture: None,
+            compare: Vec::new(),
+            compare_sensor: 0,
+            map: None,
+            simulate: false,
+            exit_on: None,
+        }
+    }
+}
+
+impl Args {
+    pub fn parse() -> Result<Self, String> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    pub fn parse_from<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut parsed = Args::default();
+        let mut args = args.into_iter().peekable();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => parsed.config = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--port" => parsed.port = value(&arg, args.next())?,
+                "--capture" => parsed.capture = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--compare" => {
+                    parsed
+                        .compare
+                        .push(PathBuf::from(value(&arg, args.next())?));
+                    // the second capture is optional
+                    if let Some(b) = args.next_if(|next| !next.starts_with("--")) {
+                        parsed.compare.push(PathBuf::from(b));
+                    }
+                }
+                "--compare-sensor" => {
+                    let n = value(&arg, args.next())?;
+                    parsed.compare_sensor = n
+                        .parse()
+                        .map_err(|_| format!("{arg} needs a sensor number, not {n}"))?;
+                }
+                "--map" => parsed.map = Some(PathBuf::from(value(&arg, args.next())?)),
+                "--simulate" => parsed.simulate = true,
+                "--exit-on" => {
+                    parsed.exit_on = Some(match value(&arg, args.next())?.as_str() {
+                        "warning" => FieldLevel::Warning,
+                        "slow" => FieldLevel::Slow,
+                        "stop" => FieldLevel::Stop,
+                        other => return Err(format!("unknown safety level {other}")),
+                    })
+                }
+                "--help" | "-h" => return Err(String::new()),
+                _ => return Err(format!("unknown argument {arg}")),
+            }
+        }
+        Ok(parsed)
+    }
+
+    /// true if the serial port needs to be opened at all
+    pub fn wants_live(&self) -> bool {
+        self.compare.len() < 2 && !self.simulate
+    }
+}
+
+fn value(arg: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("{arg} needs a value"))
+}
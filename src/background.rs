@@ -0,0 +1,236 @@
+// background subtraction. the model spends the first few revolutions learning
+// how far away things normally are at every angle, after that anything that
+// comes back noticeably closer than that is foreground
+use crate::profile::median;
+use crate::scan::{Scan, ScanPoint};
+
+#[derive(Debug, Clone, Copy)]
+pub struct BackgroundConfig {
+    // revolutions to learn from, about five seconds at the default speed
+    pub revolutions: usize,
+    // bin width in degrees, at least the ld19's ~0.8 degree spacing so every
+    // bin gets a return every revolution
+    pub resolution: f32,
+    // how much closer than the background a return has to be to count as
+    // foreground, millimeters plus a fraction of the background range
+    pub threshold: f32,
+    pub threshold_ratio: f32,
+}
+
+impl Default for BackgroundConfig {
+    fn default() -> Self {
+        Self {
+            revolutions: 50,
+            resolution: 1.0,
+            threshold: 100.0,
+            threshold_ratio: 0.03,
+        }
+    }
+}
+
+/// the learned background of one sensor
+#[derive(Debug, Clone)]
+pub struct BackgroundModel {
+    pub config: BackgroundConfig,
+    bins: usize,
+    // every range seen per bin while learning, and how many revolutions each
+    // bin got anything at all in. a bin can catch more than one return a
+    // revolution
+    samples: Vec<Vec<f32>>,
+    seen: Vec<usize>,
+    learned: usize,
+    // median range per bin once learned, None where nothing ever came back
+    background: Option<Vec<Option<f32>>>,
+}
+
+impl BackgroundModel {
+    pub fn new(config: BackgroundConfig) -> Self {
+        let resolution = config.resolution.clamp(0.1, 360.0);
+        let bins = (360.0 / resolution).round() as usize;
+        Self {
+            config: BackgroundConfig {
+                revolutions: config.revolutions.max(1),
+                resolution,
+                ..config
+            },
+            bins,
+            samples: vec![Vec::new(); bins],
+            seen: vec![0; bins],
+            learned: 0,
+            background: None,
+        }
+    }
+
+    fn bin_of(&self, angle: f32) -> usize {
+        (angle.rem_euclid(360.0) / self.config.resolution) as usize % self.bins
+    }
+
+    pub fn is_learned(&self) -> bool {
+        self.background.is_some()
+    }
+
+    /// how far through learning, 0..1
+    pub fn progress(&self) -> f32 {
+        if self.is_learned() {
+            1.0
+        } else {
+            self.learned as f32 / self.config.revolutions as f32
+        }
+    }
+
+    /// forget the background and start learning again
+    pub fn reset(&mut self) {
+        *self = Self::new(self.config);
+    }
+
+    /// feed a revolution to the model. while learning it goes into the
+    /// background and nothing is foreground, after that the foreground points
+    /// are handed back
+    pub fn push(&mut self, scan: &Scan) -> Vec<ScanPoint> {
+        if self.is_learned() {
+            return self.foreground(scan);
+        }
+        let mut hit = vec![false; self.bins];
+        for p in scan.points.iter().filter(|p| p.distance != 0) {
+            let bin = self.bin_of(p.angle);
+            self.samples[bin].push(p.distance as f32);
+            hit[bin] = true;
+        }
+        for (seen, hit) in self.seen.iter_mut().zip(hit) {
+            *seen += hit as usize;
+        }
+        self.learned += 1;
+        if self.learned >= self.config.revolutions {
+            // a bin that only got a return now and then is mostly empty, and
+            // treating it as empty means anything showing up there counts
+            let min_revolutions = self.config.revolutions / 2;
+            let samples = std::mem::take(&mut self.samples);
+            self.background = Some(
+                samples
+                    .into_iter()
+                    .zip(&self.seen)
+                    .map(|(s, seen)| {
+                        if *seen < min_revolutions {
+                            None
+                        } else {
+                            median(s)
+                        }
+                    })
+                    .collect(),
+            );
+        }
+        Vec::new()
+    }
+
+    /// points in front of the background. empty until learned
+    pub fn foreground(&self, scan: &Scan) -> Vec<ScanPoint> {
+        let Some(background) = self.background.as_ref() else {
+            return Vec::new();
+        };
+        scan.points
+            .iter()
+            .filter(|p| p.distance != 0)
+            .filter(|p| {
+                let bin = self.bin_of(p.angle);
+                let range = p.distance as f32;
+                // the neighbouring bins as well, so an edge in the background
+                // that drifts across a bin boundary doesn't light up
+                [self.bins - 1, 0, 1].iter().all(|offset| {
+                    match background[(bin + offset) % self.bins] {
+                        Some(back) => {
+                            range
+                                < back - self.config.threshold - back * self.config.threshold_ratio
+                        }
+                        None => true,
+                    }
+                })
+            })
+            .copied()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(angle: f32, distance: u16) -> ScanPoint {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        ScanPoint {
+            x: distance as f32 * cos,
+            y: distance as f32 * sin,
+            angle,
+            distance,
+            intensity: 200,
+            sensor: 0,
+        }
+    }
+
+    fn scan(points: Vec<ScanPoint>) -> Scan {
+        Scan {
+            sensor: 0,
+            revolution: 0,
+            timestamp: 0,
+            points,
+        }
+    }
+
+    // a round room 3 m across, one return per degree
+    fn room() -> Vec<ScanPoint> {
+        (0..360).map(|a| point(a as f32 + 0.5, 3000)).collect()
+    }
+
+    fn learned(
+        config: BackgroundConfig,
+        revolution: impl Fn(usize) -> Vec<ScanPoint>,
+    ) -> BackgroundModel {
+        let mut model = BackgroundModel::new(config);
+        for i in 0..config.revolutions {
+            assert!(!model.is_learned());
+            assert!(model.push(&scan(revolution(i))).is_empty());
+        }
+        assert!(model.is_learned());
+        model
+    }
+
+    #[test]
+    fn closer_returns_are_foreground() {
+        let config = BackgroundConfig {
+            revolutions: 10,
+            ..BackgroundConfig::default()
+        };
+        let mut model = learned(config, |_| room());
+        assert_eq!(model.progress(), 1.0);
+        let mut points = room();
+        // somebody 1 m in at 90 degrees, and a wall return a bit short
+        points[90] = point(90.5, 2000);
+        points[200] = point(200.5, 2950);
+        let foreground = model.push(&scan(points));
+        assert_eq!(foreground.len(), 1);
+        assert_eq!(foreground[0].distance, 2000);
+    }
+
+    #[test]
+    fn bins_need_returns_in_half_the_revolutions() {
+        let config = BackgroundConfig {
+            revolutions: 8,
+            ..BackgroundConfig::default()
+        };
+        // bin 10 gets two returns every fourth revolution, as many points as
+        // bin 20 gets from one every other revolution
+        let model = learned(config, |i| {
+            let mut points = Vec::new();
+            if i % 4 == 0 {
+                points.extend([point(10.2, 3000), point(10.7, 3000)]);
+            }
+            if i % 2 == 0 {
+                points.push(point(20.5, 3000));
+            }
+            points
+        });
+        // nothing learned at 10, so anything there is foreground
+        let foreground = model.foreground(&scan(vec![point(10.5, 2990), point(20.5, 2990)]));
+        assert_eq!(foreground.len(), 1);
+        assert_eq!(foreground[0].angle, 10.5);
+    }
+}
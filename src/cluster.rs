@@ -0,0 +1,387 @@
+// grouping scan points into objects. breakpoint segmentation walks one
+// sensor's scan in angle order and cuts wherever neighbours jump apart,
+// dbscan doesn't care about order so it works on fused scans too
+use crate::scan::{Scan, ScanPoint};
+use core::fmt;
+use std::collections::HashMap;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ClusterMethod {
+    #[default]
+    Breakpoint,
+    Dbscan,
+}
+
+impl fmt::Display for ClusterMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClusterMethod::Breakpoint => write!(f, "breakpoint"),
+            ClusterMethod::Dbscan => write!(f, "dbscan"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ClusterConfig {
+    pub method: ClusterMethod,
+    // breakpoint: neighbours farther apart than this start a new cluster,
+    // millimeters plus a fraction of the range since points spread out
+    // farther away
+    pub max_gap: f32,
+    pub max_gap_ratio: f32,
+    // dbscan: neighbourhood radius in millimeters
+    pub eps: f32,
+    // clusters smaller than this are dropped. for dbscan it's also how many
+    // neighbours make a core point
+    pub min_points: usize,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            method: ClusterMethod::default(),
+            max_gap: 100.0,
+            max_gap_ratio: 0.03,
+            eps: 120.0,
+            min_points: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Cluster {
+    pub points: Vec<ScanPoint>,
+    // robot frame millimeters
+    pub centroid: (f32, f32),
+    // axis aligned, min and max corners
+    pub min: (f32, f32),
+    pub max: (f32, f32),
+    pub mean_intensity: f32,
+}
+
+impl Cluster {
+    pub fn new(points: Vec<ScanPoint>) -> Self {
+        let n = points.len().max(1) as f32;
+        let mut min = (f32::MAX, f32::MAX);
+        let mut max = (f32::MIN, f32::MIN);
+        let (mut sx, mut sy, mut si) = (0.0, 0.0, 0.0);
+        for p in &points {
+            min = (min.0.min(p.x), min.1.min(p.y));
+            max = (max.0.max(p.x), max.1.max(p.y));
+            sx += p.x;
+            sy += p.y;
+            si += p.intensity as f32;
+        }
+        Self {
+            centroid: (sx / n, sy / n),
+            min,
+            max,
+            mean_intensity: si / n,
+            points,
+        }
+    }
+
+    /// how far across the cluster is at its widest, millimeters. a better
+    /// idea of size than the bounding box, which grows when an object sits at
+    /// an angle. it's measured along eight directions in one pass, so it comes
+    /// out within 2% of the distance between the two points farthest apart
+    pub fn extent(&self) -> f32 {
+        let directions: [(f32, f32); 8] = core::array::from_fn(|i| {
+            let (sin, cos) = (i as f32 * 22.5).to_radians().sin_cos();
+            (cos, sin)
+        });
+        let mut spans = [(f32::MAX, f32::MIN); 8];
+        for p in &self.points {
+            for ((cos, sin), span) in directions.iter().zip(&mut spans) {
+                let along = p.x * cos + p.y * sin;
+                *span = (span.0.min(along), span.1.max(along));
+            }
+        }
+        spans
+            .iter()
+            .map(|(low, high)| high - low)
+            .fold(0.0, f32::max)
+    }
+}
+
+/// cut angle ordered points wherever neighbours jump more than the gap apart,
+/// returns index ranges into points
+pub fn breakpoints(points: &[ScanPoint], max_gap: f32, max_gap_ratio: f32) -> Vec<Range<usize>> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    for i in 1..=points.len() {
+        let gap = i == points.len() || {
+            let (a, b) = (&points[i - 1], &points[i]);
+            let range = a.distance.min(b.distance) as f32;
+            (a.x - b.x).hypot(a.y - b.y) > max_gap + range * max_gap_ratio
+        };
+        if gap {
+            runs.push(start..i);
+            start = i;
+        }
+    }
+    runs
+}
+
+/// cluster one sensor's scan by breakpoints
+pub fn breakpoint(scan: &Scan, config: &ClusterConfig) -> Vec<Cluster> {
+    let points: Vec<ScanPoint> = scan
+        .points
+        .iter()
+        .filter(|p| p.distance != 0)
+        .copied()
+        .collect();
+    let mut runs = breakpoints(&points, config.max_gap, config.max_gap_ratio);
+    // the scan starts and ends at 0 degrees, so an object sitting across it
+    // would otherwise be cut in two
+    if runs.len() > 1 {
+        let (first, last) = (&points[0], &points[points.len() - 1]);
+        let range = first.distance.min(last.distance) as f32;
+        if (first.x - last.x).hypot(first.y - last.y)
+            <= config.max_gap + range * config.max_gap_ratio
+        {
+            let last = runs.pop().unwrap();
+            let mut joined: Vec<ScanPoint> = points[last].to_vec();
+            joined.extend_from_slice(&points[runs[0].clone()]);
+            let mut clusters = vec![joined];
+            clusters.extend(runs[1..].iter().map(|run| points[run.clone()].to_vec()));
+            return finish(clusters, config);
+        }
+    }
+    finish(
+        runs.into_iter().map(|run| points[run].to_vec()).collect(),
+        config,
+    )
+}
+
+/// density based clustering, any order of points from any number of sensors
+pub fn dbscan(points: &[ScanPoint], config: &ClusterConfig) -> Vec<Cluster> {
+    let points: Vec<ScanPoint> = points.iter().filter(|p| p.distance != 0).copied().collect();
+    // bucket points into eps sized cells so neighbours only need to be
+    // looked for in the surrounding nine cells
+    let cell = |x: f32, y: f32| {
+        (
+            (x / config.eps).floor() as i32,
+            (y / config.eps).floor() as i32,
+        )
+    };
+    let mut grid: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+    for (i, p) in points.iter().enumerate() {
+        grid.entry(cell(p.x, p.y)).or_default().push(i);
+    }
+    let neighbours = |i: usize| -> Vec<usize> {
+        let p = &points[i];
+        let (cx, cy) = cell(p.x, p.y);
+        let mut found = Vec::new();
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for &j in grid.get(&(cx + dx, cy + dy)).into_iter().flatten() {
+                    let q = &points[j];
+                    if (p.x - q.x).hypot(p.y - q.y) <= config.eps {
+                        found.push(j);
+                    }
+                }
+            }
+        }
+        found
+    };
+
+    // None is unvisited, Some(None) is noise
+    let mut labels: Vec<Option<Option<usize>>> = vec![None; points.len()];
+    let mut clusters: Vec<Vec<ScanPoint>> = Vec::new();
+    for i in 0..points.len() {
+        if labels[i].is_some() {
+            continue;
+        }
+        let seeds = neighbours(i);
+        // neighbours includes the point itself
+        if seeds.len() < config.min_points {
+            labels[i] = Some(None);
+            continue;
+        }
+        let id = clusters.len();
+        let mut members = Vec::new();
+        let mut queue = seeds;
+        labels[i] = Some(Some(id));
+        members.push(points[i]);
+        while let Some(j) = queue.pop() {
+            match labels[j] {
+                // noise that turns out to be on the edge of a cluster
+                Some(None) => {
+                    labels[j] = Some(Some(id));
+                    members.push(points[j]);
+                }
+                None => {
+                    labels[j] = Some(Some(id));
+                    members.push(points[j]);
+                    let more = neighbours(j);
+                    if more.len() >= config.min_points {
+                        queue.extend(more);
+                    }
+                }
+                Some(Some(_)) => (),
+            }
+        }
+        clusters.push(members);
+    }
+    finish(clusters, config)
+}
+
+fn finish(clusters: Vec<Vec<ScanPoint>>, config: &ClusterConfig) -> Vec<Cluster> {
+    clusters
+        .into_iter()
+        .filter(|points| points.len() >= config.min_points)
+        .map(Cluster::new)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(angle: f32, distance: f32) -> ScanPoint {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        ScanPoint {
+            x: distance * cos,
+            y: distance * sin,
+            angle,
+            distance: distance as u16,
+            intensity: 100,
+            sensor: 0,
+        }
+    }
+
+    fn scan(points: Vec<ScanPoint>) -> Scan {
+        Scan {
+            sensor: 0,
+            revolution: 0,
+            timestamp: 0,
+            points,
+        }
+    }
+
+    // a point every degree from..to at one range
+    fn arc(from: i32, to: i32, distance: f32) -> impl Iterator<Item = ScanPoint> {
+        (from..to).map(move |a| at(a as f32, distance))
+    }
+
+    #[test]
+    fn breakpoints_cut_at_jumps() {
+        let points: Vec<ScanPoint> = arc(0, 10, 1000.0).chain(arc(10, 20, 2000.0)).collect();
+        assert_eq!(breakpoints(&points, 100.0, 0.03), vec![0..10, 10..20]);
+    }
+
+    #[test]
+    fn objects_across_zero_come_out_whole() {
+        let points = arc(0, 10, 1000.0)
+            .chain(arc(90, 100, 2000.0))
+            .chain(arc(350, 360, 1000.0))
+            .collect();
+        let clusters = breakpoint(&scan(points), &ClusterConfig::default());
+        assert_eq!(clusters.len(), 2);
+        assert_eq!(clusters[0].points.len(), 20);
+        // the part before 0 first, so the points stay in order
+        assert_eq!(clusters[0].points[0].angle, 350.0);
+        assert!(clusters[0].centroid.1.abs() < 20.0);
+        assert_eq!(clusters[1].points.len(), 10);
+    }
+
+    #[test]
+    fn objects_apart_at_zero_stay_apart() {
+        let points = arc(0, 10, 1000.0).chain(arc(350, 360, 3000.0)).collect();
+        let clusters = breakpoint(&scan(points), &ClusterConfig::default());
+        assert_eq!(clusters.len(), 2);
+    }
+
+    #[test]
+    fn small_clusters_and_empty_returns_are_dropped() {
+        let mut points: Vec<ScanPoint> = arc(0, 10, 1000.0).collect();
+        points.extend(arc(90, 92, 2000.0));
+        points.push(at(180.0, 0.0));
+        let clusters = breakpoint(&scan(points), &ClusterConfig::default());
+        assert_eq!(clusters.len(), 1);
+    }
+
+    #[test]
+    fn dbscan_finds_blobs_in_any_order() {
+        let config = ClusterConfig {
+            method: ClusterMethod::Dbscan,
+            ..ClusterConfig::default()
+        };
+        let mut points = Vec::new();
+        // two rows of points 50 mm apart, both straddling cell boundaries
+        for i in 0..10 {
+            let x = -250.0 + i as f32 * 50.0;
+            points.push(ScanPoint {
+                x,
+                y: 0.0,
+                ..at(0.0, 1000.0)
+            });
+            points.push(ScanPoint {
+                x,
+                y: 2000.0,
+                ..at(0.0, 1000.0)
+            });
+        }
+        // on its own
+        points.push(ScanPoint {
+            x: 5000.0,
+            y: 5000.0,
+            ..at(0.0, 1000.0)
+        });
+        points.reverse();
+        let mut clusters = dbscan(&points, &config);
+        assert_eq!(clusters.len(), 2);
+        clusters.sort_by(|a, b| a.centroid.1.total_cmp(&b.centroid.1));
+        for (cluster, y) in clusters.iter().zip([0.0, 2000.0]) {
+            assert_eq!(cluster.points.len(), 10);
+            assert!((cluster.centroid.0 + 25.0).abs() < 1e-3);
+            assert_eq!(cluster.centroid.1, y);
+        }
+    }
+
+    #[test]
+    fn dbscan_picks_up_edge_points() {
+        let config = ClusterConfig {
+            method: ClusterMethod::Dbscan,
+            ..ClusterConfig::default()
+        };
+        // a tight group and one point only a single member can reach
+        let mut points: Vec<ScanPoint> = (0..4)
+            .map(|i| ScanPoint {
+                x: i as f32 * 10.0,
+                y: 0.0,
+                ..at(0.0, 1000.0)
+            })
+            .collect();
+        points.insert(
+            0,
+            ScanPoint {
+                x: 140.0,
+                y: 0.0,
+                ..at(0.0, 1000.0)
+            },
+        );
+        let clusters = dbscan(&points, &config);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(clusters[0].points.len(), 5);
+    }
+
+    #[test]
+    fn extent_is_the_widest_span() {
+        // a 300 x 400 rectangle's corners, turned 30 degrees
+        let (sin, cos) = 30f32.to_radians().sin_cos();
+        let points = [(0.0, 0.0), (300.0, 0.0), (300.0, 400.0), (0.0, 400.0)]
+            .into_iter()
+            .map(|(x, y): (f32, f32)| ScanPoint {
+                x: x * cos - y * sin,
+                y: x * sin + y * cos,
+                ..at(0.0, 1000.0)
+            })
+            .collect();
+        let extent = Cluster::new(points).extent();
+        assert!((490.0..=500.001).contains(&extent), "{extent}");
+        assert_eq!(Cluster::new(Vec::new()).extent(), 0.0);
+    }
+}
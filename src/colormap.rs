@@ -0,0 +1,159 @@
+// color maps for the viewer. every map takes a value that has already been
+// normalized to 0..1 and clamps it, so nothing wraps around no matter what the
+// sensor hands us
+use core::fmt;
+
+// intensity the sensor reports for a "good" return. anything above this is
+// drawn at the top of the map
+pub const INTENSITY_MAX: f32 = 200.0;
+// the ld19 is rated to 12 m
+pub const RANGE_MAX_MM: f32 = 12_000.0;
+// points older than this are drawn at the bottom of the map
+pub const AGE_MAX_SECS: f32 = 2.0;
+// revolutions cycle through the map in bands of this many
+pub const REVOLUTION_CYCLE: u32 = 10;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColorMap {
+    // the original red (low) to green (high) ramp
+    #[default]
+    RedGreen,
+    Viridis,
+    Turbo,
+    Grayscale,
+}
+
+impl fmt::Display for ColorMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorMap::RedGreen => write!(f, "red-green"),
+            ColorMap::Viridis => write!(f, "viridis"),
+            ColorMap::Turbo => write!(f, "turbo"),
+            ColorMap::Grayscale => write!(f, "grayscale"),
+        }
+    }
+}
+
+// matplotlib's viridis, sampled at 9 evenly spaced stops
+const VIRIDIS: [(u8, u8, u8); 9] = [
+    (68, 1, 84),
+    (71, 44, 122),
+    (59, 81, 139),
+    (44, 113, 142),
+    (33, 144, 141),
+    (39, 173, 129),
+    (92, 200, 99),
+    (170, 220, 50),
+    (253, 231, 37),
+];
+
+impl ColorMap {
+    pub fn next(self) -> Self {
+        match self {
+            ColorMap::RedGreen => ColorMap::Viridis,
+            ColorMap::Viridis => ColorMap::Turbo,
+            ColorMap::Turbo => ColorMap::Grayscale,
+            ColorMap::Grayscale => ColorMap::RedGreen,
+        }
+    }
+
+    /// sample the map at t, where t is clamped to 0..1
+    pub fn sample(&self, t: f32) -> (u8, u8, u8) {
+        // NaN shows up as the bottom of the map rather than poisoning the math
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+        match self {
+            ColorMap::RedGreen => {
+                let green = (255.0 * t) as u8;
+                (255 - green, green, 0x00)
+            }
+            ColorMap::Viridis => {
+                let scaled = t * (VIRIDIS.len() - 1) as f32;
+                let i = (scaled as usize).min(VIRIDIS.len() - 2);
+                let frac = scaled - i as f32;
+                let (a, b) = (VIRIDIS[i], VIRIDIS[i + 1]);
+                (
+                    lerp(a.0, b.0, frac),
+                    lerp(a.1, b.1, frac),
+                    lerp(a.2, b.2, frac),
+                )
+            }
+            ColorMap::Turbo => {
+                // polynomial approximation of google's turbo map
+                let r = 0.135_721_38
+                    + t * (4.615_392_6
+                        + t * (-42.660_324 + t * (132.131_08 + t * (-152.942_4 + t * 59.286_38))));
+                let g = 0.091_402_61
+                    + t * (2.194_188_4
+                        + t * (4.842_966_6
+                            + t * (-14.185_033 + t * (4.277_298_6 + t * 2.829_566))));
+                let b = 0.106_673_3
+                    + t * (12.641_946
+                        + t * (-60.582_05 + t * (110.362_77 + t * (-89.903_11 + t * 27.348_25))));
+                (unit_to_u8(r), unit_to_u8(g), unit_to_u8(b))
+            }
+            ColorMap::Grayscale => {
+                let v = unit_to_u8(t);
+                (v, v, v)
+            }
+        }
+    }
+}
+
+fn lerp(a: u8, b: u8, t: f32) -> u8 {
+    (a as f32 + (b as f32 - a as f32) * t).round() as u8
+}
+
+fn unit_to_u8(v: f32) -> u8 {
+    (v.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// which property of a point gets fed through the color map
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ColorBy {
+    #[default]
+    Intensity,
+    Range,
+    Age,
+    Revolution,
+    // use the color the point was sent with
+    Source,
+}
+
+impl fmt::Display for ColorBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorBy::Intensity => write!(f, "intensity"),
+            ColorBy::Range => write!(f, "range"),
+            ColorBy::Age => write!(f, "age"),
+            ColorBy::Revolution => write!(f, "revolution"),
+            ColorBy::Source => write!(f, "source"),
+        }
+    }
+}
+
+impl ColorBy {
+    pub fn next(self) -> Self {
+        match self {
+            ColorBy::Intensity => ColorBy::Range,
+            ColorBy::Range => ColorBy::Age,
+            ColorBy::Age => ColorBy::Revolution,
+            ColorBy::Revolution => ColorBy::Source,
+            ColorBy::Source => ColorBy::Intensity,
+        }
+    }
+
+    /// the labels for the bottom and top of the legend, if the mode has one
+    pub fn legend_labels(&self) -> Option<(String, String)> {
+        match self {
+            ColorBy::Intensity => Some((String::from("0"), format!("{INTENSITY_MAX}+"))),
+            ColorBy::Range => Some((String::from("0 m"), format!("{} m", RANGE_MAX_MM / 1000.0))),
+            // age is drawn newest at the top
+            ColorBy::Age => Some((format!("{AGE_MAX_SECS} s"), String::from("now"))),
+            ColorBy::Revolution => Some((
+                String::from("rev 0"),
+                format!("rev {}", REVOLUTION_CYCLE - 1),
+            )),
+            ColorBy::Source => None,
+        }
+    }
+}
@@ -0,0 +1,151 @@
+// comparing two captures, or a capture against live data, in the viewer
+use crate::window::DrawPoint;
+use core::fmt;
+use lidar::ld19::capture;
+use lidar::ld19::decoder::ParseError;
+use lidar::ld19::point::{parse, Point};
+use lidar::profile::{ProfileDiff, RangeProfile};
+use std::path::Path;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CompareLayout {
+    // both on top of each other, with the differences drawn between them
+    #[default]
+    Overlay,
+    SideBySide,
+}
+
+impl fmt::Display for CompareLayout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompareLayout::Overlay => write!(f, "overlay"),
+            CompareLayout::SideBySide => write!(f, "side by side"),
+        }
+    }
+}
+
+impl CompareLayout {
+    pub fn next(self) -> Self {
+        match self {
+            CompareLayout::Overlay => CompareLayout::SideBySide,
+            CompareLayout::SideBySide => CompareLayout::Overlay,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Comparison {
+    pub layout: CompareLayout,
+    pub a_name: String,
+    pub a: RangeProfile,
+    pub b_name: String,
+    // empty until the first live revolution is in
+    pub b: Option<RangeProfile>,
+    pub diff: Option<ProfileDiff>,
+    // which sensor live data comes from. profiles are in the sensor frame, so
+    // it's only ever one
+    pub sensor: u8,
+    // live revolution being collected
+    current: Vec<Point>,
+    current_revolution: Option<u32>,
+}
+
+fn load(path: &Path) -> Result<RangeProfile, ParseError> {
+    let packets = capture::read(path)?;
+    let points: Vec<Point> = packets.iter().flat_map(parse).collect();
+    Ok(RangeProfile::from_points(&points))
+}
+
+impl Comparison {
+    pub fn from_captures(a: &Path, b: &Path) -> Result<Self, ParseError> {
+        let mut comparison = Self::new(a.display().to_string(), load(a)?);
+        comparison.b_name = b.display().to_string();
+        comparison.b = Some(load(b)?);
+        comparison.update_diff();
+        Ok(comparison)
+    }
+
+    pub fn against_live(a: &Path) -> Result<Self, ParseError> {
+        Ok(Self::new(a.display().to_string(), load(a)?))
+    }
+
+    fn new(a_name: String, a: RangeProfile) -> Self {
+        Self {
+            layout: CompareLayout::default(),
+            a_name,
+            a,
+            b_name: String::from("live"),
+            b: None,
+            diff: None,
+            sensor: 0,
+            current: Vec::new(),
+            current_revolution: None,
+        }
+    }
+
+    fn update_diff(&mut self) {
+        self.diff = self.b.as_ref().map(|b| self.a.diff(b));
+    }
+
+    /// feed live points in. b is replaced every time a revolution finishes
+    pub fn push_live(&mut self, points: &[DrawPoint]) {
+        let sensor = self.sensor;
+        for p in points.iter().filter(|p| p.sensor == sensor) {
+            if self.current_revolution != Some(p.revolution) {
+                if self.current_revolution.is_some() && !self.current.is_empty() {
+                    self.b = Some(RangeProfile::from_points(&self.current));
+                    self.update_diff();
+                }
+                self.current.clear();
+                self.current_revolution = Some(p.revolution);
+            }
+            self.current.push(Point {
+                angle: p.angle,
+                distance: p.distance as u32,
+                confidence: p.intensity,
+            });
+        }
+    }
+
+    pub fn summary(&self) -> String {
+        match self
+            .diff
+            .as_ref()
+            .and_then(|d| d.rms.map(|rms| (rms, d.compared)))
+        {
+            Some((rms, compared)) => format!(
+                "a: {} b: {} | rms {:.0} mm over {} bearings",
+                self.a_name, self.b_name, rms, compared
+            ),
+            None => format!(
+                "a: {} b: {} | nothing to compare yet",
+                self.a_name, self.b_name
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.summary())?;
+        let Some(diff) = self.diff.as_ref() else {
+            return Ok(());
+        };
+        writeln!(f, "bearing      a (mm)      b (mm)   b - a (mm)")?;
+        let b = self.b.as_ref();
+        for (bin, d) in diff.per_angle.iter().enumerate() {
+            let Some(d) = d else {
+                continue;
+            };
+            writeln!(
+                f,
+                "{:>7.1} {:>11.0} {:>11.0} {:>12.0}",
+                RangeProfile::bearing(bin),
+                self.a.ranges[bin].unwrap_or_default(),
+                b.and_then(|b| b.ranges[bin]).unwrap_or_default(),
+                d
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,432 @@
+// config file, toml. everything in it is optional
+use core::fmt;
+use lidar::background::BackgroundConfig;
+use lidar::extrinsics::Extrinsics;
+use lidar::filter::CrosstalkConfig;
+use lidar::geometry::Polygon;
+use lidar::grid::GridConfig;
+use lidar::icp::{IcpConfig, IcpMethod, Kernel};
+use lidar::safety::{FieldLevel, FieldShape, SafetyConfig, SafetyField};
+use lidar::slam::SlamConfig;
+use lidar::temporal::TemporalConfig;
+use lidar::zone::Zone;
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Toml(toml::de::Error),
+    // parsed fine but doesn't make sense
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "{}", e),
+            ConfigError::Toml(e) => write!(f, "{}", e),
+            ConfigError::Invalid(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> ConfigError {
+        ConfigError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(e: toml::de::Error) -> ConfigError {
+        ConfigError::Toml(e)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // one [sensor] or as many [[sensor]] as there are sensors
+    #[serde(rename = "sensor", deserialize_with = "one_or_many")]
+    pub sensors: Vec<Sensor>,
+    pub footprint: Option<Footprint>,
+    // interference filtering between sensors, off unless there's a
+    // [crosstalk] table
+    pub crosstalk: Option<Crosstalk>,
+    // median filtering across revolutions, toggled in the viewer
+    pub temporal: Temporal,
+    // background subtraction, toggled in the viewer
+    pub background: Background,
+    // intrusion zones, [[zone]] in the file
+    #[serde(rename = "zone")]
+    pub zones: Vec<NamedPolygon>,
+    // protective fields, [safety] and [[safety.field]] in the file
+    pub safety: Safety,
+    // occupancy grid mapping, toggled in the viewer
+    pub grid: Grid,
+    // scan matching for odometry, toggled in the viewer
+    pub icp: Icp,
+    // mapping with loop closure, toggled in the viewer. the map covers the
+    // area [grid] says
+    pub slam: Slam,
+    // any other static geometry worth drawing, [[polygon]] in the file
+    #[serde(rename = "polygon")]
+    pub polygons: Vec<NamedPolygon>,
+}
+
+// a sensor and how it's mounted on the robot
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sensor {
+    pub name: Option<String>,
+    // falls back to --port
+    pub port: Option<String>,
+    // used when coloring by source
+    pub color: Option<(u8, u8, u8)>,
+    // millimeters
+    pub x: f32,
+    pub y: f32,
+    // degrees
+    pub yaw: f32,
+    pub upside_down: bool,
+}
+
+impl Sensor {
+    pub fn extrinsics(&self) -> Extrinsics {
+        Extrinsics {
+            x: self.x,
+            y: self.y,
+            yaw: self.yaw,
+            upside_down: self.upside_down,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Footprint {
+    // millimeters, in the robot frame
+    pub points: Vec<(f32, f32)>,
+    // drop any points that land inside the footprint
+    #[serde(default)]
+    pub filter: bool,
+}
+
+// anything left out uses the filter's default
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Crosstalk {
+    pub min_intensity: Option<u8>,
+    // millimeters
+    pub max_jump: Option<f32>,
+    pub max_jump_ratio: Option<f32>,
+    // degrees
+    pub cone: Option<f32>,
+}
+
+impl Crosstalk {
+    pub fn config(&self) -> CrosstalkConfig {
+        let default = CrosstalkConfig::default();
+        CrosstalkConfig {
+            min_intensity: self.min_intensity.unwrap_or(default.min_intensity),
+            max_jump: self.max_jump.unwrap_or(default.max_jump),
+            max_jump_ratio: self.max_jump_ratio.unwrap_or(default.max_jump_ratio),
+            cone: self.cone.unwrap_or(default.cone),
+        }
+    }
+}
+
+// anything left out uses the filter's default
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Temporal {
+    // revolutions
+    pub window: Option<usize>,
+    // degrees
+    pub resolution: Option<f32>,
+    // millimeters
+    pub outlier_jump: Option<f32>,
+    pub outlier_ratio: Option<f32>,
+}
+
+impl Temporal {
+    pub fn config(&self) -> TemporalConfig {
+        let default = TemporalConfig::default();
+        TemporalConfig {
+            window: self.window.unwrap_or(default.window),
+            resolution: self.resolution.unwrap_or(default.resolution),
+            outlier_jump: self.outlier_jump.unwrap_or(default.outlier_jump),
+            outlier_ratio: self.outlier_ratio.unwrap_or(default.outlier_ratio),
+        }
+    }
+}
+
+// anything left out uses the model's default
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Background {
+    pub revolutions: Option<usize>,
+    // degrees
+    pub resolution: Option<f32>,
+    // millimeters
+    pub threshold: Option<f32>,
+    pub threshold_ratio: Option<f32>,
+}
+
+impl Background {
+    pub fn config(&self) -> BackgroundConfig {
+        let default = BackgroundConfig::default();
+        BackgroundConfig {
+            revolutions: self.revolutions.unwrap_or(default.revolutions),
+            resolution: self.resolution.unwrap_or(default.resolution),
+            threshold: self.threshold.unwrap_or(default.threshold),
+            threshold_ratio: self.threshold_ratio.unwrap_or(default.threshold_ratio),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Grid {
+    // millimeters per cell
+    pub resolution: Option<f32>,
+    // corners of the mapped area, [x, y] in millimeters
+    pub min: Option<[f32; 2]>,
+    pub max: Option<[f32; 2]>,
+    pub max_range: Option<f32>,
+}
+
+impl Grid {
+    pub fn config(&self) -> GridConfig {
+        let default = GridConfig::default();
+        GridConfig {
+            resolution: self.resolution.unwrap_or(default.resolution),
+            min: self.min.map_or(default.min, |[x, y]| (x, y)),
+            max: self.max.map_or(default.max, |[x, y]| (x, y)),
+            max_range: self.max_range.unwrap_or(default.max_range),
+            ..default
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Icp {
+    pub method: Option<Method>,
+    pub kernel: Option<KernelName>,
+    // millimeters, where the kernel starts to discount residuals
+    pub kernel_width: Option<f32>,
+    pub max_iterations: Option<usize>,
+    // millimeters
+    pub max_correspondence: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Method {
+    PointToPoint,
+    PointToLine,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KernelName {
+    None,
+    Huber,
+    Cauchy,
+}
+
+impl Icp {
+    pub fn config(&self) -> IcpConfig {
+        let default = IcpConfig::default();
+        let width = self.kernel_width.unwrap_or(match default.kernel {
+            Kernel::Huber(k) | Kernel::Cauchy(k) => k,
+            Kernel::None => 50.0,
+        });
+        IcpConfig {
+            method: match self.method {
+                Some(Method::PointToPoint) => IcpMethod::PointToPoint,
+                Some(Method::PointToLine) => IcpMethod::PointToLine,
+                None => default.method,
+            },
+            kernel: match self.kernel {
+                Some(KernelName::None) => Kernel::None,
+                Some(KernelName::Huber) => Kernel::Huber(width),
+                Some(KernelName::Cauchy) => Kernel::Cauchy(width),
+                None => match default.kernel {
+                    Kernel::Huber(_) => Kernel::Huber(width),
+                    Kernel::Cauchy(_) => Kernel::Cauchy(width),
+                    Kernel::None => Kernel::None,
+                },
+            },
+            max_iterations: self.max_iterations.unwrap_or(default.max_iterations),
+            max_correspondence: self
+                .max_correspondence
+                .unwrap_or(default.max_correspondence),
+            ..default
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Slam {
+    // millimeters per cell of what scans are matched against
+    pub match_resolution: Option<f32>,
+    // millimeters and degrees moved before a new keyframe
+    pub keyframe_distance: Option<f32>,
+    pub keyframe_angle: Option<f32>,
+    // millimeters to look around a keyframe for places seen before, and how
+    // well one has to match, 0..1
+    pub loop_distance: Option<f32>,
+    pub loop_score: Option<f32>,
+}
+
+impl Slam {
+    pub fn config(&self, grid: GridConfig) -> SlamConfig {
+        let default = SlamConfig::default();
+        SlamConfig {
+            grid,
+            match_resolution: self.match_resolution.unwrap_or(default.match_resolution),
+            keyframe_distance: self.keyframe_distance.unwrap_or(default.keyframe_distance),
+            keyframe_angle: self.keyframe_angle.unwrap_or(default.keyframe_angle),
+            loop_distance: self.loop_distance.unwrap_or(default.loop_distance),
+            loop_score: self.loop_score.unwrap_or(default.loop_score),
+            ..default
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Safety {
+    pub min_points: Option<usize>,
+    pub release_ms: Option<u64>,
+    // a sensor quiet for longer than this is a stop
+    pub timeout_ms: Option<u64>,
+    #[serde(rename = "field")]
+    pub fields: Vec<Field>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Warning,
+    Slow,
+    Stop,
+}
+
+// either points or ranges, not both
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Field {
+    pub level: Level,
+    // millimeters, in the robot frame
+    pub points: Option<Vec<(f32, f32)>>,
+    // [from, to, range], degrees from the robot's forward and millimeters
+    pub ranges: Option<Vec<(f32, f32, f32)>>,
+}
+
+impl Safety {
+    pub fn config(&self) -> SafetyConfig {
+        let default = SafetyConfig::default();
+        SafetyConfig {
+            min_points: self.min_points.unwrap_or(default.min_points),
+            release: self
+                .release_ms
+                .map_or(default.release, Duration::from_millis),
+            timeout: self
+                .timeout_ms
+                .map_or(default.timeout, Duration::from_millis),
+        }
+    }
+
+    pub fn fields(&self) -> Vec<SafetyField> {
+        self.fields
+            .iter()
+            .filter_map(|field| {
+                let level = match field.level {
+                    Level::Warning => FieldLevel::Warning,
+                    Level::Slow => FieldLevel::Slow,
+                    Level::Stop => FieldLevel::Stop,
+                };
+                // check makes sure there's exactly one of them
+                let shape = match (&field.points, &field.ranges) {
+                    (Some(points), None) => FieldShape::Polygon(Polygon::new(points.clone())),
+                    (None, Some(ranges)) => FieldShape::Ranges(ranges.clone()),
+                    _ => return None,
+                };
+                Some(SafetyField { level, shape })
+            })
+            .collect()
+    }
+
+    fn check(&self) -> Result<(), ConfigError> {
+        for (i, field) in self.fields.iter().enumerate() {
+            let problem = match (&field.points, &field.ranges) {
+                (Some(_), None) | (None, Some(_)) => continue,
+                (None, None) => "needs points or ranges",
+                (Some(_), Some(_)) => "has both points and ranges, make it two fields",
+            };
+            return Err(ConfigError::Invalid(format!(
+                "[[safety.field]] number {} {problem}",
+                i + 1
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NamedPolygon {
+    pub name: String,
+    // millimeters, in the robot frame
+    pub points: Vec<(f32, f32)>,
+    pub color: Option<(u8, u8, u8)>,
+}
+
+impl Footprint {
+    pub fn polygon(&self) -> Polygon {
+        Polygon::new(self.points.clone())
+    }
+}
+
+impl NamedPolygon {
+    pub fn polygon(&self) -> Polygon {
+        Polygon::new(self.points.clone())
+    }
+
+    pub fn zone(&self) -> Zone {
+        Zone {
+            name: self.name.clone(),
+            polygon: self.polygon(),
+        }
+    }
+}
+
+fn one_or_many<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Vec<Sensor>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(Sensor),
+        Many(Vec<Sensor>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(sensor) => vec![sensor],
+        OneOrMany::Many(sensors) => sensors,
+    })
+}
+
+impl Config {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let config: Config = toml::from_str(&fs::read_to_string(path)?)?;
+        config.safety.check()?;
+        Ok(config)
+    }
+}
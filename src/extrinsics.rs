@@ -0,0 +1,67 @@
+// where a sensor sits on the robot. points come off the sensor in its own
+// frame, this moves them into the robot's frame: x forward, y to the side,
+// origin wherever the robot's origin is
+use crate::ld19::point::{polar_to_cartesian, Point};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Extrinsics {
+    // position of the sensor in the robot frame, millimeters
+    pub x: f32,
+    pub y: f32,
+    // rotation of the sensor's 0 degree mark from the robot's forward, degrees
+    pub yaw: f32,
+    // mounted upside down, which makes it spin the other way
+    pub upside_down: bool,
+}
+
+impl Extrinsics {
+    /// move a point in the sensor frame into the robot frame
+    pub fn apply(&self, x: f32, y: f32) -> (f32, f32) {
+        let y = if self.upside_down { -y } else { y };
+        let (sin, cos) = self.yaw.to_radians().sin_cos();
+        (self.x + x * cos - y * sin, self.y + x * sin + y * cos)
+    }
+
+    /// move a point in the robot frame into the sensor frame
+    pub fn to_sensor(&self, x: f32, y: f32) -> (f32, f32) {
+        let (sin, cos) = self.yaw.to_radians().sin_cos();
+        let (dx, dy) = (x - self.x, y - self.y);
+        let (x, y) = (dx * cos + dy * sin, -dx * sin + dy * cos);
+        (x, if self.upside_down { -y } else { y })
+    }
+
+    /// a sensor reading in robot frame cartesian coordinates
+    pub fn to_robot(&self, point: &Point) -> (f32, f32) {
+        let (x, y) = polar_to_cartesian(point.distance, point.angle);
+        self.apply(x, y)
+    }
+
+    /// the sensor's bearing in the robot frame for a raw sensor angle, degrees
+    pub fn bearing(&self, angle: f32) -> f32 {
+        let angle = if self.upside_down { -angle } else { angle };
+        (angle + self.yaw).rem_euclid(360.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_sensor_undoes_apply() {
+        for upside_down in [false, true] {
+            let extrinsics = Extrinsics {
+                x: 120.0,
+                y: -40.0,
+                yaw: 30.0,
+                upside_down,
+            };
+            let (rx, ry) = extrinsics.apply(500.0, 250.0);
+            let (x, y) = extrinsics.to_sensor(rx, ry);
+            assert!(
+                (x - 500.0).abs() < 1e-3 && (y - 250.0).abs() < 1e-3,
+                "{x}, {y}"
+            );
+        }
+    }
+}
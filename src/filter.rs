@@ -0,0 +1,266 @@
+// filters that throw away points we don't care about
+use crate::extrinsics::Extrinsics;
+use crate::geometry::Polygon;
+use crate::scan::ScanPoint;
+use core::fmt;
+
+/// drops every point that lands inside the robot's own footprint, e.g.
+/// returns off the chassis or a mast
+#[derive(Debug, Clone)]
+pub struct FootprintFilter {
+    // robot frame
+    pub footprint: Polygon,
+}
+
+impl FootprintFilter {
+    pub fn new(footprint: Polygon) -> Self {
+        Self { footprint }
+    }
+
+    /// remove points inside the footprint, returns how many were removed
+    pub fn apply(&self, points: &mut Vec<ScanPoint>) -> usize {
+        let before = points.len();
+        points.retain(|p| !self.footprint.contains(p.x, p.y));
+        before - points.len()
+    }
+}
+
+// one bin per degree, same as the range profiles
+const CROSSTALK_BINS: usize = 360;
+
+#[derive(Debug, Clone, Copy)]
+pub struct CrosstalkConfig {
+    // returns dimmer than this are suspicious, interference tends to come
+    // back weak
+    pub min_intensity: u8,
+    // how far a return can be from anything seen at that bearing last
+    // revolution before it's suspicious, millimeters plus a fraction of the
+    // range
+    pub max_jump: f32,
+    pub max_jump_ratio: f32,
+    // half width of the cone around the bearing to another sensor, degrees
+    pub cone: f32,
+}
+
+impl Default for CrosstalkConfig {
+    fn default() -> Self {
+        Self {
+            min_intensity: 60,
+            max_jump: 150.0,
+            max_jump_ratio: 0.05,
+            cone: 5.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CrosstalkStats {
+    pub checked: u64,
+    pub removed: u64,
+    // how often each check flagged a point, removed or not
+    pub low_intensity: u64,
+    pub inconsistent: u64,
+    pub facing: u64,
+}
+
+impl CrosstalkStats {
+    /// percentage of checked points that were removed
+    pub fn removed_percent(&self) -> f32 {
+        if self.checked == 0 {
+            0.0
+        } else {
+            self.removed as f32 / self.checked as f32 * 100.0
+        }
+    }
+}
+
+impl fmt::Display for CrosstalkStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "removed {} of {} ({:.1}%), flagged {} dim, {} inconsistent, {} facing a sensor",
+            self.removed,
+            self.checked,
+            self.removed_percent(),
+            self.low_intensity,
+            self.inconsistent,
+            self.facing
+        )
+    }
+}
+
+/// drops returns that look like interference from another ld19. each point
+/// gets three checks: is it dim, was there nothing near it at that bearing
+/// last revolution, and is it in the direction of another sensor. any two of
+/// those and it goes, one alone is too common in real scenes
+#[derive(Debug, Clone)]
+pub struct CrosstalkFilter {
+    pub config: CrosstalkConfig,
+    sensor: Extrinsics,
+    // bearings to every other sensor, degrees in the robot frame
+    others: Vec<f32>,
+    // every range per bin, last revolution and the one in progress
+    previous: Vec<Vec<f32>>,
+    current: Vec<Vec<f32>>,
+    revolution: Option<u32>,
+    pub stats: CrosstalkStats,
+}
+
+impl CrosstalkFilter {
+    /// others is the mounting of every other sensor on the robot, leave it
+    /// empty if they aren't on the same robot
+    pub fn new(config: CrosstalkConfig, sensor: Extrinsics, others: &[Extrinsics]) -> Self {
+        let others = others
+            .iter()
+            .filter(|o| **o != sensor)
+            .map(|o| (o.y - sensor.y).atan2(o.x - sensor.x).to_degrees())
+            .collect();
+        Self {
+            config,
+            sensor,
+            others,
+            previous: vec![Vec::new(); CROSSTALK_BINS],
+            current: vec![Vec::new(); CROSSTALK_BINS],
+            revolution: None,
+            stats: CrosstalkStats::default(),
+        }
+    }
+
+    fn is_low_intensity(&self, point: &ScanPoint) -> bool {
+        point.intensity < self.config.min_intensity
+    }
+
+    fn is_inconsistent(&self, bin: usize, point: &ScanPoint) -> bool {
+        // nothing to compare against on the first revolution
+        if self.previous.iter().all(Vec::is_empty) {
+            return false;
+        }
+        let range = point.distance as f32;
+        let tolerance = self.config.max_jump + range * self.config.max_jump_ratio;
+        // neighbouring bins too, start angles drift a little every revolution
+        ![CROSSTALK_BINS - 1, 0, 1].iter().any(|offset| {
+            self.previous[(bin + offset) % CROSSTALK_BINS]
+                .iter()
+                .any(|previous| (previous - range).abs() <= tolerance)
+        })
+    }
+
+    fn is_facing(&self, point: &ScanPoint) -> bool {
+        let bearing = (point.y - self.sensor.y)
+            .atan2(point.x - self.sensor.x)
+            .to_degrees();
+        self.others.iter().any(|other| {
+            let diff = (bearing - other).rem_euclid(360.0);
+            diff.min(360.0 - diff) <= self.config.cone
+        })
+    }
+
+    /// remove interference from a packet's points, returns how many were
+    /// removed. revolution is the one the points belong to
+    pub fn apply(&mut self, revolution: u32, points: &mut Vec<ScanPoint>) -> usize {
+        if self.revolution != Some(revolution) {
+            std::mem::swap(&mut self.previous, &mut self.current);
+            self.current.iter_mut().for_each(Vec::clear);
+            self.revolution = Some(revolution);
+        }
+        let before = points.len();
+        points.retain(|p| {
+            // no return at all isn't interference
+            if p.distance == 0 {
+                return true;
+            }
+            let bin = (p.angle.rem_euclid(360.0) as usize).min(CROSSTALK_BINS - 1);
+            let flags = [
+                self.is_low_intensity(p),
+                self.is_inconsistent(bin, p),
+                self.is_facing(p),
+            ];
+            // everything goes into the history, filtered or not, so a real
+            // object shows up as consistent on the next revolution
+            self.current[bin].push(p.distance as f32);
+
+            self.stats.checked += 1;
+            self.stats.low_intensity += flags[0] as u64;
+            self.stats.inconsistent += flags[1] as u64;
+            self.stats.facing += flags[2] as u64;
+            let interference = flags.iter().filter(|f| **f).count() >= 2;
+            self.stats.removed += interference as u64;
+            !interference
+        });
+        before - points.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(angle: f32, distance: u16, intensity: u8) -> ScanPoint {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        ScanPoint {
+            x: distance as f32 * cos,
+            y: distance as f32 * sin,
+            angle,
+            distance,
+            intensity,
+            sensor: 0,
+        }
+    }
+
+    // this sensor at the origin and another a meter straight ahead of it
+    fn filter() -> CrosstalkFilter {
+        let other = Extrinsics {
+            x: 1000.0,
+            ..Extrinsics::default()
+        };
+        let mut filter =
+            CrosstalkFilter::new(CrosstalkConfig::default(), Extrinsics::default(), &[other]);
+        // a bright wall 2 m away all round to compare against
+        let mut wall: Vec<ScanPoint> = (0..360).map(|a| point(a as f32 + 0.5, 2000, 200)).collect();
+        filter.apply(0, &mut wall);
+        filter
+    }
+
+    #[test]
+    fn takes_two_of_three_checks_to_remove_a_point() {
+        let mut filter = filter();
+        let mut points = vec![
+            // dim and facing the other sensor
+            point(2.5, 2000, 10),
+            // inconsistent and facing
+            point(3.5, 500, 200),
+            // dim and inconsistent
+            point(180.5, 500, 10),
+            // only one each
+            point(90.5, 2000, 10),
+            point(1.5, 2000, 200),
+            point(270.5, 500, 200),
+            // no return at all is left alone
+            point(45.5, 0, 0),
+        ];
+        assert_eq!(filter.apply(1, &mut points), 3);
+        let kept: Vec<f32> = points.iter().map(|p| p.angle).collect();
+        assert_eq!(kept, vec![90.5, 1.5, 270.5, 45.5]);
+
+        let stats = filter.stats;
+        assert_eq!((stats.checked, stats.removed), (366, 3));
+        // the wall's ten points inside the cone were facing it as well
+        assert_eq!(
+            (stats.low_intensity, stats.inconsistent, stats.facing),
+            (3, 3, 3 + 10)
+        );
+    }
+
+    #[test]
+    fn nothing_is_inconsistent_on_the_first_revolution() {
+        let mut filter =
+            CrosstalkFilter::new(CrosstalkConfig::default(), Extrinsics::default(), &[]);
+        let mut points = vec![point(10.5, 500, 10), point(20.5, 3000, 200)];
+        assert_eq!(filter.apply(0, &mut points), 0);
+        assert_eq!(filter.stats.inconsistent, 0);
+        // and a sensor on its own never faces anything
+        let mut points = vec![point(10.5, 4000, 200)];
+        assert_eq!(filter.apply(1, &mut points), 0);
+        assert_eq!(filter.stats.inconsistent, 1);
+    }
+}
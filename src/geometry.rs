@@ -0,0 +1,82 @@
+// 2d shapes in millimeters
+
+#[derive(Debug, Clone, Default)]
+pub struct Polygon {
+    // vertices in order, the last one connects back to the first
+    pub points: Vec<(f32, f32)>,
+}
+
+impl Polygon {
+    pub fn new(points: Vec<(f32, f32)>) -> Self {
+        Self { points }
+    }
+
+    /// even-odd test, so points exactly on an edge can go either way
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        let mut inside = false;
+        let n = self.points.len();
+        for i in 0..n {
+            let (xi, yi) = self.points[i];
+            let (xj, yj) = self.points[(i + n - 1) % n];
+            if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+
+    /// each edge as a pair of vertices
+    pub fn edges(&self) -> impl Iterator<Item = ((f32, f32), (f32, f32))> + '_ {
+        let n = self.points.len();
+        (0..n).map(move |i| (self.points[i], self.points[(i + 1) % n]))
+    }
+}
+
+/// where the robot is in a fixed world frame
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Pose {
+    // millimeters
+    pub x: f32,
+    pub y: f32,
+    // counterclockwise from the world's x axis, degrees
+    pub heading: f32,
+}
+
+impl Pose {
+    pub fn new(x: f32, y: f32, heading: f32) -> Self {
+        Self { x, y, heading }
+    }
+
+    /// move a point in the robot frame into the world frame
+    pub fn apply(&self, x: f32, y: f32) -> (f32, f32) {
+        let (sin, cos) = self.heading.to_radians().sin_cos();
+        (self.x + x * cos - y * sin, self.y + x * sin + y * cos)
+    }
+
+    /// other is relative to this pose, e.g. how far the robot moved since
+    /// it was here. returns where that puts it in the world frame
+    pub fn compose(&self, other: &Pose) -> Pose {
+        let (x, y) = self.apply(other.x, other.y);
+        Pose::new(x, y, normalize_degrees(self.heading + other.heading))
+    }
+
+    /// the world frame as seen from this pose
+    pub fn inverse(&self) -> Pose {
+        let (sin, cos) = self.heading.to_radians().sin_cos();
+        Pose::new(
+            -self.x * cos - self.y * sin,
+            self.x * sin - self.y * cos,
+            normalize_degrees(-self.heading),
+        )
+    }
+}
+
+/// wrap an angle in degrees into -180..180
+pub fn normalize_degrees(degrees: f32) -> f32 {
+    let wrapped = degrees.rem_euclid(360.0);
+    if wrapped > 180.0 {
+        wrapped - 360.0
+    } else {
+        wrapped
+    }
+}
@@ -0,0 +1,393 @@
+// occupancy grid mapping. every scan is ray traced into a grid of log odds:
+// cells a beam passed through get more likely to be free and the cell it
+// ended in gets more likely to be occupied. the grid sits in a fixed world
+// frame, so each scan either comes with the robot's pose in that frame or is
+// taken to be from the origin
+use crate::extrinsics::Extrinsics;
+use crate::geometry::Pose;
+use crate::scan::{Scan, ScanPoint};
+
+// the thresholds ros map_server uses by default, probability of occupancy
+pub const FREE_THRESHOLD: f32 = 0.196;
+pub const OCCUPIED_THRESHOLD: f32 = 0.65;
+
+#[derive(Debug, Clone, Copy)]
+pub struct GridConfig {
+    // cell size, millimeters
+    pub resolution: f32,
+    // corners of the area the grid covers, world frame millimeters
+    pub min: (f32, f32),
+    pub max: (f32, f32),
+    // log odds added to a cell for a beam ending in it and for one passing
+    // through, and how far either way a cell can go so it can still change
+    // its mind when something moves
+    pub hit: f32,
+    pub miss: f32,
+    pub limit: f32,
+    // returns farther than this only clear space up to it, the ld19 is rated
+    // for 12m
+    pub max_range: f32,
+}
+
+impl Default for GridConfig {
+    fn default() -> Self {
+        Self {
+            resolution: 50.0,
+            min: (-10000.0, -10000.0),
+            max: (10000.0, 10000.0),
+            hit: 0.85,
+            miss: -0.4,
+            limit: 5.0,
+            max_range: 12000.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cell {
+    Unknown,
+    Free,
+    Occupied,
+}
+
+#[derive(Debug, Clone)]
+pub struct OccupancyGrid {
+    pub config: GridConfig,
+    width: usize,
+    height: usize,
+    // log odds per cell, row major with row 0 along min.1
+    cells: Vec<f32>,
+    // the scan that last touched each cell, so no cell is updated twice for
+    // the same scan
+    stamps: Vec<u32>,
+    scans: u32,
+}
+
+impl OccupancyGrid {
+    pub fn new(config: GridConfig) -> Self {
+        let resolution = config.resolution.max(1.0);
+        let width = ((config.max.0 - config.min.0) / resolution).ceil().max(1.0) as usize;
+        let height = ((config.max.1 - config.min.1) / resolution).ceil().max(1.0) as usize;
+        Self {
+            config: GridConfig {
+                resolution,
+                ..config
+            },
+            width,
+            height,
+            cells: vec![0.0; width * height],
+            stamps: vec![0; width * height],
+            scans: 0,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// scans inserted since the grid was made or cleared
+    pub fn scans(&self) -> u32 {
+        self.scans
+    }
+
+    /// forget everything
+    pub fn clear(&mut self) {
+        *self = Self::new(self.config);
+    }
+
+    /// (column, row) of the cell a world frame point falls in
+    pub fn cell_of(&self, x: f32, y: f32) -> Option<(usize, usize)> {
+        let (col, row) = self.cell_unchecked(x, y);
+        self.in_bounds(col, row)
+            .then_some((col as usize, row as usize))
+    }
+
+    /// world frame center of a cell
+    pub fn center_of(&self, col: usize, row: usize) -> (f32, f32) {
+        let resolution = self.config.resolution;
+        (
+            self.config.min.0 + (col as f32 + 0.5) * resolution,
+            self.config.min.1 + (row as f32 + 0.5) * resolution,
+        )
+    }
+
+    pub fn log_odds(&self, col: usize, row: usize) -> f32 {
+        self.cells[row * self.width + col]
+    }
+
+    /// overwrite a cell, e.g. when loading a saved map
+    pub fn set_log_odds(&mut self, col: usize, row: usize, log_odds: f32) {
+        let limit = self.config.limit;
+        self.cells[row * self.width + col] = log_odds.clamp(-limit, limit);
+    }
+
+    /// probability the cell is occupied, 0.5 if nothing's been seen there
+    pub fn probability(&self, col: usize, row: usize) -> f32 {
+        1.0 - 1.0 / (1.0 + self.log_odds(col, row).exp())
+    }
+
+    pub fn cell(&self, col: usize, row: usize) -> Cell {
+        let p = self.probability(col, row);
+        if p >= OCCUPIED_THRESHOLD {
+            Cell::Occupied
+        } else if p <= FREE_THRESHOLD {
+            Cell::Free
+        } else {
+            Cell::Unknown
+        }
+    }
+
+    /// ray trace a revolution from the sensor it came from. pose is where the
+    /// robot was, None for the world origin
+    pub fn insert(&mut self, scan: &Scan, extrinsics: &Extrinsics, pose: Option<Pose>) {
+        self.insert_points((extrinsics.x, extrinsics.y), &scan.points, pose);
+    }
+
+    /// ray trace robot frame points seen from origin, also robot frame
+    pub fn insert_points(&mut self, origin: (f32, f32), points: &[ScanPoint], pose: Option<Pose>) {
+        let pose = pose.unwrap_or_default();
+        self.scans = self.scans.wrapping_add(1).max(1);
+        let stamp = self.scans;
+        let (ox, oy) = pose.apply(origin.0, origin.1);
+        let start = self.cell_unchecked(ox, oy);
+
+        // rays are clipped to max_range and only the ones that weren't end in
+        // a hit
+        let mut rays = Vec::with_capacity(points.len());
+        for p in points.iter().filter(|p| p.distance != 0) {
+            let (dx, dy) = (p.x - origin.0, p.y - origin.1);
+            let range = dx.hypot(dy);
+            if range < f32::EPSILON {
+                continue;
+            }
+            let hit = range <= self.config.max_range;
+            let scale = if hit {
+                1.0
+            } else {
+                self.config.max_range / range
+            };
+            let (x, y) = pose.apply(origin.0 + dx * scale, origin.1 + dy * scale);
+            rays.push((self.cell_unchecked(x, y), hit));
+        }
+
+        // hits first, so a beam grazing past an obstacle another beam landed
+        // on can't clear it
+        for &((col, row), hit) in &rays {
+            if hit && self.in_bounds(col, row) {
+                let i = row as usize * self.width + col as usize;
+                if self.stamps[i] != stamp {
+                    self.stamps[i] = stamp;
+                    self.update(i, self.config.hit);
+                }
+            }
+        }
+        for &(end, hit) in &rays {
+            self.trace(start, end, !hit, stamp);
+        }
+    }
+
+    /// the grid as 8 bit grey levels the way ros map_server draws them:
+    /// occupied 0, free 254, unknown 205. rows run top to bottom, i.e. from
+    /// max.1 down to min.1, so it can be written straight out as an image
+    pub fn image(&self) -> Vec<u8> {
+        let mut pixels = Vec::with_capacity(self.width * self.height);
+        for row in (0..self.height).rev() {
+            for col in 0..self.width {
+                pixels.push(match self.cell(col, row) {
+                    Cell::Occupied => 0,
+                    Cell::Free => 254,
+                    Cell::Unknown => 205,
+                });
+            }
+        }
+        pixels
+    }
+
+    fn cell_unchecked(&self, x: f32, y: f32) -> (i64, i64) {
+        let resolution = self.config.resolution;
+        (
+            ((x - self.config.min.0) / resolution).floor() as i64,
+            ((y - self.config.min.1) / resolution).floor() as i64,
+        )
+    }
+
+    fn in_bounds(&self, col: i64, row: i64) -> bool {
+        col >= 0 && row >= 0 && (col as usize) < self.width && (row as usize) < self.height
+    }
+
+    fn update(&mut self, i: usize, log_odds: f32) {
+        let limit = self.config.limit;
+        self.cells[i] = (self.cells[i] + log_odds).clamp(-limit, limit);
+    }
+
+    // bresenham from start to end, marking everything on the way free. the
+    // end cell is left alone unless the ray was clipped and it's just empty
+    // space
+    fn trace(&mut self, start: (i64, i64), end: (i64, i64), include_end: bool, stamp: u32) {
+        let (mut col, mut row) = start;
+        let dx = (end.0 - col).abs();
+        let dy = -(end.1 - row).abs();
+        let step_x = if col < end.0 { 1 } else { -1 };
+        let step_y = if row < end.1 { 1 } else { -1 };
+        let mut error = dx + dy;
+        let mut entered = false;
+        loop {
+            let at_end = (col, row) == end;
+            if at_end && !include_end {
+                break;
+            }
+            if self.in_bounds(col, row) {
+                entered = true;
+                let i = row as usize * self.width + col as usize;
+                if self.stamps[i] != stamp {
+                    self.stamps[i] = stamp;
+                    self.update(i, self.config.miss);
+                }
+            } else if entered {
+                // the grid is a rectangle, once out it's not coming back
+                break;
+            }
+            if at_end {
+                break;
+            }
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                col += step_x;
+            }
+            if doubled <= dx {
+                error += dx;
+                row += step_y;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 10x10 cells of 100 mm, the origin is the corner of cell (5, 5)
+    fn grid(max_range: f32) -> OccupancyGrid {
+        OccupancyGrid::new(GridConfig {
+            resolution: 100.0,
+            min: (-500.0, -500.0),
+            max: (500.0, 500.0),
+            max_range,
+            ..GridConfig::default()
+        })
+    }
+
+    fn point(x: f32, y: f32) -> ScanPoint {
+        ScanPoint {
+            x,
+            y,
+            angle: 0.0,
+            distance: x.hypot(y) as u16,
+            intensity: 200,
+            sensor: 0,
+        }
+    }
+
+    // every cell that's been touched and its log odds
+    fn touched(grid: &OccupancyGrid) -> Vec<((usize, usize), f32)> {
+        let mut cells = Vec::new();
+        for row in 0..grid.height() {
+            for col in 0..grid.width() {
+                if grid.log_odds(col, row) != 0.0 {
+                    cells.push(((col, row), grid.log_odds(col, row)));
+                }
+            }
+        }
+        cells
+    }
+
+    #[test]
+    fn a_ray_clears_the_way_and_marks_where_it_ended() {
+        let mut grid = grid(12000.0);
+        let config = grid.config;
+        grid.insert_points((0.0, 0.0), &[point(350.0, 50.0)], None);
+        assert_eq!(
+            touched(&grid),
+            vec![
+                ((5, 5), config.miss),
+                ((6, 5), config.miss),
+                ((7, 5), config.miss),
+                ((8, 5), config.hit),
+            ]
+        );
+        assert_eq!(grid.cell(8, 5), Cell::Occupied);
+        assert_eq!(grid.cell(6, 5), Cell::Unknown);
+        // free takes a few looks
+        for _ in 0..3 {
+            grid.insert_points((0.0, 0.0), &[point(350.0, 50.0)], None);
+        }
+        assert_eq!(grid.cell(6, 5), Cell::Free);
+        assert_eq!(grid.scans(), 4);
+    }
+
+    #[test]
+    fn diagonal_rays_step_one_cell_at_a_time() {
+        let mut grid = grid(12000.0);
+        grid.insert_points((0.0, 0.0), &[point(350.0, 250.0)], None);
+        let cells: Vec<(usize, usize)> = touched(&grid).into_iter().map(|(c, _)| c).collect();
+        assert_eq!(cells.len(), 4);
+        assert_eq!(cells.first(), Some(&(5, 5)));
+        assert!(cells.contains(&(8, 7)));
+        assert_eq!(grid.log_odds(8, 7), grid.config.hit);
+        for pair in cells.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            assert!(a.0.abs_diff(b.0) <= 1 && a.1.abs_diff(b.1) <= 1);
+        }
+    }
+
+    #[test]
+    fn rays_past_the_edge_only_clear() {
+        let mut grid = grid(12000.0);
+        let miss = grid.config.miss;
+        grid.insert_points((0.0, 0.0), &[point(2000.0, 50.0)], None);
+        let expected: Vec<_> = (5..10).map(|col| ((col, 5), miss)).collect();
+        assert_eq!(touched(&grid), expected);
+    }
+
+    #[test]
+    fn returns_beyond_max_range_only_clear_up_to_it() {
+        let mut grid = grid(300.0);
+        let miss = grid.config.miss;
+        grid.insert_points((0.0, 0.0), &[point(450.0, 50.0)], None);
+        // clipped at 300 mm, which is in cell 7, and that's cleared as well
+        let expected: Vec<_> = (5..8).map(|col| ((col, 5), miss)).collect();
+        assert_eq!(touched(&grid), expected);
+    }
+
+    #[test]
+    fn each_cell_changes_once_per_scan() {
+        let mut grid = grid(12000.0);
+        let config = grid.config;
+        // two returns in one cell, and a longer ray through it
+        let points = [point(350.0, 50.0), point(360.0, 80.0), point(450.0, 50.0)];
+        grid.insert_points((0.0, 0.0), &points, None);
+        assert_eq!(grid.log_odds(8, 5), config.hit);
+        assert_eq!(grid.log_odds(9, 5), config.hit);
+        for col in 5..8 {
+            assert_eq!(grid.log_odds(col, 5), config.miss);
+        }
+        // the next scan counts again
+        grid.insert_points((0.0, 0.0), &points, None);
+        assert_eq!(grid.log_odds(8, 5), 2.0 * config.hit);
+    }
+
+    #[test]
+    fn log_odds_stay_within_the_limit() {
+        let mut grid = grid(12000.0);
+        for _ in 0..50 {
+            grid.insert_points((0.0, 0.0), &[point(350.0, 50.0)], None);
+        }
+        assert_eq!(grid.log_odds(8, 5), grid.config.limit);
+        assert_eq!(grid.log_odds(6, 5), -grid.config.limit);
+    }
+}
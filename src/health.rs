@@ -0,0 +1,74 @@
+// keeping an eye on a sensor: is it sending, how fast is it spinning, and how
+// much of what it sends is garbage
+use crate::ld19::decoder::Frame;
+use core::fmt;
+use std::time::{Duration, Instant};
+
+// no packets for this long and the sensor is considered stale
+pub const STALE_AFTER: Duration = Duration::from_millis(500);
+// rates are recalculated this often
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Default, Clone)]
+pub struct SensorHealth {
+    pub packets: u64,
+    pub bad_crc: u64,
+    pub resyncs: u64,
+    pub last_packet: Option<Instant>,
+    // degrees per second, as reported by the sensor
+    pub speed: u16,
+    // packets per second over the last window
+    pub packet_rate: f32,
+    window_start: Option<Instant>,
+    window_packets: u64,
+}
+
+impl SensorHealth {
+    pub fn push(&mut self, frame: &Frame, now: Instant) {
+        match frame {
+            Frame::Packet { packet, .. } => {
+                self.packets += 1;
+                self.window_packets += 1;
+                self.last_packet = Some(now);
+                self.speed = packet.speed;
+            }
+            Frame::BadCrc { .. } => self.bad_crc += 1,
+            Frame::Skipped(_) => self.resyncs += 1,
+        }
+        let start = *self.window_start.get_or_insert(now);
+        let elapsed = now.duration_since(start);
+        if elapsed >= RATE_WINDOW {
+            self.packet_rate = self.window_packets as f32 / elapsed.as_secs_f32();
+            self.window_packets = 0;
+            self.window_start = Some(now);
+        }
+    }
+
+    /// revolutions per second
+    pub fn scan_rate(&self) -> f32 {
+        self.speed as f32 / 360.0
+    }
+
+    pub fn is_stale(&self, now: Instant) -> bool {
+        self.last_packet
+            .is_none_or(|last| now.duration_since(last) > STALE_AFTER)
+    }
+}
+
+impl fmt::Display for SensorHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{:.1} Hz, {:.0} pkt/s, {} bad crc, {} resyncs",
+            if self.is_stale(Instant::now()) {
+                "STALE "
+            } else {
+                ""
+            },
+            self.scan_rate(),
+            self.packet_rate,
+            self.bad_crc,
+            self.resyncs
+        )
+    }
+}
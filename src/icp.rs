@@ -0,0 +1,510 @@
+// scan matching with icp, for odometry without wheels. each revolution is
+// lined up against the one before it and how far it had to be moved is how
+// far the robot moved. both flavours are solved the same way, gauss-newton
+// over (x, y, heading) with every residual reweighted by a robust kernel, so
+// the covariance falls out of the same normal equations
+use crate::geometry::{normalize_degrees, Pose};
+use crate::scan::{Scan, ScanPoint};
+use core::fmt;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IcpMethod {
+    // distance to the closest point. simpler, but with points as far apart
+    // as the ld19's it tends to come up short of the real move
+    PointToPoint,
+    // distance to the line through the closest point and its neighbours,
+    // lets points slide along walls so it converges in far fewer iterations
+    #[default]
+    PointToLine,
+}
+
+impl fmt::Display for IcpMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IcpMethod::PointToPoint => write!(f, "point to point"),
+            IcpMethod::PointToLine => write!(f, "point to line"),
+        }
+    }
+}
+
+/// how much a residual counts, so a few bad matches can't drag the whole
+/// scan. parameters are in millimeters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kernel {
+    // plain least squares
+    None,
+    // quadratic up to the parameter, linear after
+    Huber(f32),
+    // keeps shrinking the further out a residual is
+    Cauchy(f32),
+}
+
+impl Kernel {
+    pub fn weight(&self, residual: f32) -> f32 {
+        let r = residual.abs();
+        match *self {
+            Kernel::None => 1.0,
+            Kernel::Huber(k) => {
+                if r <= k {
+                    1.0
+                } else {
+                    k / r
+                }
+            }
+            Kernel::Cauchy(k) => 1.0 / (1.0 + (r / k).powi(2)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IcpConfig {
+    pub method: IcpMethod,
+    pub kernel: Kernel,
+    pub max_iterations: usize,
+    // points farther than this from anything in the other scan aren't
+    // matched, millimeters
+    pub max_correspondence: f32,
+    // too few matches and the result isn't worth trusting
+    pub min_correspondences: usize,
+    // stop once an iteration moves less than this, millimeters and degrees
+    pub translation_epsilon: f32,
+    pub rotation_epsilon: f32,
+    // neighbours within this many millimeters are used to fit the line
+    // through a point
+    pub normal_radius: f32,
+}
+
+impl Default for IcpConfig {
+    fn default() -> Self {
+        Self {
+            method: IcpMethod::default(),
+            kernel: Kernel::Huber(50.0),
+            max_iterations: 30,
+            max_correspondence: 300.0,
+            min_correspondences: 30,
+            translation_epsilon: 0.5,
+            rotation_epsilon: 0.01,
+            normal_radius: 150.0,
+        }
+    }
+}
+
+/// 3x3 covariance over (x mm, y mm, heading degrees)
+pub type Covariance = [[f32; 3]; 3];
+
+#[derive(Debug, Clone, Copy)]
+pub struct IcpResult {
+    // moves the source points onto the target, i.e. the source's pose in
+    // the target's frame
+    pub transform: Pose,
+    pub covariance: Covariance,
+    pub iterations: usize,
+    // false if it ran out of iterations first
+    pub converged: bool,
+    pub correspondences: usize,
+    // rms of the residuals in the last iteration, millimeters
+    pub error: f32,
+}
+
+/// a scan prepared for matching against: a grid hash for the closest point
+/// lookups, and the line normal at each point when there's a line to fit
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub points: Vec<(f32, f32)>,
+    normals: Vec<Option<(f32, f32)>>,
+    cell: f32,
+    grid: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl Reference {
+    pub fn new(points: Vec<(f32, f32)>, config: &IcpConfig) -> Self {
+        let cell = config.max_correspondence.max(config.normal_radius).max(1.0);
+        let mut grid: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+        for (i, p) in points.iter().enumerate() {
+            grid.entry(cell_of(*p, cell)).or_default().push(i);
+        }
+        let mut reference = Self {
+            normals: Vec::new(),
+            points,
+            cell,
+            grid,
+        };
+        reference.normals = (0..reference.points.len())
+            .map(|i| reference.normal(i, config.normal_radius))
+            .collect();
+        reference
+    }
+
+    pub fn from_scan(scan: &Scan, config: &IcpConfig) -> Self {
+        Self::new(positions(&scan.points), config)
+    }
+
+    fn neighbours(&self, p: (f32, f32)) -> impl Iterator<Item = usize> + '_ {
+        let (cx, cy) = cell_of(p, self.cell);
+        (cx - 1..=cx + 1)
+            .flat_map(move |x| (cy - 1..=cy + 1).map(move |y| (x, y)))
+            .filter_map(|key| self.grid.get(&key))
+            .flatten()
+            .copied()
+    }
+
+    fn closest(&self, p: (f32, f32), max_distance: f32) -> Option<(usize, f32)> {
+        self.neighbours(p)
+            .map(|i| {
+                let q = self.points[i];
+                (i, (q.0 - p.0).hypot(q.1 - p.1))
+            })
+            .filter(|(_, d)| *d <= max_distance)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    // smallest eigenvector of the neighbourhood's scatter, as long as the
+    // neighbourhood actually looks like a line
+    fn normal(&self, i: usize, radius: f32) -> Option<(f32, f32)> {
+        let p = self.points[i];
+        let nearby: Vec<(f32, f32)> = self
+            .neighbours(p)
+            .map(|j| self.points[j])
+            .filter(|q| (q.0 - p.0).hypot(q.1 - p.1) <= radius)
+            .collect();
+        if nearby.len() < 3 {
+            return None;
+        }
+        let n = nearby.len() as f32;
+        let (mx, my) = nearby
+            .iter()
+            .fold((0.0, 0.0), |(x, y), q| (x + q.0 / n, y + q.1 / n));
+        let (mut sxx, mut sxy, mut syy) = (0.0, 0.0, 0.0);
+        for (x, y) in &nearby {
+            sxx += (x - mx) * (x - mx);
+            sxy += (x - mx) * (y - my);
+            syy += (y - my) * (y - my);
+        }
+        let trace = sxx + syy;
+        let spread = ((sxx - syy).powi(2) + 4.0 * sxy * sxy).sqrt();
+        let (large, small) = ((trace + spread) / 2.0, (trace - spread) / 2.0);
+        // a blob or a corner has no one normal
+        if large <= f32::EPSILON || small > large * 0.1 {
+            return None;
+        }
+        // direction of the line, the normal is square to it
+        let angle = 0.5 * (2.0 * sxy).atan2(sxx - syy);
+        Some((-angle.sin(), angle.cos()))
+    }
+}
+
+fn cell_of(p: (f32, f32), cell: f32) -> (i32, i32) {
+    ((p.0 / cell).floor() as i32, (p.1 / cell).floor() as i32)
+}
+
+fn positions(points: &[ScanPoint]) -> Vec<(f32, f32)> {
+    points
+        .iter()
+        .filter(|p| p.distance != 0)
+        .map(|p| (p.x, p.y))
+        .collect()
+}
+
+/// line source up with target starting from initial, the best guess at the
+/// source's pose in the target's frame. None if there weren't enough matches
+/// or the problem was degenerate
+pub fn align(
+    source: &[(f32, f32)],
+    target: &Reference,
+    initial: Pose,
+    config: &IcpConfig,
+) -> Option<IcpResult> {
+    // heading in radians while solving
+    let (mut x, mut y, mut theta) = (initial.x, initial.y, initial.heading.to_radians());
+    let mut iterations = 0;
+    let mut converged = false;
+    let mut last = None;
+    while iterations < config.max_iterations {
+        iterations += 1;
+        let (sin, cos) = theta.sin_cos();
+        let mut h = [[0.0f64; 3]; 3];
+        let mut g = [0.0f64; 3];
+        let mut squared = 0.0f64;
+        let mut residuals = 0usize;
+        let mut matches = 0usize;
+        for &(sx, sy) in source {
+            let p = (x + sx * cos - sy * sin, y + sx * sin + sy * cos);
+            let Some((i, _)) = target.closest(p, config.max_correspondence) else {
+                continue;
+            };
+            let q = target.points[i];
+            // how the transformed point moves with a small change in heading
+            let (dpx, dpy) = (-(p.1 - y), p.0 - x);
+            // residuals and their jacobians, one for point to line and one
+            // per axis for point to point
+            let rows = match (config.method, target.normals[i]) {
+                (IcpMethod::PointToLine, Some((nx, ny))) => {
+                    let r = (p.0 - q.0) * nx + (p.1 - q.1) * ny;
+                    [(r, [nx, ny, nx * dpx + ny * dpy]), (0.0, [0.0; 3])]
+                }
+                (IcpMethod::PointToLine, None) => continue,
+                (IcpMethod::PointToPoint, _) => {
+                    [(p.0 - q.0, [1.0, 0.0, dpx]), (p.1 - q.1, [0.0, 1.0, dpy])]
+                }
+            };
+            matches += 1;
+            let count = if config.method == IcpMethod::PointToLine {
+                1
+            } else {
+                2
+            };
+            for &(r, j) in &rows[..count] {
+                let w = config.kernel.weight(r) as f64;
+                for a in 0..3 {
+                    g[a] += w * j[a] as f64 * r as f64;
+                    for b in 0..3 {
+                        h[a][b] += w * j[a] as f64 * j[b] as f64;
+                    }
+                }
+                squared += (r * r) as f64;
+                residuals += 1;
+            }
+        }
+        if matches < config.min_correspondences {
+            return None;
+        }
+        let step = solve(&h, &g)?;
+        x -= step[0] as f32;
+        y -= step[1] as f32;
+        theta -= step[2] as f32;
+        last = Some((h, squared, residuals, matches));
+        if (step[0].hypot(step[1]) as f32) < config.translation_epsilon
+            && (step[2] as f32).to_degrees().abs() < config.rotation_epsilon
+        {
+            converged = true;
+            break;
+        }
+    }
+
+    let (h, squared, residuals, correspondences) = last?;
+    // residual variance over the information matrix, with the heading
+    // rows and columns turned into degrees
+    let variance = squared / (residuals.saturating_sub(3).max(1)) as f64;
+    let inverse = invert(&h)?;
+    let scale = [1.0, 1.0, 180.0 / std::f64::consts::PI];
+    let mut covariance = [[0.0; 3]; 3];
+    for (a, row) in covariance.iter_mut().enumerate() {
+        for (b, value) in row.iter_mut().enumerate() {
+            *value = (inverse[a][b] * variance * scale[a] * scale[b]) as f32;
+        }
+    }
+    Some(IcpResult {
+        transform: Pose::new(x, y, normalize_degrees(theta.to_degrees())),
+        covariance,
+        iterations,
+        converged,
+        correspondences,
+        error: (squared / residuals.max(1) as f64).sqrt() as f32,
+    })
+}
+
+fn determinant(m: &[[f64; 3]; 3]) -> f64 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+fn invert(m: &[[f64; 3]; 3]) -> Option<[[f64; 3]; 3]> {
+    let det = determinant(m);
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let mut inverse = [[0.0; 3]; 3];
+    for (i, row) in inverse.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            // cofactor of m[j][i]
+            let (r0, r1) = ((j + 1) % 3, (j + 2) % 3);
+            let (c0, c1) = ((i + 1) % 3, (i + 2) % 3);
+            *value = (m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]) / det;
+        }
+    }
+    Some(inverse)
+}
+
+// h step = g
+fn solve(h: &[[f64; 3]; 3], g: &[f64; 3]) -> Option<[f64; 3]> {
+    let inverse = invert(h)?;
+    let mut step = [0.0; 3];
+    for (a, value) in step.iter_mut().enumerate() {
+        *value = (0..3).map(|b| inverse[a][b] * g[b]).sum();
+    }
+    Some(step)
+}
+
+/// one revolution's worth of odometry
+#[derive(Debug, Clone, Copy)]
+pub struct OdometryStep {
+    // sensor time of the scan, unwrapped milliseconds
+    pub timestamp: u64,
+    // how the robot moved since the previous scan, in that scan's frame
+    pub delta: Pose,
+    // where that puts it relative to the first scan
+    pub pose: Pose,
+    // infinite along the diagonal when matching failed, nothing is known
+    // about a move that was never measured
+    pub covariance: Covariance,
+    // false when matching failed, the pose is then just carried forward
+    pub matched: bool,
+    // 0 and infinite when matching failed
+    pub iterations: usize,
+    pub error: f32,
+}
+
+/// matches every scan against the one before it and adds up the moves
+#[derive(Debug, Clone, Default)]
+pub struct Odometry {
+    pub config: IcpConfig,
+    previous: Option<Reference>,
+    // the last move, which is the guess for the next one
+    velocity: Pose,
+    pose: Pose,
+}
+
+impl Odometry {
+    pub fn new(config: IcpConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// where the robot is relative to the first scan
+    pub fn pose(&self) -> Pose {
+        self.pose
+    }
+
+    /// start again from the origin
+    pub fn reset(&mut self) {
+        *self = Self::new(self.config);
+    }
+
+    /// feed the next revolution. the first one only sets the reference, so
+    /// there's nothing to report until the second
+    pub fn push(&mut self, scan: &Scan) -> Option<OdometryStep> {
+        let reference = Reference::from_scan(scan, &self.config);
+        let previous = self.previous.replace(reference)?;
+        let source = positions(&scan.points);
+        let result = align(&source, &previous, self.velocity, &self.config);
+        let step = match result {
+            Some(result) => {
+                self.velocity = result.transform;
+                self.pose = self.pose.compose(&result.transform);
+                OdometryStep {
+                    timestamp: scan.timestamp,
+                    delta: result.transform,
+                    pose: self.pose,
+                    covariance: result.covariance,
+                    matched: true,
+                    iterations: result.iterations,
+                    error: result.error,
+                }
+            }
+            None => {
+                self.velocity = Pose::default();
+                OdometryStep {
+                    timestamp: scan.timestamp,
+                    delta: Pose::default(),
+                    pose: self.pose,
+                    covariance: [
+                        [f32::INFINITY, 0.0, 0.0],
+                        [0.0, f32::INFINITY, 0.0],
+                        [0.0, 0.0, f32::INFINITY],
+                    ],
+                    matched: false,
+                    iterations: 0,
+                    error: f32::INFINITY,
+                }
+            }
+        };
+        Some(step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{self, Scene, SimConfig, Simulator};
+
+    // drive a lap of the simulated room, 10 scans a second, and return
+    // the worst step and how far off the end pose is, millimeters and degrees
+    fn drive(method: IcpMethod) -> ((f32, f32), (f32, f32)) {
+        let mut sim = Simulator::new(Scene::room(), SimConfig::default());
+        let mut odometry = Odometry::new(IcpConfig {
+            method,
+            ..IcpConfig::default()
+        });
+        let start = sim::lap(0.0);
+        let mut previous = start;
+        let mut worst = (0.0f32, 0.0f32);
+        let mut last = (0.0, 0.0);
+        for i in 0..=300 {
+            let seconds = i as f32 * 0.1;
+            let truth = sim::lap(seconds);
+            let scan = sim.scan(truth, (seconds * 1000.0) as u64);
+            let Some(step) = odometry.push(&scan) else {
+                continue;
+            };
+            assert!(step.matched, "{method} lost track at {seconds} s");
+            let moved = previous.inverse().compose(&truth);
+            worst.0 = worst
+                .0
+                .max((step.delta.x - moved.x).hypot(step.delta.y - moved.y));
+            worst.1 = worst
+                .1
+                .max(normalize_degrees(step.delta.heading - moved.heading).abs());
+            let expected = start.inverse().compose(&truth);
+            last = (
+                (step.pose.x - expected.x).hypot(step.pose.y - expected.y),
+                normalize_degrees(step.pose.heading - expected.heading).abs(),
+            );
+            previous = truth;
+        }
+        (worst, last)
+    }
+
+    #[test]
+    fn point_to_line_follows_the_ground_truth() {
+        let ((step, step_angle), (drift, drift_angle)) = drive(IcpMethod::PointToLine);
+        assert!(
+            step < 10.0 && step_angle < 0.3,
+            "step off by {step} mm, {step_angle} deg"
+        );
+        assert!(
+            drift < 30.0 && drift_angle < 0.5,
+            "drifted {drift} mm, {drift_angle} deg"
+        );
+    }
+
+    #[test]
+    fn point_to_point_follows_the_ground_truth() {
+        // it comes up a little short every step, see IcpMethod, and over a
+        // lap that adds up to about half a meter and 20 degrees
+        let ((step, step_angle), (drift, drift_angle)) = drive(IcpMethod::PointToPoint);
+        assert!(
+            step < 25.0 && step_angle < 1.0,
+            "step off by {step} mm, {step_angle} deg"
+        );
+        assert!(
+            drift < 800.0 && drift_angle < 30.0,
+            "drifted {drift} mm, {drift_angle} deg"
+        );
+    }
+
+    #[test]
+    fn too_few_matches_is_none() {
+        let mut sim = Simulator::new(Scene::room(), SimConfig::default());
+        let config = IcpConfig::default();
+        let scan = sim.scan(sim::lap(0.0), 0);
+        let reference = Reference::from_scan(&scan, &config);
+        let source = positions(&scan.points);
+        assert!(align(&source, &reference, Pose::default(), &config).is_some());
+        let few = &source[..config.min_correspondences - 1];
+        assert!(align(few, &reference, Pose::default(), &config).is_none());
+    }
+}
@@ -0,0 +1,198 @@
+// packet inspector. keeps the most recent frames off the wire, good or bad,
+// and lets you step back through them. details go to the terminal, the
+// selected packet's points get highlighted in the viewer
+use core::fmt;
+use lidar::extrinsics::Extrinsics;
+use lidar::ld19::decoder::Frame;
+use lidar::ld19::point::parse;
+use std::collections::VecDeque;
+use std::time::Instant;
+
+// a bit under three seconds of packets
+pub const INSPECT_HISTORY: usize = 1000;
+
+#[derive(Debug, Clone)]
+pub struct Inspected {
+    // counts up from the first frame received
+    pub seq: u64,
+    pub sensor: u8,
+    pub at: Instant,
+    pub frame: Frame,
+}
+
+impl Inspected {
+    /// robot frame coordinates of the packet's points, empty unless it's a
+    /// packet
+    pub fn positions(&self, inspector: &Inspector) -> Vec<(f32, f32)> {
+        let extrinsics = inspector
+            .extrinsics
+            .get(self.sensor as usize)
+            .copied()
+            .unwrap_or_default();
+        match &self.frame {
+            Frame::Packet { packet, .. } => parse(packet)
+                .iter()
+                .map(|p| extrinsics.to_robot(p))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// one line summary for the viewer
+    pub fn summary(&self) -> String {
+        match &self.frame {
+            Frame::Packet { packet, .. } => format!(
+                "#{} sensor {} packet, {:.2}..{:.2} deg, {} ms{}",
+                self.seq,
+                self.sensor,
+                packet.start_angle as f32 / 100.0,
+                packet.end_angle as f32 / 100.0,
+                packet.timestamp,
+                if self.frame.is_malformed() {
+                    ", nonstandard header"
+                } else {
+                    ""
+                }
+            ),
+            Frame::BadCrc {
+                expected, received, ..
+            } => format!(
+                "#{} sensor {} BAD CRC, expected {expected:#04x} received {received:#04x}",
+                self.seq, self.sensor
+            ),
+            Frame::Skipped(bytes) => {
+                format!(
+                    "#{} sensor {} RESYNC, skipped {} bytes",
+                    self.seq,
+                    self.sensor,
+                    bytes.len()
+                )
+            }
+        }
+    }
+}
+
+impl fmt::Display for Inspected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} (received {:.3} s ago)",
+            self.summary(),
+            self.at.elapsed().as_secs_f32()
+        )?;
+        if let Frame::Packet { packet, .. } = &self.frame {
+            writeln!(f, "{packet}")?;
+            writeln!(f, "crc: ok")?;
+            let angles: Vec<String> = parse(packet)
+                .iter()
+                .map(|p| format!("{:.2}", p.angle))
+                .collect();
+            writeln!(f, "angles: [{}]", angles.join(", "))?;
+        }
+        // classic 16 bytes per line hex dump
+        for (i, line) in self.frame.bytes().chunks(16).enumerate() {
+            let hex: Vec<String> = line.iter().map(|b| format!("{b:02x}")).collect();
+            writeln!(f, "{:04x}  {}", i * 16, hex.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Inspector {
+    pub active: bool,
+    frames: VecDeque<Inspected>,
+    next_seq: u64,
+    // sequence number of the selected frame, None follows the newest
+    selected: Option<u64>,
+    // counts since startup
+    pub bad_crc: u64,
+    pub resyncs: u64,
+    // per sensor, so highlighted points line up with the drawn ones
+    pub extrinsics: Vec<Extrinsics>,
+}
+
+impl Inspector {
+    pub fn push(&mut self, sensor: u8, frame: Frame) {
+        match frame {
+            Frame::BadCrc { .. } => self.bad_crc += 1,
+            Frame::Skipped(_) => self.resyncs += 1,
+            _ => (),
+        }
+        if self.frames.len() >= INSPECT_HISTORY {
+            self.frames.pop_front();
+        }
+        self.frames.push_back(Inspected {
+            seq: self.next_seq,
+            sensor,
+            at: Instant::now(),
+            frame,
+        });
+        self.next_seq += 1;
+    }
+
+    pub fn toggle(&mut self) {
+        self.active = !self.active;
+        // start on the newest frame and stay there until moved
+        self.selected = self.frames.back().map(|f| f.seq);
+    }
+
+    fn index_of(&self, seq: u64) -> Option<usize> {
+        let first = self.frames.front()?.seq;
+        let i = seq.checked_sub(first)? as usize;
+        (i < self.frames.len()).then_some(i)
+    }
+
+    pub fn selected(&self) -> Option<&Inspected> {
+        match self.selected {
+            // the selected frame might have fallen out of the history
+            Some(seq) => self.index_of(seq).map(|i| &self.frames[i]),
+            None => self.frames.back(),
+        }
+    }
+
+    fn select_index(&mut self, i: usize) {
+        self.selected = self.frames.get(i).map(|f| f.seq);
+    }
+
+    fn selected_index(&self) -> usize {
+        self.selected
+            .and_then(|seq| self.index_of(seq))
+            .unwrap_or(self.frames.len().saturating_sub(1))
+    }
+
+    pub fn older(&mut self) {
+        let i = self.selected_index();
+        self.select_index(i.saturating_sub(1));
+    }
+
+    pub fn newer(&mut self) {
+        let i = self.selected_index();
+        self.select_index((i + 1).min(self.frames.len().saturating_sub(1)));
+    }
+
+    pub fn oldest(&mut self) {
+        self.select_index(0);
+    }
+
+    pub fn newest(&mut self) {
+        self.select_index(self.frames.len().saturating_sub(1));
+    }
+
+    /// jump to the closest malformed frame before the selection
+    pub fn previous_malformed(&mut self) {
+        let i = self.selected_index();
+        if let Some(j) = (0..i).rev().find(|&j| self.frames[j].frame.is_malformed()) {
+            self.select_index(j);
+        }
+    }
+
+    /// jump to the closest malformed frame after the selection
+    pub fn next_malformed(&mut self) {
+        let i = self.selected_index();
+        let next = (i + 1..self.frames.len()).find(|&j| self.frames[j].frame.is_malformed());
+        if let Some(j) = next {
+            self.select_index(j);
+        }
+    }
+}
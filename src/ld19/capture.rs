@@ -0,0 +1,45 @@
+// captures are just the raw bytes of every good packet, exactly as they came
+// off the wire, one after another. that means they decode with the same
+// codecs as the serial port and can be poked at with a hex editor
+use super::decoder::{Frame, InspectCodec, Packet, ParseError};
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use tokio_util::{bytes::BytesMut, codec::Decoder};
+
+/// read every good packet out of a capture file. bad crcs and garbage between
+/// packets are skipped, same as on a live port
+pub fn read<P: AsRef<Path>>(path: P) -> Result<Vec<Packet>, ParseError> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+    let mut src = BytesMut::from(&bytes[..]);
+    let mut codec = InspectCodec::default();
+    let mut packets = Vec::new();
+    while let Some(frame) = codec.decode(&mut src)? {
+        if let Frame::Packet { packet, .. } = frame {
+            packets.push(packet);
+        }
+    }
+    Ok(packets)
+}
+
+pub struct CaptureWriter {
+    writer: BufWriter<File>,
+}
+
+impl CaptureWriter {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+        })
+    }
+
+    pub fn write(&mut self, packet: &Packet) -> io::Result<()> {
+        self.writer.write_all(&packet.as_bytes())
+    }
+
+    /// packets are buffered, call this every so often
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
@@ -68,7 +68,7 @@ impl From<io::Error> for ParseError {
 
 impl std::error::Error for ParseError {}
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Header {
     header: u8,
     ver_len: u8,
@@ -127,6 +127,10 @@ impl Header {
     fn version(&self) -> usize {
         (self.ver_len >> 5) as usize
     }
+    /// true if ver_len is the only value the ld19 is documented to send
+    pub fn is_standard(&self) -> bool {
+        self.ver_len == Self::VER_LEN_DEFAULT
+    }
     fn payload_count(&self) -> usize {
         (self.ver_len & 0b00011111) as usize
     }
@@ -140,7 +144,7 @@ impl Header {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Payload {
     pub distance: u16,
     pub intensity: u8,
@@ -166,8 +170,8 @@ impl Payload {
         Ok(Self::from_bytes(bytes))
     }
     fn from_bytes(bytes: &[u8]) -> Self {
-        let distance = u16::from_le_bytes(bytes[..U16_LEN].try_into().unwrap()).clone();
-        let intensity = bytes[U16_LEN].clone();
+        let distance = u16::from_le_bytes(bytes[..U16_LEN].try_into().unwrap());
+        let intensity = bytes[U16_LEN];
 
         Self {
             distance,
@@ -176,7 +180,7 @@ impl Payload {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Packet {
     pub header: Header,
     pub speed: u16,
@@ -240,14 +244,12 @@ impl Packet {
             bytes[Self::IDX_SPEED..Self::IDX_SPEED + U16_LEN]
                 .try_into()
                 .unwrap(),
-        )
-        .clone();
+        );
         let start_angle = u16::from_le_bytes(
             bytes[Self::IDX_START_ANGLE..Self::IDX_START_ANGLE + U16_LEN]
                 .try_into()
                 .unwrap(),
-        )
-        .clone();
+        );
 
         let mut data = Vec::<Payload>::new();
         for i in 0..(header.payload_count()) {
@@ -264,16 +266,14 @@ impl Packet {
             bytes[idx_payload_end..idx_payload_end + U16_LEN]
                 .try_into()
                 .unwrap(),
-        )
-        .clone();
+        );
         let timestamp = u16::from_le_bytes(
             bytes[idx_payload_end + Self::OFST_TIMESTAMP
                 ..idx_payload_end + Self::OFST_TIMESTAMP + U16_LEN]
                 .try_into()
                 .unwrap(),
-        )
-        .clone();
-        let crc = bytes[idx_payload_end + Self::OFST_CRC].clone();
+        );
+        let crc = bytes[idx_payload_end + Self::OFST_CRC];
 
         Self {
             header,
@@ -293,11 +293,11 @@ impl Packet {
     }
     fn get_crc_from_described_bytes(header: &Header, bytes: &[u8]) -> u8 {
         let idx_payload_end: usize = Self::IDX_PAYLOAD + header.payload_bytes();
-        bytes[idx_payload_end + Self::OFST_CRC].clone()
+        bytes[idx_payload_end + Self::OFST_CRC]
         // lmao, technically i could also just do
         //bytes[header.described_bytes() - 1].clone()
     }
-    fn as_bytes(&self) -> Vec<u8> {
+    pub fn as_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
         bytes.push(self.header.header);
         bytes.push(self.header.ver_len);
@@ -310,7 +310,7 @@ impl Packet {
         bytes.extend(self.end_angle.to_le_bytes());
         bytes.extend(self.timestamp.to_le_bytes());
         bytes.push(self.crc);
-        return bytes;
+        bytes
     }
     fn length_in_bytes(&self) -> usize {
         Header::BYTES + self.data.len() + 9
@@ -346,7 +346,7 @@ impl Decoder for LidarCodec {
             // the crc calculation does not include the crc value, we can
             // decrease the packet_data length to ignore it
             if Packet::get_crc_from_described_bytes(&header, &packet_data)
-            != calc_crc(&packet_data, header.described_bytes() - 1)
+                != calc_crc(&packet_data, header.described_bytes() - 1)
             {
                 // here is a great spot to log a warning if i ever get around
                 // to implementing logging
@@ -358,8 +358,92 @@ impl Decoder for LidarCodec {
             return Ok(Some(Packet::from_described_bytes(header, &packet_data)));
         } else {
             // no header was parsed, split off the first byte loop and try the next one
-            let _ = src.advance(1);
+            src.advance(1);
         }
         Ok(None)
     }
 }
+
+/// everything the decoder saw on the wire, including what it would normally
+/// throw away. useful for debugging the protocol
+#[derive(Debug, Clone)]
+pub enum Frame {
+    Packet {
+        packet: Packet,
+        bytes: Vec<u8>,
+    },
+    // a complete packet whose crc didn't match what we calculated
+    BadCrc {
+        bytes: Vec<u8>,
+        expected: u8,
+        received: u8,
+    },
+    // bytes skipped over while looking for the next header
+    Skipped(Vec<u8>),
+}
+
+impl Frame {
+    pub fn bytes(&self) -> &[u8] {
+        match self {
+            Frame::Packet { bytes, .. } => bytes,
+            Frame::BadCrc { bytes, .. } => bytes,
+            Frame::Skipped(bytes) => bytes,
+        }
+    }
+    /// a frame that isn't a clean, standard packet
+    pub fn is_malformed(&self) -> bool {
+        match self {
+            Frame::Packet { packet, .. } => !packet.header.is_standard(),
+            _ => true,
+        }
+    }
+}
+
+/// like LidarCodec, but hands back every Frame instead of just the good
+/// packets
+#[derive(Debug, Default)]
+pub struct InspectCodec {
+    skipped: Vec<u8>,
+}
+
+impl Decoder for InspectCodec {
+    type Item = Frame;
+    type Error = ParseError;
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        loop {
+            let current_len = src.len();
+            if current_len < Header::BYTES {
+                src.reserve(Header::BYTES - current_len);
+                return Ok(None);
+            }
+            let Ok(header) = Header::try_parse(&src[..2]) else {
+                // keep track of what we skip so it can be reported as one gap
+                self.skipped.push(src[0]);
+                src.advance(1);
+                continue;
+            };
+            // found the next header, report the gap before the packet
+            if !self.skipped.is_empty() {
+                return Ok(Some(Frame::Skipped(std::mem::take(&mut self.skipped))));
+            }
+            if current_len < header.described_bytes() {
+                src.reserve(header.described_bytes() - current_len);
+                return Ok(None);
+            }
+            let packet_data = src.split_to(header.described_bytes());
+            let received = Packet::get_crc_from_described_bytes(&header, &packet_data);
+            let expected = calc_crc(&packet_data, header.described_bytes() - 1);
+            if received != expected {
+                return Ok(Some(Frame::BadCrc {
+                    bytes: packet_data.to_vec(),
+                    expected,
+                    received,
+                }));
+            }
+            return Ok(Some(Frame::Packet {
+                packet: Packet::from_described_bytes(header, &packet_data),
+                bytes: packet_data.to_vec(),
+            }));
+        }
+    }
+}
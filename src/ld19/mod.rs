@@ -1,2 +1,4 @@
 #![allow(dead_code)]
-pub mod decoder;
\ No newline at end of file
+pub mod capture;
+pub mod decoder;
+pub mod point;
@@ -0,0 +1,43 @@
+// turning packets into measurements
+use super::decoder::Packet;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Point {
+    // degrees, 0..360
+    pub angle: f32,
+    // millimeters
+    pub distance: u32,
+    pub confidence: u8,
+}
+
+/// spread a packet's measurements evenly between its start and end angle
+pub fn parse(packet: &Packet) -> Vec<Point> {
+    let start_angle = packet.start_angle as f32 / 100.0;
+    let end_angle = packet.end_angle as f32 / 100.0;
+    // a packet that crosses 0 has an end angle smaller than its start angle,
+    // so add a full turn before taking the difference. the c++ sdk does this
+    // in hundredths of a degree, here it's already degrees
+    let diff = (end_angle + 360.0 - start_angle) % 360.0;
+    // the first point sits on the start angle and the last on the end angle
+    let step = diff / (packet.data.len().max(2) - 1) as f32;
+    packet
+        .data
+        .iter()
+        .enumerate()
+        .map(|(i, d)| {
+            let angle = start_angle + (step * i as f32);
+            Point {
+                angle: if angle >= 360.0 { angle - 360.0 } else { angle },
+                distance: d.distance as u32,
+                confidence: d.intensity,
+            }
+        })
+        .collect()
+}
+
+pub fn polar_to_cartesian(distance: u32, theta: f32) -> (f32, f32) {
+    (
+        distance as f32 * f32::cos(theta.to_radians()),
+        distance as f32 * f32::sin(theta.to_radians()),
+    )
+}
@@ -0,0 +1,22 @@
+pub mod background;
+pub mod cluster;
+pub mod extrinsics;
+pub mod filter;
+pub mod geometry;
+pub mod grid;
+pub mod health;
+pub mod icp;
+pub mod ld19;
+pub mod lines;
+pub mod map;
+pub mod people;
+pub mod profile;
+pub mod query;
+pub mod raster;
+pub mod safety;
+pub mod scan;
+pub mod sim;
+pub mod slam;
+pub mod temporal;
+pub mod track;
+pub mod zone;
@@ -0,0 +1,294 @@
+// line segment extraction with split-and-merge. a scan from one sensor comes
+// in angle order, so neighbouring points are neighbours on the wall too
+use crate::cluster::breakpoints;
+use crate::scan::{Scan, ScanPoint};
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy)]
+pub struct LineConfig {
+    // a point farther than this from the line through a run's endpoints
+    // splits the run there, millimeters
+    pub split_threshold: f32,
+    // neighbouring segments are merged back together if a single line fits
+    // them with an rms residual under this, millimeters
+    pub merge_threshold: f32,
+    // neighbouring points farther apart than this can't be on the same
+    // segment, millimeters plus a fraction of the range
+    pub max_gap: f32,
+    pub max_gap_ratio: f32,
+    // anything smaller isn't worth calling a wall
+    pub min_points: usize,
+    pub min_length: f32,
+}
+
+impl Default for LineConfig {
+    fn default() -> Self {
+        Self {
+            split_threshold: 30.0,
+            merge_threshold: 15.0,
+            max_gap: 150.0,
+            max_gap_ratio: 0.05,
+            min_points: 6,
+            min_length: 150.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Segment {
+    // endpoints on the fitted line, robot frame millimeters
+    pub start: (f32, f32),
+    pub end: (f32, f32),
+    // rms distance of the supporting points from the line, millimeters
+    pub residual: f32,
+    pub points: Vec<ScanPoint>,
+}
+
+impl Segment {
+    pub fn length(&self) -> f32 {
+        (self.end.0 - self.start.0).hypot(self.end.1 - self.start.1)
+    }
+
+    /// direction of the segment in the robot frame, degrees
+    pub fn heading(&self) -> f32 {
+        (self.end.1 - self.start.1)
+            .atan2(self.end.0 - self.start.0)
+            .to_degrees()
+    }
+}
+
+// a least squares line through some points: centroid, unit direction and rms
+// residual
+#[derive(Debug, Clone, Copy)]
+struct Fit {
+    centroid: (f32, f32),
+    direction: (f32, f32),
+    residual: f32,
+}
+
+impl Fit {
+    /// total least squares, which unlike y = mx + b doesn't care which way
+    /// the wall runs
+    fn new(points: &[ScanPoint]) -> Self {
+        let n = points.len() as f32;
+        let cx = points.iter().map(|p| p.x).sum::<f32>() / n;
+        let cy = points.iter().map(|p| p.y).sum::<f32>() / n;
+        let (mut sxx, mut syy, mut sxy) = (0.0, 0.0, 0.0);
+        for p in points {
+            let (dx, dy) = (p.x - cx, p.y - cy);
+            sxx += dx * dx;
+            syy += dy * dy;
+            sxy += dx * dy;
+        }
+        let theta = 0.5 * (2.0 * sxy).atan2(sxx - syy);
+        let direction = (theta.cos(), theta.sin());
+        let mut fit = Self {
+            centroid: (cx, cy),
+            direction,
+            residual: 0.0,
+        };
+        let squares: f32 = points.iter().map(|p| fit.distance(p.x, p.y).powi(2)).sum();
+        fit.residual = (squares / n).sqrt();
+        fit
+    }
+
+    fn distance(&self, x: f32, y: f32) -> f32 {
+        let (dx, dy) = (x - self.centroid.0, y - self.centroid.1);
+        (dx * self.direction.1 - dy * self.direction.0).abs()
+    }
+
+    /// closest point on the line
+    fn project(&self, x: f32, y: f32) -> (f32, f32) {
+        let (dx, dy) = (x - self.centroid.0, y - self.centroid.1);
+        let t = dx * self.direction.0 + dy * self.direction.1;
+        (
+            self.centroid.0 + t * self.direction.0,
+            self.centroid.1 + t * self.direction.1,
+        )
+    }
+}
+
+// distance from a point to the infinite line through a and b
+fn distance_to_line(p: &ScanPoint, a: &ScanPoint, b: &ScanPoint) -> f32 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length = dx.hypot(dy);
+    if length == 0.0 {
+        return (p.x - a.x).hypot(p.y - a.y);
+    }
+    ((p.x - a.x) * dy - (p.y - a.y) * dx).abs() / length
+}
+
+/// pull line segments out of a scan
+pub fn extract(scan: &Scan, config: &LineConfig) -> Vec<Segment> {
+    let points: Vec<ScanPoint> = scan
+        .points
+        .iter()
+        .filter(|p| p.distance != 0)
+        .copied()
+        .collect();
+
+    // runs of points with no big jumps between them
+    let runs = breakpoints(&points, config.max_gap, config.max_gap_ratio);
+
+    let mut segments = Vec::new();
+    for run in runs {
+        let run = &points[run];
+        let mut pieces = Vec::new();
+        split(run, 0..run.len(), config, &mut pieces);
+        for piece in merge(run, pieces, config) {
+            let piece = &run[piece];
+            if piece.len() < config.min_points {
+                continue;
+            }
+            let fit = Fit::new(piece);
+            let (first, last) = (&piece[0], &piece[piece.len() - 1]);
+            let segment = Segment {
+                start: fit.project(first.x, first.y),
+                end: fit.project(last.x, last.y),
+                residual: fit.residual,
+                points: piece.to_vec(),
+            };
+            if segment.length() >= config.min_length {
+                segments.push(segment);
+            }
+        }
+    }
+    segments
+}
+
+// keep splitting at the farthest point until every piece is close enough to
+// the line through its endpoints. pieces are index ranges into the run
+fn split(
+    run: &[ScanPoint],
+    range: Range<usize>,
+    config: &LineConfig,
+    pieces: &mut Vec<Range<usize>>,
+) {
+    let points = &run[range.clone()];
+    if points.len() < 3 {
+        pieces.push(range);
+        return;
+    }
+    let (first, last) = (&points[0], &points[points.len() - 1]);
+    let (farthest, distance) = points
+        .iter()
+        .enumerate()
+        .map(|(i, p)| (i, distance_to_line(p, first, last)))
+        .fold((0, 0.0), |best, d| if d.1 > best.1 { d } else { best });
+    if distance > config.split_threshold {
+        // the corner point goes with both sides so the walls meet
+        let corner = range.start + farthest;
+        split(run, range.start..corner + 1, config, pieces);
+        split(run, corner..range.end, config, pieces);
+    } else {
+        pieces.push(range);
+    }
+}
+
+// splitting only ever looks at endpoints, so noise can cut one wall into a
+// few pieces. glue neighbours back together if they still fit one line
+fn merge(run: &[ScanPoint], pieces: Vec<Range<usize>>, config: &LineConfig) -> Vec<Range<usize>> {
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for piece in pieces {
+        if let Some(previous) = merged.last_mut() {
+            let joined = previous.start..piece.end;
+            if joined.len() >= 3
+                && Fit::new(&run[joined.clone()]).residual <= config.merge_threshold
+            {
+                *previous = joined;
+                continue;
+            }
+        }
+        merged.push(piece);
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f32, y: f32) -> ScanPoint {
+        ScanPoint {
+            x,
+            y,
+            angle: y.atan2(x).to_degrees().rem_euclid(360.0),
+            distance: x.hypot(y) as u16,
+            intensity: 200,
+            sensor: 0,
+        }
+    }
+
+    fn scan(points: Vec<ScanPoint>) -> Scan {
+        Scan {
+            sensor: 0,
+            revolution: 0,
+            timestamp: 0,
+            points,
+        }
+    }
+
+    // a corner a meter ahead and a meter to the left, walls along x = 1000
+    // and y = 1000, a return every degree from 0 to 90
+    fn corner() -> Vec<ScanPoint> {
+        (0..=90)
+            .map(|a| {
+                let (sin, cos) = (a as f32).to_radians().sin_cos();
+                let range = (1000.0 / cos.max(1e-6)).min(1000.0 / sin.max(1e-6));
+                point(range * cos, range * sin)
+            })
+            .collect()
+    }
+
+    fn close(a: (f32, f32), b: (f32, f32)) -> bool {
+        (a.0 - b.0).hypot(a.1 - b.1) < 5.0
+    }
+
+    #[test]
+    fn splits_a_corner_into_two_walls() {
+        let segments = extract(&scan(corner()), &LineConfig::default());
+        assert_eq!(segments.len(), 2);
+        let (a, b) = (&segments[0], &segments[1]);
+        assert!(close(a.start, (1000.0, 0.0)), "{:?}", a.start);
+        assert!(close(a.end, (1000.0, 1000.0)), "{:?}", a.end);
+        assert!(close(b.start, (1000.0, 1000.0)), "{:?}", b.start);
+        assert!(close(b.end, (0.0, 1000.0)), "{:?}", b.end);
+        assert!((a.heading() - 90.0).abs() < 0.5);
+        assert!((b.heading().abs() - 180.0).abs() < 0.5);
+        assert!(a.residual < 1.0 && b.residual < 1.0);
+        // the corner is on both
+        assert_eq!(a.points.len() + b.points.len(), 92);
+    }
+
+    #[test]
+    fn a_noisy_wall_stays_in_one_piece() {
+        // a few millimeters of noise is enough to split it with a tight
+        // threshold, and merging should put it back together
+        let points = (0..40)
+            .map(|i| {
+                let noise = [0.0, 8.0, -8.0, 4.0][i % 4];
+                point(1500.0 + noise, -1000.0 + i as f32 * 50.0)
+            })
+            .collect();
+        let config = LineConfig {
+            split_threshold: 5.0,
+            ..LineConfig::default()
+        };
+        let segments = extract(&scan(points), &config);
+        assert_eq!(segments.len(), 1);
+        assert!((segments[0].length() - 1950.0).abs() < 20.0);
+    }
+
+    #[test]
+    fn gaps_and_short_pieces_are_left_out() {
+        let mut points: Vec<ScanPoint> = (0..20)
+            .map(|i| point(1000.0, -500.0 + i as f32 * 25.0))
+            .collect();
+        // a jump to a wall farther back, then a stub too short to count
+        points.extend((0..20).map(|i| point(3000.0, 500.0 + i as f32 * 50.0)));
+        points.extend((0..4).map(|i| point(500.0, 2000.0 + i as f32 * 25.0)));
+        let segments = extract(&scan(points), &LineConfig::default());
+        assert_eq!(segments.len(), 2);
+        assert!(segments.iter().all(|s| s.points.len() == 20));
+    }
+}
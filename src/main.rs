@@ -1,6 +1,19 @@
+use args::Args;
+use compare::Comparison;
+use config::Config;
 use futures::stream::StreamExt;
-use ld19::decoder::Packet;
+use lidar::extrinsics::Extrinsics;
+use lidar::filter::{CrosstalkFilter, FootprintFilter};
+use lidar::ld19::capture::CaptureWriter;
+use lidar::ld19::decoder::{Frame, InspectCodec};
+use lidar::map;
+use lidar::safety::{FieldLevel, SafetyMonitor};
+use lidar::scan::{ScanAssembler, ScanPoint};
+use lidar::sim::{self, Scene, SimConfig, Simulator};
+use lidar::zone::ZoneMonitor;
+use std::path::{Path, PathBuf};
 use std::thread;
+use std::time::{Duration, Instant};
 use tokio::runtime::Runtime;
 use tokio_serial::{SerialPort, SerialPortBuilderExt};
 use tokio_util::codec::Decoder;
@@ -9,76 +22,257 @@ use winit::{
     event_loop::{ControlFlow, EventLoop, EventLoopProxy},
 };
 
-mod ld19;
+mod args;
+mod colormap;
+mod compare;
+mod config;
+mod inspector;
+mod mapping;
+mod measure;
+mod playback;
+mod screenshot;
 mod window;
 use window::*;
 
 const WIDTH: u32 = 800;
 const HEIGHT: u32 = 800;
 
+// colors handed out to sensors that don't pick their own
+const SENSOR_COLORS: [(u8, u8, u8); 4] = [
+    (0x00, 0xc8, 0xff),
+    (0xff, 0x40, 0xff),
+    (0xff, 0xe0, 0x00),
+    (0x40, 0xff, 0x40),
+];
+
+// everything a lidar thread needs to know about its sensor
+struct SensorSource {
+    index: u8,
+    name: String,
+    port: String,
+    color: (u8, u8, u8),
+    extrinsics: Extrinsics,
+    capture: Option<PathBuf>,
+    footprint: Option<FootprintFilter>,
+    crosstalk: Option<CrosstalkFilter>,
+    safety: Option<SafetyMonitor>,
+    // stop everything once the safety state gets this bad
+    exit_on: Option<FieldLevel>,
+}
+
+// with more than one sensor every capture gets the sensor's name tacked on,
+// so capture.bin becomes capture-front.bin
+fn capture_path(path: &Path, name: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let mut file = format!("{stem}-{name}");
+    if let Some(ext) = path.extension() {
+        file.push('.');
+        file.push_str(&ext.to_string_lossy());
+    }
+    path.with_file_name(file)
+}
+
 #[tokio::main]
 async fn main() {
     //env_logger::init();
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(err) => {
+            if !err.is_empty() {
+                println!("{err}");
+            }
+            println!("{}", args::USAGE);
+            return;
+        }
+    };
+
+    let config = match args.config.as_ref().map(Config::load).transpose() {
+        Ok(config) => config.unwrap_or_default(),
+        Err(err) => {
+            println!("[config] unable to load config, {err}");
+            return;
+        }
+    };
+
     let event_loop = EventLoop::<UserEvent>::with_user_event().build().unwrap();
     event_loop.set_control_flow(ControlFlow::Poll);
     let mut state = State::with_size(PhysicalSize::new(WIDTH as f64, HEIGHT as f64));
     let mut surface = Surface::new(WIDTH, HEIGHT);
     surface.init();
+    if let Some(footprint) = config.footprint.as_ref() {
+        surface.shapes.push(Shape {
+            name: String::from("footprint"),
+            polygon: footprint.polygon(),
+            color: (0x80, 0x80, 0xff),
+            fill: true,
+        });
+    }
+    for polygon in &config.polygons {
+        surface.shapes.push(Shape {
+            name: polygon.name.clone(),
+            polygon: polygon.polygon(),
+            color: polygon.color.unwrap_or((0xff, 0xff, 0xff)),
+            fill: false,
+        });
+    }
+    surface.zones = config
+        .zones
+        .iter()
+        .map(|zone| Shape {
+            name: zone.name.clone(),
+            polygon: zone.polygon(),
+            color: ZONE_COLOR,
+            fill: false,
+        })
+        .collect();
+    state.zones = ZoneMonitor::new(config.zones.iter().map(|zone| zone.zone()).collect());
+    state.background_config = config.background.config();
+    state.grid_config = config.grid.config();
+    state.slam_config = config.slam.config(state.grid_config);
+    let comparison = match args.compare.as_slice() {
+        [] => None,
+        [a] => Some(Comparison::against_live(a)),
+        [a, b, ..] => Some(Comparison::from_captures(a, b)),
+    };
+    match comparison.transpose() {
+        Ok(comparison) => surface.compare = comparison,
+        Err(err) => {
+            println!("[compare] unable to load captures, {err}");
+            return;
+        }
+    }
+    if let Some(path) = args.map.as_ref() {
+        match map::load(path) {
+            Ok(grid) => {
+                println!(
+                    "[map] loaded {}, {}x{} cells of {} mm",
+                    path.display(),
+                    grid.width(),
+                    grid.height(),
+                    grid.config.resolution
+                );
+                surface.map = Some(grid);
+            }
+            Err(err) => {
+                println!("[map] unable to load {}, {err}", path.display());
+                return;
+            }
+        }
+    }
     state.surface = Some(surface);
 
-    let proxy = event_loop.create_proxy();
-    let _receive_thread_handle = thread::Builder::new()
-        .name(String::from("lidar"))
-        .spawn(move || write_to_surface(proxy))
-        .expect("[lidar] listen thread failed!");
-
-    let _runtime = event_loop.run_app(&mut state);
-}
-
-#[derive(Debug)]
-struct Point {
-    angle: f32,
-    distance: u32,
-    confidence: u8,
-}
-
-fn parse(packet: Packet) -> Vec<Point> {
-    let start_angle = packet.start_angle as f32 / 100.0;
-    let end_angle = packet.end_angle as f32 / 100.0;
-    // this does *something*, i think it has to do with angle rollovers? its in the
-    // c++ lib so im including it
-    let diff = (end_angle + 36000.0 - start_angle) % 36000.0;
-    let step = diff / packet.data.len() as f32;
-    let points = packet
-        .data
+    // no sensors configured means one sensor on --port, mounted at the origin
+    let sensors = if config.sensors.is_empty() {
+        vec![config::Sensor::default()]
+    } else {
+        config.sensors
+    };
+    let safety_fields = config.safety.fields();
+    state.surface.as_mut().unwrap().safety_fields = safety_fields
+        .iter()
+        .flat_map(|field| field.outline().into_iter().map(|p| (field.level, p)))
+        .collect();
+    let mountings: Vec<Extrinsics> = sensors.iter().map(|s| s.extrinsics()).collect();
+    let mut sources: Vec<SensorSource> = sensors
         .iter()
         .enumerate()
-        .map(|(i, d)| {
-            let angle = start_angle + (step * i as f32);
-            Point {
-                angle: if angle > 360.0 { angle - 360.0 } else { angle },
-                distance: d.distance as u32,
-                confidence: d.intensity,
+        .map(|(i, sensor)| {
+            let name = sensor.name.clone().unwrap_or_else(|| format!("lidar{i}"));
+            let capture = args.capture.as_ref().map(|path| {
+                if sensors.len() > 1 {
+                    capture_path(path, &name)
+                } else {
+                    path.clone()
+                }
+            });
+            SensorSource {
+                index: i as u8,
+                port: sensor.port.clone().unwrap_or_else(|| args.port.clone()),
+                color: sensor
+                    .color
+                    .unwrap_or(SENSOR_COLORS[i % SENSOR_COLORS.len()]),
+                extrinsics: sensor.extrinsics(),
+                capture,
+                footprint: config
+                    .footprint
+                    .as_ref()
+                    .filter(|footprint| footprint.filter)
+                    .map(|footprint| FootprintFilter::new(footprint.polygon())),
+                safety: (!safety_fields.is_empty())
+                    .then(|| SafetyMonitor::new(config.safety.config(), safety_fields.clone())),
+                exit_on: args.exit_on,
+                crosstalk: config.crosstalk.as_ref().map(|crosstalk| {
+                    CrosstalkFilter::new(crosstalk.config(), sensor.extrinsics(), &mountings)
+                }),
+                name,
             }
         })
         .collect();
-    points
-}
+    state.temporal_config = config.temporal.config();
+    state.icp_config = config.icp.config();
+    // the simulator only does one sensor
+    if args.simulate {
+        sources.truncate(1);
+        state.simulated = true;
+    }
+    state.set_sensors(
+        sources
+            .iter()
+            .map(|s| (s.name.clone(), s.extrinsics))
+            .collect(),
+    );
 
-fn polar_to_cartesian(distance: u32, theta: f32) -> (f32, f32) {
-    (
-        distance as f32 * f32::cos(theta.to_radians()),
-        distance as f32 * f32::sin(theta.to_radians()),
-    )
+    if let Some(compare) = state.surface.as_mut().unwrap().compare.as_mut() {
+        if args.compare.len() == 1 {
+            let Some(source) = sources.get(args.compare_sensor as usize) else {
+                println!(
+                    "[compare] no sensor {}, there are only {}",
+                    args.compare_sensor,
+                    sources.len()
+                );
+                return;
+            };
+            compare.sensor = source.index;
+            compare.b_name = format!("live {}", source.name);
+        }
+        println!("[compare] {} against {}", compare.a_name, compare.b_name);
+    }
+
+    if args.simulate {
+        let proxy = event_loop.create_proxy();
+        let source = sources.remove(0);
+        let _sim_thread_handle = thread::Builder::new()
+            .name(String::from("lidar-sim"))
+            .spawn(move || write_to_surface_sim(proxy, source))
+            .expect("[sim] simulator thread failed!");
+    } else if args.wants_live() {
+        for source in sources {
+            let proxy = event_loop.create_proxy();
+            let _receive_thread_handle = thread::Builder::new()
+                .name(format!("lidar-{}", source.name))
+                .spawn(move || write_to_surface(proxy, source))
+                .expect("[lidar] listen thread failed!");
+        }
+    }
+
+    let _runtime = event_loop.run_app(&mut state);
+    // everything's been shut down properly by now, so the status is all
+    // that's left
+    if let Some(code) = state.exit_code {
+        std::process::exit(code);
+    }
 }
 
-fn write_to_surface(event_loop: EventLoopProxy<UserEvent>) {
+fn write_to_surface(event_loop: EventLoopProxy<UserEvent>, mut source: SensorSource) {
     let rt = Runtime::new().expect("uh oh");
 
     // Spawn the root task
     rt.block_on(async {
-        println!("starting serial read...");
-        let serial_builder = tokio_serial::new("/dev/tty.usbserial-0001", 230_400)
+        println!(
+            "[{}] starting serial read on {}...",
+            source.name, source.port
+        );
+        let serial_builder = tokio_serial::new(&source.port, 230_400)
             .data_bits(tokio_serial::DataBits::Eight)
             .stop_bits(tokio_serial::StopBits::One)
             .parity(tokio_serial::Parity::None)
@@ -94,29 +288,82 @@ fn write_to_surface(event_loop: EventLoopProxy<UserEvent>) {
             .read_data_set_ready()
             .expect("unable to set serial port read data ready");
 
-        let mut reader = ld19::decoder::LidarCodec.framed(serial);
-        println!("beginning await for sensor data...");
-        while let Some(packet) = reader.next().await {
-            let points = parse(packet.expect("bad packet!"));
-            //println!("received data: {:?}", points);
+        let mut capture = source.capture.as_ref().map(|path| {
+            println!("[{}] capturing packets to {}", source.name, path.display());
+            CaptureWriter::create(path).expect("unable to create capture file")
+        });
 
-            let draw_points: Vec<DrawPoint> = points
-                .iter()
-                .map(|p| {
-                    let (x, y) = polar_to_cartesian(p.distance / 1, p.angle);
-                    let confidence = p.confidence as f32 / 200.0;
-                    let green = (255.0 * confidence) as u8;
-                    let red = 255 - green;
-                    //println!("drawing at: {}, {}", x, y);
-                    DrawPoint {
-                        x: x as f32,
-                        y: y as f32,
-                        r: red,
-                        g: green,
-                        b: 0x00,
+        // the inspect codec hands back bad packets and resync gaps as well,
+        // so they can be shown in the packet inspector
+        let mut reader = InspectCodec::default().framed(serial);
+        println!("[{}] beginning await for sensor data...", source.name);
+        let mut assembler = ScanAssembler::new(source.index, source.extrinsics);
+        // the safety monitor has to hear about it when the sensor goes quiet,
+        // so don't wait on it forever
+        let poll = source
+            .safety
+            .as_ref()
+            .map_or(Duration::MAX, |monitor| monitor.config.timeout / 2);
+        loop {
+            let frame = match tokio::time::timeout(poll, reader.next()).await {
+                Ok(Some(frame)) => frame.expect("bad packet!"),
+                Ok(None) => break,
+                Err(_) => {
+                    let monitor = source.safety.as_mut().unwrap();
+                    if let Some(level) = monitor.check(Instant::now()) {
+                        println!("[safety] {} has gone quiet", source.name);
+                        if report_safety(&event_loop, &source, capture.as_mut(), level) {
+                            break;
+                        }
+                    }
+                    continue;
+                }
+            };
+            let _ = event_loop.send_event(UserEvent::Frame(source.index, frame.clone()));
+            let Frame::Packet { packet, .. } = frame else {
+                continue;
+            };
+            if let Some(writer) = capture.as_mut() {
+                if let Err(err) = writer.write(&packet) {
+                    println!("[capture] unable to write packet, {err}");
+                }
+            }
+            // points are in the robot frame, so everything downstream agrees
+            // on where things are
+            let mut points = assembler.convert(&packet);
+            if let Some(filter) = source.crosstalk.as_mut() {
+                filter.apply(assembler.revolution_of(&packet), &mut points);
+            }
+            if let Some(filter) = source.footprint.as_ref() {
+                filter.apply(&mut points);
+            }
+            // every packet, not every revolution, so stops happen as soon as
+            // the beam finds something
+            if let Some(monitor) = source.safety.as_mut() {
+                if let Some(level) = monitor.push(&points, Instant::now()) {
+                    if report_safety(&event_loop, &source, capture.as_mut(), level) {
+                        break;
+                    }
+                }
+            }
+            if let Some(scan) = assembler.push(&packet, points.clone()) {
+                // the thread never ends on its own, it's stopped along with
+                // the viewer, so don't leave more than a revolution buffered
+                if let Some(writer) = capture.as_mut() {
+                    if let Err(err) = writer.flush() {
+                        println!("[capture] unable to write packets, {err}");
                     }
-                })
-                .collect();
+                }
+                let _ = event_loop.send_event(UserEvent::Scan(scan));
+                if let Some(filter) = source.crosstalk.as_ref() {
+                    let stats = filter.stats;
+                    let _ = event_loop.send_event(UserEvent::Crosstalk(source.index, stats));
+                }
+            }
+            let revolution = assembler.revolution();
+            //println!("received data: {:?}", points);
+
+            let draw_points = to_draw_points(&points, source.color, revolution);
 
             // write to buffer/send event
             //print!(".");
@@ -127,6 +374,71 @@ fn write_to_surface(event_loop: EventLoopProxy<UserEvent>) {
     })
 }
 
+// tell the viewer about a new safety state. true if it's bad enough to stop,
+// in which case the capture's been written out and the viewer told to exit
+fn report_safety(
+    event_loop: &EventLoopProxy<UserEvent>,
+    source: &SensorSource,
+    capture: Option<&mut CaptureWriter>,
+    level: FieldLevel,
+) -> bool {
+    let _ = event_loop.send_event(UserEvent::Safety(source.index, level));
+    if source.exit_on.is_none_or(|exit_on| level < exit_on) {
+        return false;
+    }
+    if let Some(writer) = capture {
+        if let Err(err) = writer.flush() {
+            println!("[capture] unable to write packets, {err}");
+        }
+    }
+    let _ = event_loop.send_event(UserEvent::Exit(source.index, level));
+    true
+}
+
+fn to_draw_points(points: &[ScanPoint], color: (u8, u8, u8), revolution: u32) -> Vec<DrawPoint> {
+    let (r, g, b) = color;
+    points
+        .iter()
+        .map(|p| DrawPoint {
+            x: p.x,
+            y: p.y,
+            r,
+            g,
+            b,
+            angle: p.angle,
+            distance: p.distance,
+            intensity: p.intensity,
+            revolution,
+            sensor: p.sensor,
+        })
+        .collect()
+}
+
+// stands in for write_to_surface with a simulated sensor driving laps of
+// sim::Scene::room at 10 Hz. the viewer gets where it really was as well, so
+// odometry and slam can be checked against it
+fn write_to_surface_sim(event_loop: EventLoopProxy<UserEvent>, source: SensorSource) {
+    println!("[{}] simulating laps of a room", source.name);
+    let mut sim = Simulator::new(
+        Scene::room(),
+        SimConfig {
+            extrinsics: source.extrinsics,
+            ..SimConfig::default()
+        },
+    );
+    let start = Instant::now();
+    loop {
+        let seconds = start.elapsed().as_secs_f32();
+        let truth = sim::lap(seconds);
+        let scan = sim.scan(truth, (seconds * 1000.0) as u64);
+        let draw_points = to_draw_points(&scan.points, source.color, scan.revolution);
+        let _ = event_loop.send_event(UserEvent::GroundTruth(truth));
+        let _ = event_loop.send_event(UserEvent::Scan(scan));
+        let _ = event_loop.send_event(UserEvent::DrawPointBuffer(draw_points));
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
 // like write_to_surface but doesnt rely on a serial device. good for testing
 fn _write_to_surface_dummy(event_loop: EventLoopProxy<UserEvent>) {
     //println!("running dummy thread!");
@@ -145,6 +457,11 @@ fn _write_to_surface_dummy(event_loop: EventLoopProxy<UserEvent>) {
             r: 0xFF,
             g: 0x00,
             b: 0x00,
+            angle: 0.0,
+            distance: 0,
+            intensity: 0,
+            revolution: 0,
+            sensor: 0,
         };
 
         counter += 1;
@@ -0,0 +1,351 @@
+// maps in the format ros map_server reads and writes: a pgm image with one
+// pixel per cell, and a yaml file next to it saying how big a cell is and
+// where the image sits in the world. ros works in meters and radians,
+// everything here is millimeters
+use crate::grid::{GridConfig, OccupancyGrid, FREE_THRESHOLD, OCCUPIED_THRESHOLD};
+use core::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// biggest image read_pgm will take, 4096 x 4096 pixels. a grid of it is
+// already a couple hundred megabytes
+pub const MAX_PIXELS: usize = 1 << 24;
+
+#[derive(Debug)]
+pub enum MapError {
+    Io(io::Error),
+    Yaml(String),
+    Pgm(String),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::Io(e) => write!(f, "{}", e),
+            MapError::Yaml(e) => write!(f, "bad map yaml, {}", e),
+            MapError::Pgm(e) => write!(f, "bad map image, {}", e),
+        }
+    }
+}
+
+impl From<io::Error> for MapError {
+    fn from(e: io::Error) -> MapError {
+        MapError::Io(e)
+    }
+}
+
+impl std::error::Error for MapError {}
+
+/// how the image's grey levels turn into occupancy
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MapMode {
+    // occupied, free or unknown by the thresholds
+    #[default]
+    Trinary,
+    // like trinary, but anything between the thresholds keeps its probability
+    Scale,
+    // the pixel is the occupancy in percent, anything over 100 is unknown
+    Raw,
+}
+
+/// what's in the yaml file, units as ros has them
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapMetadata {
+    // relative to the yaml file unless it's absolute
+    pub image: PathBuf,
+    pub mode: MapMode,
+    // meters per pixel
+    pub resolution: f32,
+    // world position of the bottom left pixel, meters, and the map's
+    // rotation, radians
+    pub origin: (f32, f32, f32),
+    // white is occupied instead of black
+    pub negate: bool,
+    pub occupied_thresh: f32,
+    pub free_thresh: f32,
+}
+
+impl MapMetadata {
+    /// map_server yaml is flat `key: value` pairs, nothing more is needed to
+    /// read it
+    pub fn parse(text: &str) -> Result<Self, MapError> {
+        let mut image = None;
+        let mut resolution = None;
+        let mut origin = None;
+        let mut metadata = MapMetadata {
+            image: PathBuf::new(),
+            mode: MapMode::default(),
+            resolution: 0.0,
+            origin: (0.0, 0.0, 0.0),
+            negate: false,
+            occupied_thresh: OCCUPIED_THRESHOLD,
+            free_thresh: FREE_THRESHOLD,
+        };
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+            match key.trim() {
+                "image" => image = Some(PathBuf::from(value)),
+                "mode" => {
+                    metadata.mode = match value {
+                        "trinary" => MapMode::Trinary,
+                        "scale" => MapMode::Scale,
+                        "raw" => MapMode::Raw,
+                        other => return Err(MapError::Yaml(format!("unknown mode {other}"))),
+                    }
+                }
+                "resolution" => resolution = Some(number(key, value)?),
+                "origin" => {
+                    let values: Vec<f32> = value
+                        .trim_start_matches('[')
+                        .trim_end_matches(']')
+                        .split(',')
+                        .map(|v| number(key, v.trim()))
+                        .collect::<Result<_, _>>()?;
+                    match values[..] {
+                        [x, y, yaw] => origin = Some((x, y, yaw)),
+                        _ => return Err(MapError::Yaml(String::from("origin needs x, y, yaw"))),
+                    }
+                }
+                "negate" => metadata.negate = number(key, value)? != 0.0,
+                "occupied_thresh" => metadata.occupied_thresh = number(key, value)?,
+                "free_thresh" => metadata.free_thresh = number(key, value)?,
+                _ => (),
+            }
+        }
+        let missing = |key: &str| MapError::Yaml(format!("missing {key}"));
+        metadata.image = image.ok_or_else(|| missing("image"))?;
+        metadata.resolution = resolution.ok_or_else(|| missing("resolution"))?;
+        metadata.origin = origin.ok_or_else(|| missing("origin"))?;
+        if metadata.resolution <= 0.0 {
+            return Err(MapError::Yaml(String::from(
+                "resolution has to be positive",
+            )));
+        }
+        Ok(metadata)
+    }
+
+    pub fn to_yaml(&self) -> String {
+        let mode = match self.mode {
+            MapMode::Trinary => "trinary",
+            MapMode::Scale => "scale",
+            MapMode::Raw => "raw",
+        };
+        format!(
+            "image: {}\nmode: {}\nresolution: {}\norigin: [{}, {}, {}]\nnegate: {}\n\
+             occupied_thresh: {}\nfree_thresh: {}\n",
+            self.image.display(),
+            mode,
+            self.resolution,
+            self.origin.0,
+            self.origin.1,
+            self.origin.2,
+            self.negate as u8,
+            self.occupied_thresh,
+            self.free_thresh,
+        )
+    }
+
+    /// probability of occupancy for a pixel, None for unknown
+    fn occupancy(&self, value: u8) -> Option<f32> {
+        if self.mode == MapMode::Raw {
+            return (value <= 100).then_some(value as f32 / 100.0);
+        }
+        let p = if self.negate {
+            value as f32 / 255.0
+        } else {
+            (255 - value) as f32 / 255.0
+        };
+        if p > self.occupied_thresh {
+            Some(1.0)
+        } else if p < self.free_thresh {
+            Some(0.0)
+        } else if self.mode == MapMode::Scale {
+            Some(p)
+        } else {
+            None
+        }
+    }
+}
+
+fn number(key: &str, value: &str) -> Result<f32, MapError> {
+    value
+        .parse()
+        .map_err(|_| MapError::Yaml(format!("{} isn't a number: {value}", key.trim())))
+}
+
+/// write the grid as map.yaml and map.pgm side by side, the pgm named after
+/// the yaml
+pub fn save(grid: &OccupancyGrid, yaml: &Path) -> Result<(), MapError> {
+    let pgm = yaml.with_extension("pgm");
+    write_pgm(&pgm, grid.width(), grid.height(), &grid.image())?;
+    let metadata = MapMetadata {
+        image: PathBuf::from(pgm.file_name().unwrap_or_default()),
+        mode: MapMode::Trinary,
+        resolution: grid.config.resolution / 1000.0,
+        origin: (grid.config.min.0 / 1000.0, grid.config.min.1 / 1000.0, 0.0),
+        negate: false,
+        occupied_thresh: OCCUPIED_THRESHOLD,
+        free_thresh: FREE_THRESHOLD,
+    };
+    fs::write(yaml, metadata.to_yaml())?;
+    Ok(())
+}
+
+/// read a map_server map. known cells are loaded as sure as the grid allows
+/// so live scans take a while to change them. the grid can't be rotated, so
+/// a map whose origin has a yaw is refused rather than put in the wrong place
+pub fn load(yaml: &Path) -> Result<OccupancyGrid, MapError> {
+    let metadata = MapMetadata::parse(&fs::read_to_string(yaml)?)?;
+    if metadata.origin.2 != 0.0 {
+        return Err(MapError::Yaml(format!(
+            "origin yaw is {}, only unrotated maps are supported",
+            metadata.origin.2
+        )));
+    }
+    let image = yaml.parent().unwrap_or(Path::new("")).join(&metadata.image);
+    let (width, height, pixels) = read_pgm(&fs::read(image)?)?;
+
+    let resolution = metadata.resolution * 1000.0;
+    let min = (metadata.origin.0 * 1000.0, metadata.origin.1 * 1000.0);
+    let mut grid = OccupancyGrid::new(GridConfig {
+        resolution,
+        min,
+        max: (
+            min.0 + width as f32 * resolution,
+            min.1 + height as f32 * resolution,
+        ),
+        ..GridConfig::default()
+    });
+    let limit = grid.config.limit;
+    for (i, value) in pixels.iter().enumerate() {
+        // the image's first row is the top of the map
+        let (col, row) = (i % width, height - 1 - i / width);
+        let log_odds = match metadata.occupancy(*value) {
+            None => 0.0,
+            Some(p) if p >= 1.0 => limit,
+            Some(p) if p <= 0.0 => -limit,
+            Some(p) => (p / (1.0 - p)).ln(),
+        };
+        grid.set_log_odds(col, row, log_odds);
+    }
+    Ok(grid)
+}
+
+/// binary 8 bit pgm, rows top to bottom
+pub fn write_pgm(path: &Path, width: usize, height: usize, pixels: &[u8]) -> io::Result<()> {
+    let mut data = format!("P5\n{width} {height}\n255\n").into_bytes();
+    data.extend_from_slice(pixels);
+    fs::write(path, data)
+}
+
+/// binary or ascii pgm, 8 or 16 bit. returns the width, height and the
+/// pixels scaled to 8 bits, rows top to bottom
+pub fn read_pgm(data: &[u8]) -> Result<(usize, usize, Vec<u8>), MapError> {
+    let bad = |e: &str| MapError::Pgm(String::from(e));
+    let mut pos = 0;
+    let mut header = Vec::new();
+    // magic, width, height and maxval, with # comments allowed in between
+    while header.len() < 4 {
+        match data.get(pos) {
+            None => return Err(bad("header cut short")),
+            Some(b'#') => {
+                while data.get(pos).is_some_and(|c| *c != b'\n') {
+                    pos += 1;
+                }
+            }
+            Some(c) if c.is_ascii_whitespace() => pos += 1,
+            Some(_) => {
+                let start = pos;
+                while data.get(pos).is_some_and(|c| !c.is_ascii_whitespace()) {
+                    pos += 1;
+                }
+                header.push(String::from_utf8_lossy(&data[start..pos]).into_owned());
+            }
+        }
+    }
+    let field = |i: usize| header[i].parse::<usize>().map_err(|_| bad("bad header"));
+    let (width, height, maxval) = (field(1)?, field(2)?, field(3)?);
+    if maxval == 0 || maxval > 65535 {
+        return Err(bad("bad maxval"));
+    }
+    let count = width
+        .checked_mul(height)
+        .filter(|count| *count <= MAX_PIXELS)
+        .ok_or_else(|| bad("image too big"))?;
+    let scale = |v: usize| (v.min(maxval) * 255 / maxval) as u8;
+
+    let pixels: Vec<u8> = match header[0].as_str() {
+        "P5" => {
+            // exactly one whitespace byte between the header and the pixels
+            let body = data.get(pos + 1..).unwrap_or_default();
+            if maxval < 256 {
+                body.iter()
+                    .take(count)
+                    .map(|v| scale(*v as usize))
+                    .collect()
+            } else {
+                body.chunks_exact(2)
+                    .take(count)
+                    .map(|v| scale(u16::from_be_bytes([v[0], v[1]]) as usize))
+                    .collect()
+            }
+        }
+        "P2" => String::from_utf8_lossy(&data[pos..])
+            .split_ascii_whitespace()
+            .take(count)
+            .map(|v| v.parse().map(scale).map_err(|_| bad("bad pixel")))
+            .collect::<Result<_, _>>()?,
+        _ => return Err(bad("not a greyscale pgm")),
+    };
+    if pixels.len() < count {
+        return Err(bad("pixels cut short"));
+    }
+    Ok((width, height, pixels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_binary_and_ascii() {
+        let binary = b"P5\n# made by hand\n3 2\n255\n\x00\x80\xff\x01\x02\x03";
+        assert_eq!(
+            read_pgm(binary).unwrap(),
+            (3, 2, vec![0, 128, 255, 1, 2, 3])
+        );
+        let ascii = b"P2 2 1 15 0 15";
+        assert_eq!(read_pgm(ascii).unwrap(), (2, 1, vec![0, 255]));
+    }
+
+    #[test]
+    fn refuses_huge_images() {
+        for header in [
+            "P5\n100000 100000\n255\n",
+            "P5\n18446744073709551615 2\n255\n",
+        ] {
+            assert!(matches!(read_pgm(header.as_bytes()), Err(MapError::Pgm(_))));
+        }
+    }
+
+    #[test]
+    fn refuses_rotated_maps() {
+        let dir = std::env::temp_dir().join(format!("lidar-map-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        write_pgm(&dir.join("map.pgm"), 2, 2, &[0, 254, 205, 0]).unwrap();
+        let yaml = dir.join("map.yaml");
+        let metadata =
+            |yaw| format!("image: map.pgm\nresolution: 0.05\norigin: [-1.0, 2.0, {yaw}]\n");
+        fs::write(&yaml, metadata(0.0)).unwrap();
+        let grid = load(&yaml).unwrap();
+        assert_eq!((grid.width(), grid.height()), (2, 2));
+        fs::write(&yaml, metadata(0.5)).unwrap();
+        assert!(matches!(load(&yaml), Err(MapError::Yaml(_))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,82 @@
+// slam on a thread of its own. matching, loop closures and drawing the map
+// again after one all take a while, and none of that should hold up drawing
+// points. scans go in, and what came of them is picked up whenever the viewer
+// gets round to it
+use lidar::extrinsics::Extrinsics;
+use lidar::geometry::Pose;
+use lidar::grid::OccupancyGrid;
+use lidar::scan::Scan;
+use lidar::slam::{Slam, SlamConfig, SlamUpdate};
+use std::io;
+use std::sync::mpsc::{self, Receiver, SyncSender, TryIter};
+use std::thread::{self, JoinHandle};
+
+// scans waiting on the worker before new ones get dropped
+const QUEUE: usize = 4;
+
+/// what came of one scan
+pub struct MappingUpdate {
+    pub update: SlamUpdate,
+    // only for keyframes, nothing else changes any of it
+    pub snapshot: Option<MapSnapshot>,
+}
+
+pub struct MapSnapshot {
+    pub grid: OccupancyGrid,
+    // every keyframe's pose, and both ends of every loop closure
+    pub keyframes: Vec<Pose>,
+    pub loops: Vec<(Pose, Pose)>,
+}
+
+pub struct Mapper {
+    sender: Option<SyncSender<(Scan, Extrinsics)>>,
+    receiver: Receiver<MappingUpdate>,
+    worker: Option<JoinHandle<Slam>>,
+}
+
+impl Mapper {
+    pub fn start(config: SlamConfig) -> io::Result<Self> {
+        let (sender, scans) = mpsc::sync_channel::<(Scan, Extrinsics)>(QUEUE);
+        let (updates, receiver) = mpsc::channel();
+        let worker = thread::Builder::new()
+            .name(String::from("slam"))
+            .spawn(move || {
+                let mut slam = Slam::new(config);
+                while let Ok((scan, extrinsics)) = scans.recv() {
+                    let update = slam.push(&scan, &extrinsics);
+                    let snapshot = update.keyframe.then(|| MapSnapshot {
+                        grid: slam.map().clone(),
+                        keyframes: slam.keyframes().collect(),
+                        loops: slam.loops().collect(),
+                    });
+                    // the viewer's stopped listening, finish is on its way
+                    let _ = updates.send(MappingUpdate { update, snapshot });
+                }
+                slam
+            })?;
+        Ok(Self {
+            sender: Some(sender),
+            receiver,
+            worker: Some(worker),
+        })
+    }
+
+    /// hand a scan over. false if the worker's too far behind and it was
+    /// dropped
+    pub fn push(&self, scan: Scan, extrinsics: Extrinsics) -> bool {
+        let sent = self.sender.as_ref().map(|s| s.try_send((scan, extrinsics)));
+        matches!(sent, Some(Ok(())))
+    }
+
+    /// everything that's come back since last time
+    pub fn updates(&self) -> TryIter<'_, MappingUpdate> {
+        self.receiver.try_iter()
+    }
+
+    /// stop once the queued scans are done, and hand back what it made
+    pub fn finish(mut self) -> Option<Slam> {
+        // dropping the sender ends the worker's loop
+        self.sender.take();
+        self.worker.take()?.join().ok()
+    }
+}
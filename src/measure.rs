@@ -0,0 +1,94 @@
+// click-to-measure tool for the viewer. points are in robot coordinates
+// (millimeters, which is the sensor frame unless the sensor's mounting is
+// configured), along with what the sensor itself measures to them. the
+// surface takes care of converting to and from screen pixels
+use core::fmt;
+
+#[derive(Debug, Clone, Copy)]
+pub struct MeasurePoint {
+    pub x: f32,
+    pub y: f32,
+    // range in millimeters and bearing in degrees from the sensor, in its
+    // own frame. for a snapped point it's the reading itself, otherwise it's
+    // worked out for the first sensor
+    pub sensor: u8,
+    pub sensor_range: f32,
+    pub sensor_bearing: f32,
+    // true if the point was snapped onto an actual scan point
+    pub snapped: bool,
+}
+
+impl MeasurePoint {
+    /// range from the robot origin in millimeters
+    pub fn range(&self) -> f32 {
+        self.x.hypot(self.y)
+    }
+    /// bearing from the robot origin in degrees, using the same convention
+    /// as the sensor itself (0..360, increasing in the direction of rotation)
+    pub fn bearing(&self) -> f32 {
+        self.y.atan2(self.x).to_degrees().rem_euclid(360.0)
+    }
+}
+
+impl fmt::Display for MeasurePoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "range: {:.0} mm, bearing: {:.2} deg from sensor {} \
+             ({:.0} mm, {:.2} deg from the robot origin){}",
+            self.sensor_range,
+            self.sensor_bearing,
+            self.sensor,
+            self.range(),
+            self.bearing(),
+            if self.snapped { " (snapped)" } else { "" },
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Measure {
+    pub active: bool,
+    pub points: Vec<MeasurePoint>,
+}
+
+impl Measure {
+    pub fn toggle(&mut self) {
+        self.active = !self.active;
+        self.points.clear();
+    }
+
+    /// add a point to the measurement. a third click starts a new one
+    pub fn push(&mut self, point: MeasurePoint) {
+        if self.points.len() >= 2 {
+            self.points.clear();
+        }
+        self.points.push(point);
+    }
+
+    /// distance between the two measured points in millimeters, once both
+    /// have been placed
+    pub fn distance(&self) -> Option<f32> {
+        match self.points.as_slice() {
+            [a, b] => Some((b.x - a.x).hypot(b.y - a.y)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Measure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, point) in self.points.iter().enumerate() {
+            writeln!(f, "point {}: {}", i + 1, point)?;
+        }
+        if let Some(distance) = self.distance() {
+            write!(
+                f,
+                "distance: {:.0} mm ({:.3} m)",
+                distance,
+                distance / 1000.0
+            )?;
+        }
+        Ok(())
+    }
+}
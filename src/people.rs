@@ -0,0 +1,407 @@
+// finding people by their legs. at shin height a leg comes back as a short
+// arc, so fit a circle to every cluster, keep the ones that are leg sized,
+// pair up legs that are close enough to belong to the same person and then
+// follow those people from one revolution to the next
+use crate::cluster::Cluster;
+use crate::scan::ScanPoint;
+
+#[derive(Debug, Clone, Copy)]
+pub struct LegConfig {
+    // fitted radius range for a leg, millimeters
+    pub min_radius: f32,
+    pub max_radius: f32,
+    // a leg up close has plenty of points, far away only a couple
+    pub min_points: usize,
+    pub max_points: usize,
+    // rms distance of the points from the circle, millimeters
+    pub max_residual: f32,
+    // two legs farther apart than this aren't the same person, millimeters
+    pub max_leg_gap: f32,
+}
+
+impl Default for LegConfig {
+    fn default() -> Self {
+        Self {
+            min_radius: 25.0,
+            max_radius: 120.0,
+            min_points: 3,
+            max_points: 40,
+            max_residual: 20.0,
+            max_leg_gap: 450.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Circle {
+    // robot frame millimeters
+    pub center: (f32, f32),
+    pub radius: f32,
+    // rms distance of the points from the circle
+    pub residual: f32,
+}
+
+/// least squares circle through some points (kasa's method). None if there
+/// aren't enough points or they're in a straight line
+pub fn fit_circle(points: &[ScanPoint]) -> Option<Circle> {
+    if points.len() < 3 {
+        return None;
+    }
+    // centered on the mean to keep the sums small
+    let n = points.len() as f32;
+    let mx = points.iter().map(|p| p.x).sum::<f32>() / n;
+    let my = points.iter().map(|p| p.y).sum::<f32>() / n;
+    let (mut suu, mut svv, mut suv, mut suuu, mut svvv, mut suvv, mut svuu) =
+        (0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0);
+    for p in points {
+        let (u, v) = (p.x - mx, p.y - my);
+        suu += u * u;
+        svv += v * v;
+        suv += u * v;
+        suuu += u * u * u;
+        svvv += v * v * v;
+        suvv += u * v * v;
+        svuu += v * u * u;
+    }
+    let det = suu * svv - suv * suv;
+    if det.abs() < f32::EPSILON {
+        return None;
+    }
+    let a = 0.5 * (suuu + suvv);
+    let b = 0.5 * (svvv + svuu);
+    let uc = (a * svv - b * suv) / det;
+    let vc = (b * suu - a * suv) / det;
+    let radius = (uc * uc + vc * vc + (suu + svv) / n).sqrt();
+    let center = (uc + mx, vc + my);
+    let squares: f32 = points
+        .iter()
+        .map(|p| ((p.x - center.0).hypot(p.y - center.1) - radius).powi(2))
+        .sum();
+    Some(Circle {
+        center,
+        radius,
+        residual: (squares / n).sqrt(),
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Leg {
+    pub circle: Circle,
+    pub points: usize,
+}
+
+/// keep the clusters that look like legs
+pub fn detect_legs(clusters: &[Cluster], config: &LegConfig) -> Vec<Leg> {
+    clusters
+        .iter()
+        .filter(|c| (config.min_points..=config.max_points).contains(&c.points.len()))
+        .filter_map(|c| {
+            let circle = fit_circle(&c.points)?;
+            // the sensor sees the near side of a leg, so the center has to
+            // be behind the points. a concave arc like a corner fits a
+            // circle too but with the center in front
+            let center = circle.center.0.hypot(circle.center.1);
+            let near = c.centroid.0.hypot(c.centroid.1);
+            let leg = (config.min_radius..=config.max_radius).contains(&circle.radius)
+                && circle.residual <= config.max_residual
+                && center > near;
+            leg.then_some(Leg {
+                circle,
+                points: c.points.len(),
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct Person {
+    // between the legs, robot frame millimeters
+    pub position: (f32, f32),
+    // one when the other leg is hidden behind it
+    pub legs: Vec<Leg>,
+}
+
+/// pair legs into people, closest pairs first. a leg without a partner
+/// is still a person, just one standing side on or mid stride
+pub fn pair_legs(legs: &[Leg], config: &LegConfig) -> Vec<Person> {
+    let mut pairs: Vec<(f32, usize, usize)> = Vec::new();
+    for (i, a) in legs.iter().enumerate() {
+        for (j, b) in legs.iter().enumerate().skip(i + 1) {
+            let gap = (a.circle.center.0 - b.circle.center.0)
+                .hypot(a.circle.center.1 - b.circle.center.1);
+            if gap <= config.max_leg_gap {
+                pairs.push((gap, i, j));
+            }
+        }
+    }
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut used = vec![false; legs.len()];
+    let mut people = Vec::new();
+    for (_, i, j) in pairs {
+        if used[i] || used[j] {
+            continue;
+        }
+        used[i] = true;
+        used[j] = true;
+        let (a, b) = (legs[i].circle.center, legs[j].circle.center);
+        people.push(Person {
+            position: ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0),
+            legs: vec![legs[i], legs[j]],
+        });
+    }
+    for (leg, _) in legs.iter().zip(used).filter(|(_, used)| !used) {
+        people.push(Person {
+            position: leg.circle.center,
+            legs: vec![*leg],
+        });
+    }
+    people
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TrackerConfig {
+    // farthest a person can move between updates and keep their id,
+    // millimeters
+    pub gate: f32,
+    // how many updates a track has to be seen in before it's confirmed, and
+    // how many in a row it can go unseen before it's dropped
+    pub min_hits: u32,
+    pub max_misses: u32,
+    // how much of a new position goes into the smoothed one, 0..1
+    pub smoothing: f32,
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        Self {
+            gate: 500.0,
+            min_hits: 3,
+            max_misses: 5,
+            smoothing: 0.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PersonTrack {
+    pub id: u32,
+    // smoothed, robot frame millimeters
+    pub position: (f32, f32),
+    pub legs: usize,
+    pub hits: u32,
+    pub misses: u32,
+}
+
+impl PersonTrack {
+    /// seen often enough to not be a passing bit of noise
+    pub fn confirmed(&self, config: &TrackerConfig) -> bool {
+        self.hits >= config.min_hits
+    }
+}
+
+/// nearest neighbour tracking that keeps ids stable between revolutions
+#[derive(Debug, Clone, Default)]
+pub struct PeopleTracker {
+    pub config: TrackerConfig,
+    tracks: Vec<PersonTrack>,
+    next_id: u32,
+}
+
+impl PeopleTracker {
+    pub fn new(config: TrackerConfig) -> Self {
+        Self {
+            config,
+            tracks: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    /// every track, confirmed or not
+    pub fn tracks(&self) -> &[PersonTrack] {
+        &self.tracks
+    }
+
+    pub fn confirmed(&self) -> impl Iterator<Item = &PersonTrack> {
+        self.tracks.iter().filter(|t| t.confirmed(&self.config))
+    }
+
+    /// match this revolution's people to the tracks, closest first
+    pub fn update(&mut self, people: &[Person]) {
+        let mut pairs: Vec<(f32, usize, usize)> = Vec::new();
+        for (t, track) in self.tracks.iter().enumerate() {
+            for (p, person) in people.iter().enumerate() {
+                let distance = (track.position.0 - person.position.0)
+                    .hypot(track.position.1 - person.position.1);
+                if distance <= self.config.gate {
+                    pairs.push((distance, t, p));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut track_matched = vec![false; self.tracks.len()];
+        let mut person_matched = vec![false; people.len()];
+        let alpha = self.config.smoothing.clamp(0.0, 1.0);
+        for (_, t, p) in pairs {
+            if track_matched[t] || person_matched[p] {
+                continue;
+            }
+            track_matched[t] = true;
+            person_matched[p] = true;
+            let track = &mut self.tracks[t];
+            let seen = people[p].position;
+            track.position = (
+                track.position.0 + alpha * (seen.0 - track.position.0),
+                track.position.1 + alpha * (seen.1 - track.position.1),
+            );
+            track.legs = people[p].legs.len();
+            track.hits += 1;
+            track.misses = 0;
+        }
+
+        for (track, matched) in self.tracks.iter_mut().zip(&track_matched) {
+            if !matched {
+                track.misses += 1;
+            }
+        }
+        let max_misses = self.config.max_misses;
+        self.tracks.retain(|t| t.misses <= max_misses);
+
+        for (person, _) in people.iter().zip(person_matched).filter(|(_, m)| !m) {
+            self.tracks.push(PersonTrack {
+                id: self.next_id,
+                position: person.position,
+                legs: person.legs.len(),
+                hits: 1,
+                misses: 0,
+            });
+            self.next_id = self.next_id.wrapping_add(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // points around a circle every 10 degrees from..to, counterclockwise
+    fn arc(center: (f32, f32), radius: f32, from: i32, to: i32) -> Vec<ScanPoint> {
+        (from..=to)
+            .step_by(10)
+            .map(|a| {
+                let (sin, cos) = (a as f32).to_radians().sin_cos();
+                let (x, y) = (center.0 + radius * cos, center.1 + radius * sin);
+                ScanPoint {
+                    x,
+                    y,
+                    angle: y.atan2(x).to_degrees(),
+                    distance: x.hypot(y) as u16,
+                    intensity: 100,
+                    sensor: 0,
+                }
+            })
+            .collect()
+    }
+
+    fn leg(x: f32, y: f32) -> Leg {
+        Leg {
+            circle: Circle {
+                center: (x, y),
+                radius: 60.0,
+                residual: 0.0,
+            },
+            points: 8,
+        }
+    }
+
+    fn person(x: f32, y: f32) -> Person {
+        Person {
+            position: (x, y),
+            legs: vec![leg(x, y)],
+        }
+    }
+
+    #[test]
+    fn fits_a_circle_to_an_arc() {
+        let circle = fit_circle(&arc((1500.0, 200.0), 60.0, 130, 230)).unwrap();
+        assert!((circle.center.0 - 1500.0).abs() < 0.5, "{circle:?}");
+        assert!((circle.center.1 - 200.0).abs() < 0.5, "{circle:?}");
+        assert!((circle.radius - 60.0).abs() < 0.5, "{circle:?}");
+        assert!(circle.residual < 0.1);
+    }
+
+    #[test]
+    fn no_circle_through_a_line_or_two_points() {
+        let line: Vec<ScanPoint> = arc((0.0, 0.0), 0.0, 0, 40)
+            .into_iter()
+            .enumerate()
+            .map(|(i, p)| ScanPoint {
+                x: 1000.0 + i as f32 * 20.0,
+                y: 500.0,
+                ..p
+            })
+            .collect();
+        assert!(fit_circle(&line).is_none());
+        assert!(fit_circle(&line[..2]).is_none());
+    }
+
+    #[test]
+    fn legs_curve_away_from_the_sensor() {
+        let config = LegConfig::default();
+        let clusters = [
+            // the near side of a leg
+            Cluster::new(arc((1500.0, 200.0), 60.0, 130, 230)),
+            // the far side, what the inside of a corner looks like
+            Cluster::new(arc((1500.0, -800.0), 60.0, -50, 50)),
+            // far too big
+            Cluster::new(arc((3000.0, 0.0), 500.0, 150, 210)),
+        ];
+        let legs = detect_legs(&clusters, &config);
+        assert_eq!(legs.len(), 1);
+        assert!((legs[0].circle.center.1 - 200.0).abs() < 1.0);
+        assert_eq!(legs[0].points, 11);
+    }
+
+    #[test]
+    fn pairs_legs_closest_first() {
+        let config = LegConfig::default();
+        let legs = [
+            leg(1000.0, 0.0),
+            leg(1000.0, 300.0),
+            leg(1000.0, 200.0),
+            leg(3000.0, 0.0),
+        ];
+        let people = pair_legs(&legs, &config);
+        assert_eq!(people.len(), 3);
+        // the two 100 mm apart, then whatever's left on its own
+        assert_eq!(people[0].position, (1000.0, 250.0));
+        assert_eq!(people[0].legs.len(), 2);
+        let mut alone: Vec<(f32, f32)> = people[1..].iter().map(|p| p.position).collect();
+        alone.sort_by(|a, b| a.0.total_cmp(&b.0));
+        assert_eq!(alone, vec![(1000.0, 0.0), (3000.0, 0.0)]);
+    }
+
+    #[test]
+    fn tracker_smooths_and_keeps_ids() {
+        let mut tracker = PeopleTracker::default();
+        tracker.update(&[person(0.0, 0.0)]);
+        tracker.update(&[person(100.0, 0.0)]);
+        assert_eq!(tracker.tracks().len(), 1);
+        assert_eq!(tracker.tracks()[0].position, (50.0, 0.0));
+        assert_eq!(tracker.confirmed().count(), 0);
+        tracker.update(&[person(150.0, 0.0)]);
+        let track = tracker.confirmed().next().unwrap();
+        assert_eq!((track.id, track.position), (0, (100.0, 0.0)));
+
+        // somebody new out of reach of the gate
+        tracker.update(&[person(150.0, 0.0), person(2000.0, 0.0)]);
+        let ids: Vec<u32> = tracker.tracks().iter().map(|t| t.id).collect();
+        assert_eq!(ids, vec![0, 1]);
+
+        for _ in 0..=tracker.config.max_misses {
+            tracker.update(&[]);
+        }
+        assert!(tracker.tracks().is_empty());
+    }
+}
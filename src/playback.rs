@@ -0,0 +1,222 @@
+// pausing and stepping through the live stream. acquisition never stops,
+// while paused every incoming packet and scan is queued in a backlog instead
+// of being drawn, and the step keys pull from the front of it
+use crate::window::DrawPoint;
+use lidar::scan::Scan;
+use std::collections::VecDeque;
+
+// roughly ten seconds of packets at 375 packets/s. once the backlog is full
+// the oldest packets are dropped so memory doesn't grow without bound while
+// paused
+pub const BACKLOG_LIMIT: usize = 3750;
+
+// something that came in while paused. packets and scans share one queue so
+// stepping lets them out in the order they arrived
+#[derive(Debug)]
+enum Queued {
+    Points(Vec<DrawPoint>),
+    Scan(Scan),
+}
+
+/// what a step lets out of the backlog
+#[derive(Debug, Default)]
+pub struct Step {
+    pub points: Vec<DrawPoint>,
+    // full revolutions that finished in among those points
+    pub scans: Vec<Scan>,
+}
+
+#[derive(Debug, Default)]
+pub struct Playback {
+    pub paused: bool,
+    backlog: VecDeque<Queued>,
+    // packets dropped off the front of a full backlog since pausing
+    dropped: usize,
+}
+
+impl Playback {
+    /// toggle pause. resuming throws the queued packets away and goes straight
+    /// back to live data, returns how many were skipped doing so. the queued
+    /// scans are handed back so odometry and slam don't lose their place
+    pub fn toggle(&mut self) -> (usize, Vec<Scan>) {
+        self.paused = !self.paused;
+        let skipped = self.backlog() + self.dropped;
+        let scans = self
+            .backlog
+            .drain(..)
+            .filter_map(|queued| match queued {
+                Queued::Scan(scan) => Some(scan),
+                Queued::Points(_) => None,
+            })
+            .collect();
+        self.dropped = 0;
+        if self.paused {
+            (0, scans)
+        } else {
+            (skipped, scans)
+        }
+    }
+
+    /// hand a packet to playback. returns it right back if it should be drawn
+    /// now
+    pub fn push(&mut self, points: Vec<DrawPoint>) -> Option<Vec<DrawPoint>> {
+        if !self.paused {
+            return Some(points);
+        }
+        self.queue(Queued::Points(points));
+        None
+    }
+
+    /// hand a scan to playback. returns it right back if it should be used
+    /// now
+    pub fn push_scan(&mut self, scan: Scan) -> Option<Scan> {
+        if !self.paused {
+            return Some(scan);
+        }
+        self.queue(Queued::Scan(scan));
+        None
+    }
+
+    fn queue(&mut self, queued: Queued) {
+        if self.backlog.len() >= BACKLOG_LIMIT {
+            if let Some(Queued::Points(_)) = self.backlog.pop_front() {
+                self.dropped += 1;
+            }
+        }
+        self.backlog.push_back(queued);
+    }
+
+    /// take the next packet from the backlog, along with any scans queued
+    /// ahead of it
+    pub fn step_packet(&mut self) -> Option<Step> {
+        let end = self
+            .backlog
+            .iter()
+            .position(|queued| matches!(queued, Queued::Points(_)))?;
+        Some(self.take(end + 1))
+    }
+
+    /// take packets from the backlog up to the start of the next revolution.
+    /// nothing is returned unless that revolution has fully arrived, so a
+    /// step always shows a whole revolution. with several sensors revolutions
+    /// are counted on whichever sensor is at the front. packets the filters
+    /// emptied are passed over
+    pub fn step_revolution(&mut self) -> Option<Step> {
+        let mut packets = self.backlog.iter().filter_map(|queued| match queued {
+            Queued::Points(points) => points.first(),
+            Queued::Scan(_) => None,
+        });
+        let first = *packets.next()?;
+        let end = self.backlog.iter().position(|queued| match queued {
+            Queued::Points(points) => points
+                .first()
+                .is_some_and(|p| p.sensor == first.sensor && p.revolution != first.revolution),
+            Queued::Scan(_) => false,
+        })?;
+        Some(self.take(end))
+    }
+
+    fn take(&mut self, end: usize) -> Step {
+        let mut step = Step::default();
+        for queued in self.backlog.drain(..end) {
+            match queued {
+                Queued::Points(points) => step.points.extend(points),
+                Queued::Scan(scan) => step.scans.push(scan),
+            }
+        }
+        step
+    }
+
+    /// how many packets are waiting
+    pub fn backlog(&self) -> usize {
+        self.backlog
+            .iter()
+            .filter(|queued| matches!(queued, Queued::Points(_)))
+            .count()
+    }
+
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(sensor: u8, revolution: u32, len: usize) -> Vec<DrawPoint> {
+        let point = DrawPoint {
+            x: 0.0,
+            y: 0.0,
+            r: 0,
+            g: 0,
+            b: 0,
+            angle: 0.0,
+            distance: 1000,
+            intensity: 200,
+            revolution,
+            sensor,
+        };
+        vec![point; len]
+    }
+
+    fn scan(revolution: u32) -> Scan {
+        Scan {
+            sensor: 0,
+            revolution,
+            timestamp: 0,
+            points: Vec::new(),
+        }
+    }
+
+    fn paused() -> Playback {
+        let mut playback = Playback::default();
+        playback.toggle();
+        playback
+    }
+
+    #[test]
+    fn steps_past_packets_the_filters_emptied() {
+        let mut playback = paused();
+        playback.push(packet(0, 0, 0));
+        playback.push(packet(0, 0, 12));
+        playback.push(packet(0, 0, 0));
+        playback.push(packet(0, 0, 12));
+        playback.push(packet(0, 1, 12));
+        let step = playback.step_revolution().unwrap();
+        assert_eq!(step.points.len(), 24);
+        assert!(step.points.iter().all(|p| p.revolution == 0));
+        assert_eq!(playback.backlog(), 1);
+    }
+
+    #[test]
+    fn waits_for_the_revolution_to_finish() {
+        let mut playback = paused();
+        playback.push(packet(0, 0, 0));
+        playback.push(packet(0, 0, 12));
+        assert!(playback.step_revolution().is_none());
+        playback.push(packet(0, 1, 12));
+        assert!(playback.step_revolution().is_some());
+    }
+
+    #[test]
+    fn scans_come_out_with_their_packets() {
+        let mut playback = paused();
+        playback.push(packet(0, 0, 12));
+        playback.push(packet(0, 1, 12));
+        playback.push_scan(scan(0));
+        playback.push(packet(0, 1, 12));
+        playback.push(packet(0, 2, 12));
+
+        let step = playback.step_packet().unwrap();
+        assert_eq!((step.points.len(), step.scans.len()), (12, 0));
+        let step = playback.step_revolution().unwrap();
+        assert_eq!((step.points.len(), step.scans.len()), (24, 1));
+        assert_eq!(step.scans[0].revolution, 0);
+
+        playback.push_scan(scan(1));
+        let (skipped, scans) = playback.toggle();
+        assert_eq!((skipped, scans.len()), (1, 1));
+        assert!(playback.push_scan(scan(2)).is_some());
+    }
+}
@@ -0,0 +1,85 @@
+// per-angle range profiles, for comparing one scan (or a whole capture's
+// worth of scans) against another
+use crate::ld19::point::Point;
+
+// one bin per degree
+pub const PROFILE_BINS: usize = 360;
+
+#[derive(Debug, Clone)]
+pub struct RangeProfile {
+    // median range of each bin in millimeters, None if nothing came back
+    pub ranges: Vec<Option<f32>>,
+}
+
+impl RangeProfile {
+    /// bin the points by angle and take the median range of each bin. points
+    /// with a distance of 0 are the sensor saying "no return" and are ignored
+    pub fn from_points<'a, I: IntoIterator<Item = &'a Point>>(points: I) -> Self {
+        let mut bins: Vec<Vec<f32>> = vec![Vec::new(); PROFILE_BINS];
+        for point in points {
+            if point.distance == 0 {
+                continue;
+            }
+            bins[Self::bin_of(point.angle)].push(point.distance as f32);
+        }
+        Self {
+            ranges: bins.into_iter().map(median).collect(),
+        }
+    }
+
+    /// which bin an angle in degrees falls into
+    pub fn bin_of(angle: f32) -> usize {
+        let bin = (angle.rem_euclid(360.0) / 360.0 * PROFILE_BINS as f32) as usize;
+        bin.min(PROFILE_BINS - 1)
+    }
+
+    /// the angle in degrees at the middle of a bin
+    pub fn bearing(bin: usize) -> f32 {
+        (bin as f32 + 0.5) * 360.0 / PROFILE_BINS as f32
+    }
+
+    /// compare other against this profile. differences are other - self, so
+    /// positive means other saw something farther away
+    pub fn diff(&self, other: &RangeProfile) -> ProfileDiff {
+        let per_angle: Vec<Option<f32>> = self
+            .ranges
+            .iter()
+            .zip(other.ranges.iter())
+            .map(|(a, b)| Some(b.as_ref()? - a.as_ref()?))
+            .collect();
+        let compared: Vec<f32> = per_angle.iter().flatten().copied().collect();
+        let rms = if compared.is_empty() {
+            None
+        } else {
+            Some((compared.iter().map(|d| d * d).sum::<f32>() / compared.len() as f32).sqrt())
+        };
+        ProfileDiff {
+            compared: compared.len(),
+            per_angle,
+            rms,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ProfileDiff {
+    // millimeters, one per bin, None where either profile had no return
+    pub per_angle: Vec<Option<f32>>,
+    // root mean square of every bin that could be compared
+    pub rms: Option<f32>,
+    // how many bins that was
+    pub compared: usize,
+}
+
+pub(crate) fn median(mut values: Vec<f32>) -> Option<f32> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        Some((values[mid - 1] + values[mid]) / 2.0)
+    } else {
+        Some(values[mid])
+    }
+}
@@ -0,0 +1,281 @@
+// questions a motion controller asks about the latest scan: what's closest in
+// this direction, which directions are free, and how far can the robot drive
+// along a heading. building the index is one pass over the points, after that
+// sector and gap queries only look at 360 bins, and the points in the two
+// bins at a sector's edges
+use crate::geometry::normalize_degrees;
+use crate::scan::ScanPoint;
+
+// one bin per degree of bearing from the robot's origin
+const QUERY_BINS: usize = 360;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gap {
+    // bearings in degrees from the robot's forward, counterclockwise. from
+    // can be bigger than to when the gap crosses 0
+    pub from: f32,
+    pub to: f32,
+    pub width: f32,
+}
+
+impl Gap {
+    /// bearing in the middle of the gap
+    pub fn center(&self) -> f32 {
+        (self.from + self.width / 2.0).rem_euclid(360.0)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ScanIndex {
+    pub points: Vec<ScanPoint>,
+    // closest point per bin, as (range, index into points)
+    bins: Vec<Option<(f32, usize)>>,
+    // every point by bin, bin b's are members[starts[b]..starts[b + 1]]
+    members: Vec<usize>,
+    starts: Vec<usize>,
+}
+
+impl ScanIndex {
+    /// index some robot frame points, e.g. Scan::points or Fusion::fused
+    pub fn new(points: Vec<ScanPoint>) -> Self {
+        let mut bins: Vec<Option<(f32, usize)>> = vec![None; QUERY_BINS];
+        let mut point_bins = vec![None; points.len()];
+        let mut starts = vec![0; QUERY_BINS + 1];
+        for (i, p) in points.iter().enumerate() {
+            if p.distance == 0 {
+                continue;
+            }
+            let range = p.x.hypot(p.y);
+            let bin = bin_of(bearing_of(p.x, p.y));
+            if bins[bin].is_none_or(|(closest, _)| range < closest) {
+                bins[bin] = Some((range, i));
+            }
+            point_bins[i] = Some(bin);
+            starts[bin + 1] += 1;
+        }
+        for bin in 0..QUERY_BINS {
+            starts[bin + 1] += starts[bin];
+        }
+        let mut next = starts.clone();
+        let mut members = vec![0; starts[QUERY_BINS]];
+        for (i, bin) in point_bins.into_iter().enumerate() {
+            if let Some(bin) = bin {
+                members[next[bin]] = i;
+                next[bin] += 1;
+            }
+        }
+        Self {
+            points,
+            bins,
+            members,
+            starts,
+        }
+    }
+
+    /// closest point within half_width degrees either side of a bearing, and
+    /// its range from the robot's origin in millimeters
+    pub fn closest_in_sector(&self, bearing: f32, half_width: f32) -> Option<(ScanPoint, f32)> {
+        let half_width = half_width.clamp(0.0, 180.0);
+        let bins: Vec<usize> = sector_bins(bearing, half_width).collect();
+        let last = bins.len() - 1;
+        let mut closest: Option<(f32, usize)> = None;
+        for (n, &bin) in bins.iter().enumerate() {
+            // the edge bins stick out past the sector, so their points are
+            // checked one by one
+            let candidates: Vec<(f32, usize)> = if n == 0 || n == last {
+                self.members[self.starts[bin]..self.starts[bin + 1]]
+                    .iter()
+                    .map(|&i| (&self.points[i], i))
+                    .filter(|(p, _)| {
+                        normalize_degrees(bearing_of(p.x, p.y) - bearing).abs() <= half_width
+                    })
+                    .map(|(p, i)| (p.x.hypot(p.y), i))
+                    .collect()
+            } else {
+                self.bins[bin].into_iter().collect()
+            };
+            for candidate in candidates {
+                if closest.is_none_or(|(range, _)| candidate.0 < range) {
+                    closest = Some(candidate);
+                }
+            }
+        }
+        closest.map(|(range, i)| (self.points[i], range))
+    }
+
+    /// every run of bearings with nothing closer than range millimeters that's
+    /// at least min_width degrees wide. bearings with no return at all count
+    /// as free
+    pub fn free_gaps(&self, range: f32, min_width: f32) -> Vec<Gap> {
+        let free: Vec<bool> = self
+            .bins
+            .iter()
+            .map(|bin| bin.is_none_or(|(closest, _)| closest > range))
+            .collect();
+        // nothing in the way anywhere
+        if free.iter().all(|f| *f) {
+            return vec![Gap {
+                from: 0.0,
+                to: 360.0,
+                width: 360.0,
+            }];
+        }
+        // start just after a blocked bin so a gap across 0 comes out whole
+        let start = (0..QUERY_BINS).find(|&i| !free[i]).unwrap() + 1;
+        let mut gaps = Vec::new();
+        let mut run: Option<usize> = None;
+        for offset in 0..=QUERY_BINS {
+            let bin = (start + offset) % QUERY_BINS;
+            match (free[bin] && offset < QUERY_BINS, run) {
+                (true, None) => run = Some(offset),
+                (false, Some(first)) => {
+                    let width = (offset - first) as f32 * 360.0 / QUERY_BINS as f32;
+                    if width >= min_width {
+                        let from =
+                            ((start + first) % QUERY_BINS) as f32 * 360.0 / QUERY_BINS as f32;
+                        gaps.push(Gap {
+                            from,
+                            to: (from + width).rem_euclid(360.0),
+                            width,
+                        });
+                    }
+                    run = None;
+                }
+                _ => (),
+            }
+        }
+        gaps
+    }
+
+    /// how far the robot can go along a bearing before something width
+    /// millimeters wide, centered on the robot's origin, would hit a point.
+    /// None if nothing's in the way within max_distance
+    pub fn clearance(&self, bearing: f32, width: f32, max_distance: f32) -> Option<f32> {
+        let (sin, cos) = bearing.to_radians().sin_cos();
+        let half = width / 2.0;
+        self.points
+            .iter()
+            .filter(|p| p.distance != 0)
+            .filter_map(|p| {
+                // along and across the heading
+                let along = p.x * cos + p.y * sin;
+                let across = -p.x * sin + p.y * cos;
+                (along >= 0.0 && along <= max_distance && across.abs() <= half).then_some(along)
+            })
+            .min_by(|a, b| a.total_cmp(b))
+    }
+}
+
+fn bearing_of(x: f32, y: f32) -> f32 {
+    y.atan2(x).to_degrees().rem_euclid(360.0)
+}
+
+fn bin_of(bearing: f32) -> usize {
+    (bearing.rem_euclid(360.0) * QUERY_BINS as f32 / 360.0) as usize % QUERY_BINS
+}
+
+// bins covering bearing +- half_width, wrapping around 0
+fn sector_bins(bearing: f32, half_width: f32) -> impl Iterator<Item = usize> {
+    let half_width = half_width.clamp(0.0, 180.0);
+    let first = bin_of(bearing - half_width);
+    let count = ((2.0 * half_width) * QUERY_BINS as f32 / 360.0).ceil() as usize + 1;
+    (0..count.min(QUERY_BINS)).map(move |i| (first + i) % QUERY_BINS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(bearing: f32, range: f32) -> ScanPoint {
+        let (sin, cos) = bearing.to_radians().sin_cos();
+        ScanPoint {
+            x: range * cos,
+            y: range * sin,
+            angle: bearing,
+            distance: range as u16,
+            intensity: 200,
+            sensor: 0,
+        }
+    }
+
+    #[test]
+    fn sector_wraps_across_zero() {
+        let index = ScanIndex::new(vec![at(350.0, 2000.0), at(8.0, 1500.0), at(90.0, 500.0)]);
+        let (point, range) = index.closest_in_sector(0.0, 30.0).unwrap();
+        assert!((range - 1500.0).abs() < 1.0);
+        assert!((point.angle - 8.0).abs() < 1e-3);
+        let (_, range) = index.closest_in_sector(355.0, 10.0).unwrap();
+        assert!((range - 2000.0).abs() < 1.0);
+        assert!(index.closest_in_sector(180.0, 30.0).is_none());
+    }
+
+    #[test]
+    fn sector_leaves_out_points_just_past_its_edge() {
+        // 30.6 and 29.4 share the 30 degree bin and 330.4 the 330 one, only
+        // the bearings inside the sector count
+        let index = ScanIndex::new(vec![
+            at(30.6, 500.0),
+            at(29.4, 900.0),
+            at(329.6, 400.0),
+            at(330.4, 1200.0),
+        ]);
+        let (point, range) = index.closest_in_sector(0.0, 30.0).unwrap();
+        assert!((range - 900.0).abs() < 1.0);
+        assert!((point.angle - 29.4).abs() < 1e-3);
+    }
+
+    #[test]
+    fn gaps_cross_zero_and_360() {
+        // a wall everywhere except from 300 round to 60
+        let points: Vec<ScanPoint> = (60..300).map(|b| at(b as f32 + 0.5, 800.0)).collect();
+        let index = ScanIndex::new(points);
+        let gaps = index.free_gaps(1000.0, 10.0);
+        assert_eq!(gaps.len(), 1);
+        let gap = gaps[0];
+        assert_eq!((gap.from, gap.to, gap.width), (300.0, 60.0, 120.0));
+        assert_eq!(gap.center(), 0.0);
+        // short of the wall nothing's in the way
+        assert_eq!(index.free_gaps(700.0, 10.0)[0].width, 360.0);
+        assert!(index.free_gaps(1000.0, 130.0).is_empty());
+    }
+
+    #[test]
+    fn no_returns_is_one_big_gap() {
+        let gaps = ScanIndex::new(Vec::new()).free_gaps(1000.0, 10.0);
+        assert_eq!(
+            gaps,
+            vec![Gap {
+                from: 0.0,
+                to: 360.0,
+                width: 360.0
+            }]
+        );
+    }
+
+    #[test]
+    fn clearance_along_a_heading() {
+        let index = ScanIndex::new(vec![
+            // in the way, 2 m ahead and 200 mm to the left
+            ScanPoint {
+                x: 2000.0,
+                y: 200.0,
+                ..at(0.0, 2000.0)
+            },
+            // too far to the side for something 500 mm wide
+            ScanPoint {
+                x: 1000.0,
+                y: -300.0,
+                ..at(0.0, 1000.0)
+            },
+            // behind
+            at(180.0, 500.0),
+        ]);
+        let clear = index.clearance(0.0, 500.0, 5000.0).unwrap();
+        assert!((clear - 2000.0).abs() < 1e-3);
+        assert!(index.clearance(0.0, 500.0, 1500.0).is_none());
+        assert_eq!(index.clearance(0.0, 700.0, 5000.0), Some(1000.0));
+        // straight back the point behind is the only one in the way
+        let clear = index.clearance(180.0, 100.0, 5000.0).unwrap();
+        assert!((clear - 500.0).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,269 @@
+// direct-to-buffer point rasterizer. the buffer layout is the same one raqote
+// uses for its DrawTarget (one u32 per pixel, 0xAARRGGBB, row major), so these
+// can draw straight into DrawTarget::get_data_mut() without building a path
+// for every single point
+
+/// pack an opaque color into the 0xAARRGGBB layout
+pub fn pack(r: u8, g: u8, b: u8) -> u32 {
+    0xff00_0000 | (r as u32) << 16 | (g as u32) << 8 | b as u32
+}
+
+/// fill a size x size square with its top left corner at (x, y). the
+/// position is snapped to the pixel grid, anything off the buffer is clipped
+pub fn plot(buf: &mut [u32], width: usize, x: f32, y: f32, size: f32, color: u32) {
+    if width == 0 || !(x.is_finite() && y.is_finite()) || x + size <= 0.0 || y + size <= 0.0 {
+        return;
+    }
+    let height = buf.len() / width;
+    let x0 = x.floor().max(0.0) as usize;
+    let y0 = y.floor().max(0.0) as usize;
+    let x1 = ((x + size).floor().max(0.0) as usize).min(width);
+    let y1 = ((y + size).floor().max(0.0) as usize).min(height);
+    // a point that's less than a pixel wide still lights up the pixel it
+    // starts in
+    let x1 = x1.max((x0 + 1).min(width));
+    let y1 = y1.max((y0 + 1).min(height));
+    if x0 >= x1 || y0 >= y1 {
+        return;
+    }
+    for row in y0..y1 {
+        buf[row * width + x0..row * width + x1].fill(color);
+    }
+}
+
+/// like plot, but the square keeps its subpixel position and each pixel it
+/// touches is blended by how much of it is covered
+pub fn plot_aa(buf: &mut [u32], width: usize, x: f32, y: f32, size: f32, color: u32) {
+    if width == 0 || !(x.is_finite() && y.is_finite()) {
+        return;
+    }
+    let height = buf.len() / width;
+    let (x_end, y_end) = (x + size, y + size);
+    let x0 = x.floor().max(0.0) as usize;
+    let y0 = y.floor().max(0.0) as usize;
+    let x1 = (x_end.ceil().max(0.0) as usize).min(width);
+    let y1 = (y_end.ceil().max(0.0) as usize).min(height);
+    for row in y0..y1 {
+        let cover_y = coverage(row as f32, y, y_end);
+        for col in x0..x1 {
+            let cover = cover_y * coverage(col as f32, x, x_end);
+            if cover <= 0.0 {
+                continue;
+            }
+            let dst = &mut buf[row * width + col];
+            *dst = blend(*dst, color, cover);
+        }
+    }
+}
+
+/// copy src, src_width pixels wide, onto buf turned and scaled: source pixel
+/// (u, v) covers the parallelogram from origin + u * x_axis + v * y_axis. each
+/// pixel takes the source pixel under its center, 0 source pixels are left out
+pub fn blit(
+    buf: &mut [u32],
+    width: usize,
+    src: &[u32],
+    src_width: usize,
+    origin: (f32, f32),
+    x_axis: (f32, f32),
+    y_axis: (f32, f32),
+) {
+    let det = x_axis.0 * y_axis.1 - x_axis.1 * y_axis.0;
+    if width == 0 || src_width == 0 || det == 0.0 || !det.is_finite() {
+        return;
+    }
+    let height = buf.len() / width;
+    let src_height = src.len() / src_width;
+    // only the pixels the source's corners could reach
+    let (w, h) = (src_width as f32, src_height as f32);
+    let corners = [
+        origin,
+        (origin.0 + x_axis.0 * w, origin.1 + x_axis.1 * w),
+        (origin.0 + y_axis.0 * h, origin.1 + y_axis.1 * h),
+        (
+            origin.0 + x_axis.0 * w + y_axis.0 * h,
+            origin.1 + x_axis.1 * w + y_axis.1 * h,
+        ),
+    ];
+    let (mut min, mut max) = ((f32::MAX, f32::MAX), (f32::MIN, f32::MIN));
+    for (x, y) in corners {
+        min = (min.0.min(x), min.1.min(y));
+        max = (max.0.max(x), max.1.max(y));
+    }
+    if !(min.0.is_finite() && min.1.is_finite() && max.0.is_finite() && max.1.is_finite()) {
+        return;
+    }
+    let x0 = min.0.floor().max(0.0) as usize;
+    let y0 = min.1.floor().max(0.0) as usize;
+    let x1 = (max.0.ceil().max(0.0) as usize).min(width);
+    let y1 = (max.1.ceil().max(0.0) as usize).min(height);
+    for row in y0..y1 {
+        for col in x0..x1 {
+            // back through the axes to the source pixel
+            let dx = col as f32 + 0.5 - origin.0;
+            let dy = row as f32 + 0.5 - origin.1;
+            let u = (dx * y_axis.1 - dy * y_axis.0) / det;
+            let v = (x_axis.0 * dy - x_axis.1 * dx) / det;
+            if !(0.0..w).contains(&u) || !(0.0..h).contains(&v) {
+                continue;
+            }
+            let color = src[v as usize * src_width + u as usize];
+            if color != 0 {
+                buf[row * width + col] = color;
+            }
+        }
+    }
+}
+
+// how much of the pixel starting at p is covered by the span start..end
+fn coverage(p: f32, start: f32, end: f32) -> f32 {
+    ((p + 1.0).min(end) - p.max(start)).clamp(0.0, 1.0)
+}
+
+fn blend(dst: u32, src: u32, alpha: f32) -> u32 {
+    let mix = |shift: u32| {
+        let d = ((dst >> shift) & 0xff) as f32;
+        let s = ((src >> shift) & 0xff) as f32;
+        ((d + (s - d) * alpha).round() as u32) << shift
+    };
+    0xff00_0000 | mix(16) | mix(8) | mix(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = 8;
+
+    fn lit(buf: &[u32]) -> Vec<(usize, usize)> {
+        (0..buf.len())
+            .filter(|i| buf[*i] != 0)
+            .map(|i| (i % WIDTH, i / WIDTH))
+            .collect()
+    }
+
+    #[test]
+    fn plot_fills_the_square() {
+        let mut buf = vec![0; WIDTH * WIDTH];
+        plot(&mut buf, WIDTH, 2.0, 3.0, 2.0, 1);
+        assert_eq!(lit(&buf), vec![(2, 3), (3, 3), (2, 4), (3, 4)]);
+    }
+
+    #[test]
+    fn plot_lights_a_pixel_for_tiny_points() {
+        let mut buf = vec![0; WIDTH * WIDTH];
+        plot(&mut buf, WIDTH, 5.5, 1.2, 0.3, 1);
+        assert_eq!(lit(&buf), vec![(5, 1)]);
+    }
+
+    #[test]
+    fn plot_clips_at_the_edges() {
+        let mut buf = vec![0; WIDTH * WIDTH];
+        plot(&mut buf, WIDTH, -1.0, 6.5, 3.0, 1);
+        assert_eq!(lit(&buf), vec![(0, 6), (1, 6), (0, 7), (1, 7)]);
+    }
+
+    #[test]
+    fn plot_skips_points_off_the_buffer() {
+        let mut buf = vec![0; WIDTH * WIDTH];
+        for (x, y) in [
+            (-5.0, 2.0),
+            (2.0, -5.0),
+            (-2.0, -2.0),
+            (-1.0, 3.0),
+            (WIDTH as f32, 2.0),
+            (2.0, WIDTH as f32 + 3.0),
+            (f32::NAN, 2.0),
+            (2.0, f32::INFINITY),
+        ] {
+            plot(&mut buf, WIDTH, x, y, 1.0, 1);
+        }
+        assert!(lit(&buf).is_empty());
+    }
+
+    #[test]
+    fn plot_aa_skips_points_off_the_buffer() {
+        let mut buf = vec![0; WIDTH * WIDTH];
+        plot_aa(&mut buf, WIDTH, -3.0, -3.0, 1.0, 0xffff_ffff);
+        plot_aa(&mut buf, WIDTH, f32::NAN, 2.0, 1.0, 0xffff_ffff);
+        assert!(lit(&buf).is_empty());
+    }
+
+    #[test]
+    fn blit_scales_and_turns() {
+        // 2x1 source, each pixel twice the size
+        let mut buf = vec![0; WIDTH * WIDTH];
+        blit(
+            &mut buf,
+            WIDTH,
+            &[1, 2],
+            2,
+            (1.0, 1.0),
+            (2.0, 0.0),
+            (0.0, 2.0),
+        );
+        let expected = vec![
+            (1, 1),
+            (2, 1),
+            (3, 1),
+            (4, 1),
+            (1, 2),
+            (2, 2),
+            (3, 2),
+            (4, 2),
+        ];
+        assert_eq!(lit(&buf), expected);
+        assert_eq!(buf[WIDTH + 2], 1);
+        assert_eq!(buf[WIDTH + 3], 2);
+
+        // a quarter turn, u runs down the screen and v to the left
+        let mut buf = vec![0; WIDTH * WIDTH];
+        blit(
+            &mut buf,
+            WIDTH,
+            &[1, 2, 0, 3],
+            2,
+            (4.0, 2.0),
+            (0.0, 1.0),
+            (-1.0, 0.0),
+        );
+        assert_eq!(lit(&buf), vec![(3, 2), (2, 3), (3, 3)]);
+        assert_eq!(buf[2 * WIDTH + 3], 1);
+        assert_eq!(buf[3 * WIDTH + 3], 2);
+        assert_eq!(buf[3 * WIDTH + 2], 3);
+    }
+
+    #[test]
+    fn blit_clips_at_the_edges() {
+        let mut buf = vec![0; WIDTH * WIDTH];
+        blit(
+            &mut buf,
+            WIDTH,
+            &[1; 9],
+            3,
+            (-1.0, 6.0),
+            (1.0, 0.0),
+            (0.0, 1.0),
+        );
+        assert_eq!(lit(&buf), vec![(0, 6), (1, 6), (0, 7), (1, 7)]);
+        blit(
+            &mut buf,
+            WIDTH,
+            &[1; 9],
+            3,
+            (0.0, 0.0),
+            (0.0, 0.0),
+            (0.0, 1.0),
+        );
+        blit(
+            &mut buf,
+            WIDTH,
+            &[1; 9],
+            3,
+            (f32::NAN, 0.0),
+            (1.0, 0.0),
+            (0.0, 1.0),
+        );
+        assert_eq!(lit(&buf).len(), 4);
+    }
+}
@@ -0,0 +1,352 @@
+// protective fields like an industrial safety scanner: nested warning, slow and
+// stop fields around the robot. fields are checked on every packet rather than
+// every revolution so a stop comes as soon as the beam sweeps over something,
+// and the state only drops back down once it's been clear for a while. a
+// sensor that stops sending can't see anything, so that's a stop as well
+use crate::geometry::Polygon;
+use crate::scan::ScanPoint;
+use core::fmt;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum FieldLevel {
+    #[default]
+    Clear,
+    Warning,
+    Slow,
+    Stop,
+}
+
+impl FieldLevel {
+    pub const ALL: [FieldLevel; 4] = [
+        FieldLevel::Clear,
+        FieldLevel::Warning,
+        FieldLevel::Slow,
+        FieldLevel::Stop,
+    ];
+
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl fmt::Display for FieldLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FieldLevel::Clear => write!(f, "clear"),
+            FieldLevel::Warning => write!(f, "warning"),
+            FieldLevel::Slow => write!(f, "slow"),
+            FieldLevel::Stop => write!(f, "stop"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum FieldShape {
+    // robot frame millimeters
+    Polygon(Polygon),
+    // (from, to, range) bearings in degrees from the robot's forward,
+    // counterclockwise, and the range in millimeters from the robot's origin.
+    // a return inside any of them is inside the field
+    Ranges(Vec<(f32, f32, f32)>),
+}
+
+#[derive(Debug, Clone)]
+pub struct SafetyField {
+    pub level: FieldLevel,
+    pub shape: FieldShape,
+}
+
+impl SafetyField {
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        match &self.shape {
+            FieldShape::Polygon(polygon) => polygon.contains(x, y),
+            FieldShape::Ranges(limits) => {
+                let bearing = y.atan2(x).to_degrees().rem_euclid(360.0);
+                let range = x.hypot(y);
+                limits.iter().any(|&(from, to, limit)| {
+                    // measured from `from` so sectors across 0 degrees work
+                    let width = (to - from).rem_euclid(360.0);
+                    (bearing - from).rem_euclid(360.0) <= width && range <= limit
+                })
+            }
+        }
+    }
+
+    /// the field as a polygon, per angle limits become pie slices
+    pub fn outline(&self) -> Vec<Polygon> {
+        match &self.shape {
+            FieldShape::Polygon(polygon) => vec![polygon.clone()],
+            FieldShape::Ranges(limits) => limits
+                .iter()
+                .map(|&(from, to, limit)| {
+                    let width = (to - from).rem_euclid(360.0);
+                    let steps = (width / 5.0).ceil().max(1.0) as usize;
+                    let mut points = vec![(0.0, 0.0)];
+                    for i in 0..=steps {
+                        let angle = (from + width * i as f32 / steps as f32).to_radians();
+                        points.push((limit * angle.cos(), limit * angle.sin()));
+                    }
+                    Polygon::new(points)
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SafetyConfig {
+    // returns needed inside a field before it trips, so a single stray
+    // return doesn't stop the robot
+    pub min_points: usize,
+    // how long it has to stay clear of a field before the state drops below
+    // it
+    pub release: Duration,
+    // longest the sensor can go without sending a packet before the state
+    // goes to stop. a revolution is 100 ms at the ld19's default speed
+    pub timeout: Duration,
+}
+
+impl Default for SafetyConfig {
+    fn default() -> Self {
+        Self {
+            min_points: 3,
+            release: Duration::from_millis(500),
+            timeout: Duration::from_millis(150),
+        }
+    }
+}
+
+// one bin per degree of sensor angle
+const SAFETY_BINS: usize = 360;
+
+pub type SafetyCallback = Box<dyn FnMut(FieldLevel, FieldLevel) + Send>;
+
+/// evaluates the fields for one sensor. every packet replaces what was known
+/// about its angles, so the state always reflects the last revolution's worth
+/// of returns with the newest packet's included straight away
+pub struct SafetyMonitor {
+    pub config: SafetyConfig,
+    pub fields: Vec<SafetyField>,
+    // returns per level in every bin, for the most recent packet to cover it
+    bins: Vec<[u16; 4]>,
+    state: FieldLevel,
+    // when the returns last justified the current state
+    last_seen: Option<Instant>,
+    // when the newest packet came in, or the first check if none has
+    last_packet: Option<Instant>,
+    callback: Option<SafetyCallback>,
+}
+
+impl fmt::Debug for SafetyMonitor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SafetyMonitor")
+            .field("config", &self.config)
+            .field("fields", &self.fields)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl SafetyMonitor {
+    pub fn new(config: SafetyConfig, fields: Vec<SafetyField>) -> Self {
+        Self {
+            config,
+            fields,
+            bins: vec![[0; 4]; SAFETY_BINS],
+            state: FieldLevel::Clear,
+            last_seen: None,
+            last_packet: None,
+            callback: None,
+        }
+    }
+
+    /// called with the old and new state whenever it changes. drive a relay,
+    /// a gpio pin, a motor controller, whatever
+    pub fn on_change<F: FnMut(FieldLevel, FieldLevel) + Send + 'static>(&mut self, callback: F) {
+        self.callback = Some(Box::new(callback));
+    }
+
+    /// debounced state
+    pub fn state(&self) -> FieldLevel {
+        self.state
+    }
+
+    /// most severe field a point is in
+    pub fn level_of(&self, x: f32, y: f32) -> FieldLevel {
+        self.fields
+            .iter()
+            .filter(|field| field.contains(x, y))
+            .map(|field| field.level)
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// what the returns say right now, before debouncing
+    pub fn raw_level(&self) -> FieldLevel {
+        let mut totals = [0usize; 4];
+        for bin in &self.bins {
+            for (total, count) in totals.iter_mut().zip(bin) {
+                *total += *count as usize;
+            }
+        }
+        // nested fields, so a return in the stop field counts towards slow
+        // and warning too
+        let mut at_least = 0;
+        for level in FieldLevel::ALL.iter().rev() {
+            at_least += totals[level.index()];
+            if *level != FieldLevel::Clear && at_least >= self.config.min_points {
+                return *level;
+            }
+        }
+        FieldLevel::Clear
+    }
+
+    /// feed one packet's points, robot frame. returns the new state if it
+    /// changed
+    pub fn push(&mut self, points: &[ScanPoint], now: Instant) -> Option<FieldLevel> {
+        // a packet after a long gap still means the sensor went quiet
+        let stale = self.check(now);
+        self.last_packet = Some(now);
+        let mut touched = [false; SAFETY_BINS];
+        for p in points {
+            let bin = (p.angle.rem_euclid(360.0) as usize).min(SAFETY_BINS - 1);
+            if !touched[bin] {
+                touched[bin] = true;
+                self.bins[bin] = [0; 4];
+            }
+            if p.distance == 0 {
+                continue;
+            }
+            let level = self.level_of(p.x, p.y);
+            self.bins[bin][level.index()] += 1;
+        }
+
+        let raw = self.raw_level();
+        let next = if raw >= self.state {
+            // going up is immediate
+            self.last_seen = Some(now);
+            raw
+        } else if self
+            .last_seen
+            .is_none_or(|seen| now.duration_since(seen) >= self.config.release)
+        {
+            // going down waits out the release time, and then only goes as
+            // far as the returns say
+            self.last_seen = Some(now);
+            raw
+        } else {
+            self.state
+        };
+        self.set(next).or(stale)
+    }
+
+    /// call every so often, packets or not. once the sensor's been quiet for
+    /// longer than the timeout nothing it said before counts and the state
+    /// goes to stop. returns the new state if it changed
+    pub fn check(&mut self, now: Instant) -> Option<FieldLevel> {
+        let last = *self.last_packet.get_or_insert(now);
+        if now.duration_since(last) < self.config.timeout {
+            return None;
+        }
+        self.bins.fill([0; 4]);
+        // the release time starts over from here
+        self.last_seen = Some(now);
+        self.set(FieldLevel::Stop)
+    }
+
+    fn set(&mut self, next: FieldLevel) -> Option<FieldLevel> {
+        if next == self.state {
+            return None;
+        }
+        let previous = self.state;
+        self.state = next;
+        if let Some(callback) = self.callback.as_mut() {
+            callback(previous, next);
+        }
+        Some(next)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor() -> SafetyMonitor {
+        let field = SafetyField {
+            level: FieldLevel::Stop,
+            shape: FieldShape::Ranges(vec![(-45.0, 45.0, 500.0)]),
+        };
+        SafetyMonitor::new(SafetyConfig::default(), vec![field])
+    }
+
+    // a packet of returns straight ahead at some range
+    fn packet(distance: u16) -> Vec<ScanPoint> {
+        (0..12)
+            .map(|i| ScanPoint {
+                x: distance as f32,
+                y: 0.0,
+                angle: i as f32 * 0.5,
+                distance,
+                intensity: 200,
+                sensor: 0,
+            })
+            .collect()
+    }
+
+    // clear packets every 10 ms from one time to another, and every change
+    // they caused
+    fn clear_between(monitor: &mut SafetyMonitor, start: Instant, from: u64, to: u64) -> Vec<u64> {
+        (from..to)
+            .step_by(10)
+            .filter(|ms| {
+                let now = start + Duration::from_millis(*ms);
+                monitor.push(&packet(2000), now).is_some()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn trips_and_releases() {
+        let mut monitor = monitor();
+        let start = Instant::now();
+        assert_eq!(monitor.push(&packet(2000), start), None);
+        let at = |ms| start + Duration::from_millis(ms);
+        assert_eq!(monitor.push(&packet(300), at(10)), Some(FieldLevel::Stop));
+        // clear again, and released once it's been clear for long enough
+        assert_eq!(clear_between(&mut monitor, start, 20, 600), vec![510]);
+        assert_eq!(monitor.state(), FieldLevel::Clear);
+    }
+
+    #[test]
+    fn a_quiet_sensor_is_a_stop() {
+        let mut monitor = monitor();
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        monitor.push(&packet(2000), start);
+        assert_eq!(monitor.check(at(100)), None);
+        assert_eq!(monitor.check(at(200)), Some(FieldLevel::Stop));
+        assert_eq!(monitor.state(), FieldLevel::Stop);
+        // packets again, and clear once they've been clear for the release
+        // time
+        assert_eq!(clear_between(&mut monitor, start, 300, 900), vec![800]);
+    }
+
+    #[test]
+    fn a_sensor_that_never_sends_is_a_stop() {
+        let mut monitor = monitor();
+        let start = Instant::now();
+        assert_eq!(monitor.check(start), None);
+        let later = start + Duration::from_millis(200);
+        assert_eq!(monitor.check(later), Some(FieldLevel::Stop));
+    }
+
+    #[test]
+    fn a_late_packet_is_a_stop() {
+        let mut monitor = monitor();
+        let start = Instant::now();
+        monitor.push(&packet(2000), start);
+        let late = start + Duration::from_millis(400);
+        assert_eq!(monitor.push(&packet(2000), late), Some(FieldLevel::Stop));
+    }
+}
@@ -0,0 +1,194 @@
+// assembling packets into full revolutions ("scans"), and fusing the latest
+// scan from every sensor into one set of points
+use crate::extrinsics::Extrinsics;
+use crate::ld19::decoder::Packet;
+use crate::ld19::point::parse;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ScanPoint {
+    // robot frame, millimeters
+    pub x: f32,
+    pub y: f32,
+    // raw sensor angle in degrees and range in millimeters
+    pub angle: f32,
+    pub distance: u16,
+    pub intensity: u8,
+    // which sensor it came from
+    pub sensor: u8,
+}
+
+#[derive(Debug, Clone)]
+pub struct Scan {
+    pub sensor: u8,
+    // counts up from the first revolution seen
+    pub revolution: u32,
+    // sensor time of the first packet in the scan, unwrapped milliseconds
+    pub timestamp: u64,
+    pub points: Vec<ScanPoint>,
+}
+
+// the ld19 counts milliseconds up to this and starts again from 0
+pub const TIMESTAMP_PERIOD: u16 = 30000;
+
+/// the ld19's timestamp restarts every 30 seconds. this keeps a running
+/// total instead
+#[derive(Debug, Default, Clone)]
+pub struct TimestampUnwrapper {
+    last: Option<u16>,
+    total: u64,
+}
+
+impl TimestampUnwrapper {
+    pub fn unwrap(&mut self, timestamp: u16) -> u64 {
+        if let Some(last) = self.last {
+            // going backwards means it restarted in between
+            let step = (timestamp as i64 - last as i64).rem_euclid(TIMESTAMP_PERIOD as i64);
+            self.total += step as u64;
+        } else {
+            self.total = timestamp as u64;
+        }
+        self.last = Some(timestamp);
+        self.total
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanAssembler {
+    pub sensor: u8,
+    pub extrinsics: Extrinsics,
+    current: Vec<ScanPoint>,
+    current_timestamp: u64,
+    last_start_angle: Option<u16>,
+    revolution: u32,
+    clock: TimestampUnwrapper,
+}
+
+impl ScanAssembler {
+    pub fn new(sensor: u8, extrinsics: Extrinsics) -> Self {
+        Self {
+            sensor,
+            extrinsics,
+            current: Vec::new(),
+            current_timestamp: 0,
+            last_start_angle: None,
+            revolution: 0,
+            clock: TimestampUnwrapper::default(),
+        }
+    }
+
+    /// revolution the next packet will be part of
+    pub fn revolution(&self) -> u32 {
+        self.revolution
+    }
+
+    /// revolution a packet will be part of once it's pushed
+    pub fn revolution_of(&self, packet: &Packet) -> u32 {
+        if self.starts_revolution(packet) {
+            self.revolution.wrapping_add(1)
+        } else {
+            self.revolution
+        }
+    }
+
+    fn starts_revolution(&self, packet: &Packet) -> bool {
+        self.last_start_angle
+            .is_some_and(|last| packet.start_angle < last)
+    }
+
+    /// a packet's points in the robot frame
+    pub fn convert(&self, packet: &Packet) -> Vec<ScanPoint> {
+        parse(packet)
+            .iter()
+            .map(|p| {
+                let (x, y) = self.extrinsics.to_robot(p);
+                ScanPoint {
+                    x,
+                    y,
+                    angle: p.angle,
+                    distance: p.distance as u16,
+                    intensity: p.confidence,
+                    sensor: self.sensor,
+                }
+            })
+            .collect()
+    }
+
+    /// add a packet's points, usually from convert() and maybe filtered. the
+    /// start angle only ever goes down when the sensor passes 0 degrees, so
+    /// that's when the previous scan is handed back
+    pub fn push(&mut self, packet: &Packet, points: Vec<ScanPoint>) -> Option<Scan> {
+        let timestamp = self.clock.unwrap(packet.timestamp);
+        let wrapped = self.starts_revolution(packet);
+        self.last_start_angle = Some(packet.start_angle);
+
+        let finished = if wrapped {
+            let scan = Scan {
+                sensor: self.sensor,
+                revolution: self.revolution,
+                timestamp: self.current_timestamp,
+                points: std::mem::take(&mut self.current),
+            };
+            self.revolution = self.revolution.wrapping_add(1);
+            Some(scan)
+        } else {
+            None
+        };
+
+        if self.current.is_empty() {
+            self.current_timestamp = timestamp;
+        }
+        self.current.extend(points);
+        finished
+    }
+}
+
+/// the latest scan from each sensor
+#[derive(Debug, Default, Clone)]
+pub struct Fusion {
+    latest: Vec<Option<Scan>>,
+}
+
+impl Fusion {
+    pub fn push(&mut self, scan: Scan) {
+        let i = scan.sensor as usize;
+        if self.latest.len() <= i {
+            self.latest.resize(i + 1, None);
+        }
+        self.latest[i] = Some(scan);
+    }
+
+    /// latest scan from one sensor
+    pub fn scan(&self, sensor: u8) -> Option<&Scan> {
+        self.latest.get(sensor as usize)?.as_ref()
+    }
+
+    /// latest scan from every sensor that has sent one
+    pub fn scans(&self) -> impl Iterator<Item = &Scan> {
+        self.latest.iter().flatten()
+    }
+
+    /// every sensor's latest points together, in the robot frame
+    pub fn fused(&self) -> Vec<ScanPoint> {
+        self.scans()
+            .flat_map(|s| s.points.iter().copied())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_carry_on_across_the_restart() {
+        let mut clock = TimestampUnwrapper::default();
+        assert_eq!(clock.unwrap(29_950), 29_950);
+        assert_eq!(clock.unwrap(29_990), 29_990);
+        // 10 ms to the restart and 30 after it
+        assert_eq!(clock.unwrap(30), 30_030);
+        assert_eq!(clock.unwrap(80), 30_080);
+        // and again a lap later
+        assert_eq!(clock.unwrap(29_999), 59_999);
+        assert_eq!(clock.unwrap(1), 60_001);
+    }
+}
@@ -0,0 +1,176 @@
+// saving what's on screen: single png screenshots and recording a sequence of
+// pngs. frames are written from a separate thread so recording doesn't stall
+// the event loop. tracked objects go alongside the frames in tracks.csv
+use lidar::grid::OccupancyGrid;
+use lidar::map::{self, MapError};
+use lidar::track::Track;
+use raqote::DrawTarget;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// recording every redraw would be way more frames than anyone needs
+const RECORD_INTERVAL: Duration = Duration::from_millis(1000 / 30);
+
+/// UTC timestamp for file names down to the millisecond, so two saves in
+/// the same second don't overwrite each other, e.g. 20241018-154502-123
+pub fn timestamp() -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let (secs, millis) = (millis / 1000, millis % 1000);
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{year:04}{month:02}{day:02}-{:02}{:02}{:02}-{millis:03}",
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+// days since 1970-01-01 to a (year, month, day), from howard hinnant's
+// date algorithms
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn write_png(path: &PathBuf, width: u32, height: u32, frame: Vec<u32>) -> io::Result<()> {
+    DrawTarget::from_vec(width as i32, height as i32, frame)
+        .write_png(path)
+        .map_err(io::Error::other)
+}
+
+/// save a single frame as lidar-<timestamp>.png in the working directory
+pub fn save(width: u32, height: u32, frame: &[u32]) -> io::Result<PathBuf> {
+    let path = PathBuf::from(format!("lidar-{}.png", timestamp()));
+    write_png(&path, width, height, frame.to_vec())?;
+    Ok(path)
+}
+
+/// save an occupancy grid as map-<timestamp>.yaml and .pgm in the working
+/// directory, ready for ros map_server
+pub fn save_map(grid: &OccupancyGrid) -> Result<PathBuf, MapError> {
+    let path = PathBuf::from(format!("map-{}.yaml", timestamp()));
+    map::save(grid, &path)?;
+    Ok(path)
+}
+
+struct Frame {
+    path: PathBuf,
+    width: u32,
+    height: u32,
+    data: Vec<u32>,
+}
+
+/// records frames to numbered pngs in a lidar-<timestamp> directory
+pub struct Recorder {
+    pub dir: PathBuf,
+    frames: u32,
+    tracks: BufWriter<File>,
+    last: Option<Instant>,
+    sender: Option<Sender<Frame>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl Recorder {
+    pub fn start() -> io::Result<Self> {
+        let dir = PathBuf::from(format!("lidar-{}", timestamp()));
+        fs::create_dir_all(&dir)?;
+        let mut tracks = BufWriter::new(File::create(dir.join("tracks.csv"))?);
+        // frame is the last frame queued before the update, so tracks can be
+        // lined up with the pngs
+        writeln!(
+            tracks,
+            "timestamp_ms,frame,id,x_mm,y_mm,vx_mm_s,vy_mm_s,hits,misses"
+        )?;
+
+        let (sender, receiver) = mpsc::channel::<Frame>();
+        let writer = thread::Builder::new()
+            .name(String::from("recorder"))
+            .spawn(move || {
+                while let Ok(frame) = receiver.recv() {
+                    if let Err(err) = write_png(&frame.path, frame.width, frame.height, frame.data)
+                    {
+                        println!("[record] unable to write {:?}, {err}", frame.path);
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            dir,
+            frames: 0,
+            tracks,
+            last: None,
+            sender: Some(sender),
+            writer: Some(writer),
+        })
+    }
+
+    /// queue a frame to be written, if enough time has passed since the last
+    /// one
+    pub fn push(&mut self, width: u32, height: u32, frame: &[u32]) {
+        let now = Instant::now();
+        if self
+            .last
+            .is_some_and(|last| now.duration_since(last) < RECORD_INTERVAL)
+        {
+            return;
+        }
+        self.last = Some(now);
+
+        let frame = Frame {
+            path: self.dir.join(format!("frame-{:05}.png", self.frames)),
+            width,
+            height,
+            data: frame.to_vec(),
+        };
+        if let Some(sender) = self.sender.as_ref() {
+            let _ = sender.send(frame);
+            self.frames += 1;
+        }
+    }
+
+    /// append a tracker update, timestamp is unwrapped sensor milliseconds
+    pub fn push_tracks(&mut self, timestamp: u64, tracks: &[Track]) {
+        let frame = self.frames.saturating_sub(1);
+        for t in tracks {
+            let written = writeln!(
+                self.tracks,
+                "{timestamp},{frame},{},{:.1},{:.1},{:.1},{:.1},{},{}",
+                t.id, t.position.0, t.position.1, t.velocity.0, t.velocity.1, t.hits, t.misses
+            );
+            if let Err(err) = written {
+                println!("[record] unable to write tracks, {err}");
+                return;
+            }
+        }
+    }
+
+    /// stop recording and wait for the queued frames to be written. returns
+    /// how many frames were recorded
+    pub fn finish(mut self) -> u32 {
+        if let Err(err) = self.tracks.flush() {
+            println!("[record] unable to write tracks, {err}");
+        }
+        // dropping the sender ends the writer's loop
+        self.sender.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+        self.frames
+    }
+}
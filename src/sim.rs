@@ -0,0 +1,170 @@
+// a simulated ld19 in a made up room. the noise comes from a seeded generator
+// so the same seed always gives the same scans, and the poses the robot is
+// put at are the ground truth to check odometry and mapping against
+use crate::extrinsics::Extrinsics;
+use crate::geometry::Pose;
+use crate::scan::{Scan, ScanPoint};
+
+/// walls as line segments, world frame millimeters
+#[derive(Debug, Clone, Default)]
+pub struct Scene {
+    pub walls: Vec<((f32, f32), (f32, f32))>,
+}
+
+impl Scene {
+    pub fn new(walls: Vec<((f32, f32), (f32, f32))>) -> Self {
+        Self { walls }
+    }
+
+    /// add a rectangle's four sides
+    pub fn with_rectangle(mut self, min: (f32, f32), max: (f32, f32)) -> Self {
+        let corners = [min, (max.0, min.1), max, (min.0, max.1)];
+        for i in 0..4 {
+            self.walls.push((corners[i], corners[(i + 1) % 4]));
+        }
+        self
+    }
+
+    /// an 8 x 5 m room with an alcove, a couple of boxes and a pillar, so no
+    /// two spots in it look quite the same
+    pub fn room() -> Self {
+        Scene::new(vec![
+            ((-4000.0, -2500.0), (4000.0, -2500.0)),
+            ((4000.0, -2500.0), (4000.0, 2500.0)),
+            ((4000.0, 2500.0), (1000.0, 2500.0)),
+            ((1000.0, 2500.0), (1000.0, 3500.0)),
+            ((1000.0, 3500.0), (-1000.0, 3500.0)),
+            ((-1000.0, 3500.0), (-1000.0, 2500.0)),
+            ((-1000.0, 2500.0), (-4000.0, 2500.0)),
+            ((-4000.0, 2500.0), (-4000.0, -2500.0)),
+        ])
+        .with_rectangle((2500.0, -2000.0), (3300.0, -1400.0))
+        .with_rectangle((-3200.0, 1200.0), (-2600.0, 2000.0))
+        .with_rectangle((-300.0, -300.0), (300.0, 300.0))
+    }
+
+    /// distance along a ray to the nearest wall, direction in degrees
+    pub fn raycast(&self, origin: (f32, f32), direction: f32, max_range: f32) -> Option<f32> {
+        let (sin, cos) = direction.to_radians().sin_cos();
+        self.walls
+            .iter()
+            .filter_map(|&((ax, ay), (bx, by))| {
+                // solve origin + t * dir = a + s * (b - a)
+                let (ex, ey) = (bx - ax, by - ay);
+                let denominator = cos * ey - sin * ex;
+                if denominator.abs() < f32::EPSILON {
+                    return None;
+                }
+                let (wx, wy) = (ax - origin.0, ay - origin.1);
+                let t = (wx * ey - wy * ex) / denominator;
+                let s = (wx * sin - wy * cos) / denominator;
+                (t > 0.0 && (0.0..=1.0).contains(&s)).then_some(t)
+            })
+            .filter(|t| *t <= max_range)
+            .min_by(|a, b| a.total_cmp(b))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SimConfig {
+    // returns per revolution, the ld19 does about 450 at 10 Hz
+    pub points: usize,
+    // standard deviation of the range noise, millimeters
+    pub noise: f32,
+    pub max_range: f32,
+    pub seed: u64,
+    pub extrinsics: Extrinsics,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            points: 450,
+            noise: 10.0,
+            max_range: 12000.0,
+            seed: 1,
+            extrinsics: Extrinsics::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Simulator {
+    pub scene: Scene,
+    pub config: SimConfig,
+    sensor: u8,
+    revolution: u32,
+    rng: u64,
+}
+
+impl Simulator {
+    pub fn new(scene: Scene, config: SimConfig) -> Self {
+        Self {
+            scene,
+            config,
+            sensor: 0,
+            revolution: 0,
+            // xorshift gets stuck on 0
+            rng: config.seed.max(1),
+        }
+    }
+
+    /// a revolution seen from pose, robot frame points like ScanAssembler
+    /// makes. the whole revolution is taken at once, so unlike the real thing
+    /// there's no smear while moving
+    pub fn scan(&mut self, pose: Pose, timestamp: u64) -> Scan {
+        let extrinsics = self.config.extrinsics;
+        let origin = pose.apply(extrinsics.x, extrinsics.y);
+        let mut points = Vec::with_capacity(self.config.points);
+        for i in 0..self.config.points {
+            let angle = i as f32 * 360.0 / self.config.points as f32;
+            let direction = pose.heading + extrinsics.bearing(angle);
+            let Some(range) = self.scene.raycast(origin, direction, self.config.max_range) else {
+                continue;
+            };
+            let distance = (range + self.gaussian() * self.config.noise).max(0.0);
+            let (sin, cos) = angle.to_radians().sin_cos();
+            let (x, y) = extrinsics.apply(distance * cos, distance * sin);
+            points.push(ScanPoint {
+                x,
+                y,
+                angle,
+                distance: distance.round().min(u16::MAX as f32) as u16,
+                intensity: 200,
+                sensor: self.sensor,
+            });
+        }
+        let scan = Scan {
+            sensor: self.sensor,
+            revolution: self.revolution,
+            timestamp,
+            points,
+        };
+        self.revolution += 1;
+        scan
+    }
+
+    // xorshift64 and box-muller, plenty for sensor noise
+    fn uniform(&mut self) -> f32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        (self.rng >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn gaussian(&mut self) -> f32 {
+        let u = self.uniform().max(f32::MIN_POSITIVE);
+        let v = self.uniform();
+        (-2.0 * u.ln()).sqrt() * (std::f32::consts::TAU * v).cos()
+    }
+}
+
+/// ground truth for driving around Scene::room: a lap of an oval around the
+/// pillar every 30 seconds, facing the way it's going
+pub fn lap(seconds: f32) -> Pose {
+    let (rx, ry) = (2200.0, 1300.0);
+    let t = seconds / 30.0 * std::f32::consts::TAU;
+    let (sin, cos) = t.sin_cos();
+    let heading = (ry * cos).atan2(-rx * sin).to_degrees();
+    Pose::new(rx * cos, ry * sin, heading)
+}
@@ -0,0 +1,864 @@
+// 2d slam. the front end lines every revolution up against a likelihood map
+// of the last few keyframes by brute force correlation, and behind it sits a
+// pose graph: every so often a keyframe is dropped and tied to the one before
+// by the front end's estimate. when the robot comes back somewhere it's been
+// before, a wider search against the old keyframes ties the new one to them
+// as well, and optimizing the graph spreads the drift that built up over the
+// whole loop. the map is an occupancy grid of every keyframe at its optimized
+// pose. the world frame is wherever the robot was for the first scan
+use crate::extrinsics::Extrinsics;
+use crate::geometry::{normalize_degrees, Pose};
+use crate::grid::{GridConfig, OccupancyGrid};
+use crate::scan::{Scan, ScanPoint};
+use std::collections::BTreeMap;
+
+// the map is only drawn again after a loop closure once a keyframe has moved
+// this far from where it was drawn, half a grid cell or this many degrees
+const REMAP_ANGLE: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SlamConfig {
+    pub grid: GridConfig,
+    // cell size of the likelihood map scans are matched against, millimeters.
+    // scans are thinned to one point per two cells before matching, and the
+    // coarse first pass of every search uses cells four times the size
+    pub match_resolution: f32,
+    // how far from the predicted pose the front end looks, millimeters and
+    // degrees either way
+    pub search_distance: f32,
+    pub search_angle: f32,
+    // matches scoring less than this are ignored and the prediction kept,
+    // 0..1 for how much of the scan lands on the map
+    pub min_score: f32,
+    // a new keyframe once the robot has moved or turned this much since the
+    // last one, and how many of the latest keyframes the front end matches
+    // against
+    pub keyframe_distance: f32,
+    pub keyframe_angle: f32,
+    pub local_keyframes: usize,
+    // loop closure candidates are keyframes within loop_distance of a new
+    // one and at least loop_gap keyframes older. they're searched
+    // loop_search_distance and loop_search_angle either way and the match has
+    // to score loop_score to count
+    pub loop_distance: f32,
+    pub loop_gap: usize,
+    pub loop_search_distance: f32,
+    pub loop_search_angle: f32,
+    pub loop_score: f32,
+    // how far off the front end and loop closures are expected to be,
+    // standard deviations in millimeters and degrees
+    pub odometry_sigma: (f32, f32),
+    pub loop_sigma: (f32, f32),
+    // gauss-newton iterations per optimization
+    pub iterations: usize,
+}
+
+impl Default for SlamConfig {
+    fn default() -> Self {
+        Self {
+            grid: GridConfig::default(),
+            match_resolution: 50.0,
+            search_distance: 300.0,
+            search_angle: 10.0,
+            min_score: 0.3,
+            keyframe_distance: 300.0,
+            keyframe_angle: 15.0,
+            local_keyframes: 10,
+            loop_distance: 2000.0,
+            loop_gap: 20,
+            loop_search_distance: 800.0,
+            loop_search_angle: 20.0,
+            loop_score: 0.6,
+            odometry_sigma: (20.0, 1.0),
+            loop_sigma: (50.0, 2.0),
+            iterations: 10,
+        }
+    }
+}
+
+/// what happened with one revolution
+#[derive(Debug, Clone, Copy)]
+pub struct SlamUpdate {
+    // sensor time of the scan, unwrapped milliseconds
+    pub timestamp: u64,
+    pub pose: Pose,
+    // how well the scan matched the map, 0..1
+    pub score: f32,
+    pub keyframe: bool,
+    // the keyframes a loop closure tied together, old then new
+    pub loop_closure: Option<(usize, usize)>,
+}
+
+#[derive(Debug, Clone)]
+struct Keyframe {
+    pose: Pose,
+    // where it was when it went into the map
+    mapped: Pose,
+    // where the sensor is on the robot and everything it saw, robot frame,
+    // for the map
+    origin: (f32, f32),
+    points: Vec<ScanPoint>,
+    // thinned out for matching
+    sparse: Vec<(f32, f32)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Constraint {
+    from: usize,
+    to: usize,
+    // to's pose in from's frame
+    measurement: Pose,
+    information: [f64; 3],
+    closes_loop: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Slam {
+    pub config: SlamConfig,
+    keyframes: Vec<Keyframe>,
+    constraints: Vec<Constraint>,
+    local: Option<Matcher>,
+    grid: OccupancyGrid,
+    pose: Pose,
+    // the last move, which is the guess for the next one
+    motion: Pose,
+}
+
+impl Slam {
+    pub fn new(config: SlamConfig) -> Self {
+        Self {
+            config,
+            keyframes: Vec::new(),
+            constraints: Vec::new(),
+            local: None,
+            grid: OccupancyGrid::new(config.grid),
+            pose: Pose::default(),
+            motion: Pose::default(),
+        }
+    }
+
+    /// where the robot is now
+    pub fn pose(&self) -> Pose {
+        self.pose
+    }
+
+    /// every keyframe's optimized pose, oldest first
+    pub fn keyframes(&self) -> impl Iterator<Item = Pose> + '_ {
+        self.keyframes.iter().map(|k| k.pose)
+    }
+
+    /// the poses at either end of every loop closure
+    pub fn loops(&self) -> impl Iterator<Item = (Pose, Pose)> + '_ {
+        self.constraints
+            .iter()
+            .filter(|c| c.closes_loop)
+            .map(|c| (self.keyframes[c.from].pose, self.keyframes[c.to].pose))
+    }
+
+    pub fn map(&self) -> &OccupancyGrid {
+        &self.grid
+    }
+
+    /// start again with nothing
+    pub fn reset(&mut self) {
+        *self = Self::new(self.config);
+    }
+
+    /// feed a revolution and the sensor it came from
+    pub fn push(&mut self, scan: &Scan, extrinsics: &Extrinsics) -> SlamUpdate {
+        let sparse = thin(&scan.points, self.config.match_resolution * 2.0);
+        let mut update = SlamUpdate {
+            timestamp: scan.timestamp,
+            pose: self.pose,
+            score: 0.0,
+            keyframe: false,
+            loop_closure: None,
+        };
+
+        let Some(local) = self.local.as_ref() else {
+            // the first scan is the origin
+            update.keyframe = true;
+            update.score = 1.0;
+            self.add_keyframe(scan, extrinsics, sparse);
+            return update;
+        };
+
+        let predicted = self.pose.compose(&self.motion);
+        let (matched, score) = local.search(
+            &sparse,
+            predicted,
+            self.config.search_distance,
+            self.config.search_angle,
+        );
+        let pose = if score >= self.config.min_score {
+            matched
+        } else {
+            predicted
+        };
+        self.motion = self.pose.inverse().compose(&pose);
+        self.pose = pose;
+        update.pose = pose;
+        update.score = score;
+
+        let last = self.keyframes.last().unwrap().pose;
+        let moved = last.inverse().compose(&pose);
+        if moved.x.hypot(moved.y) >= self.config.keyframe_distance
+            || moved.heading.abs() >= self.config.keyframe_angle
+        {
+            update.keyframe = true;
+            self.add_keyframe(scan, extrinsics, sparse);
+            update.loop_closure = self.close_loop();
+            update.pose = self.pose;
+        }
+        update
+    }
+
+    fn add_keyframe(&mut self, scan: &Scan, extrinsics: &Extrinsics, sparse: Vec<(f32, f32)>) {
+        let keyframe = Keyframe {
+            pose: self.pose,
+            mapped: self.pose,
+            origin: (extrinsics.x, extrinsics.y),
+            points: scan.points.clone(),
+            sparse,
+        };
+        self.grid
+            .insert_points(keyframe.origin, &keyframe.points, Some(keyframe.pose));
+        if let Some(previous) = self.keyframes.last() {
+            self.constraints.push(Constraint {
+                from: self.keyframes.len() - 1,
+                to: self.keyframes.len(),
+                measurement: previous.pose.inverse().compose(&keyframe.pose),
+                information: information(self.config.odometry_sigma),
+                closes_loop: false,
+            });
+        }
+        self.keyframes.push(keyframe);
+        self.rebuild_local();
+    }
+
+    fn rebuild_local(&mut self) {
+        let first = self
+            .keyframes
+            .len()
+            .saturating_sub(self.config.local_keyframes);
+        self.local = Some(Matcher::new(
+            &world_points(&self.keyframes[first..]),
+            self.config.match_resolution,
+        ));
+    }
+
+    // match the newest keyframe against the closest old one nearby, and if
+    // it fits, tie them together and optimize
+    fn close_loop(&mut self) -> Option<(usize, usize)> {
+        let newest = self.keyframes.len() - 1;
+        let pose = self.keyframes[newest].pose;
+        let candidate = self.keyframes[..newest.saturating_sub(self.config.loop_gap)]
+            .iter()
+            .enumerate()
+            .map(|(i, k)| (i, (k.pose.x - pose.x).hypot(k.pose.y - pose.y)))
+            .filter(|(_, distance)| *distance <= self.config.loop_distance)
+            .min_by(|a, b| a.1.total_cmp(&b.1))?
+            .0;
+
+        // the candidate and its neighbours, so there's enough to match
+        // against
+        let around = &self.keyframes[candidate.saturating_sub(2)..(candidate + 3).min(newest)];
+        let matcher = Matcher::new(&world_points(around), self.config.match_resolution);
+        let (matched, score) = matcher.search(
+            &self.keyframes[newest].sparse,
+            pose,
+            self.config.loop_search_distance,
+            self.config.loop_search_angle,
+        );
+        if score < self.config.loop_score {
+            return None;
+        }
+        self.constraints.push(Constraint {
+            from: candidate,
+            to: newest,
+            measurement: self.keyframes[candidate].pose.inverse().compose(&matched),
+            information: information(self.config.loop_sigma),
+            closes_loop: true,
+        });
+        self.optimize();
+        self.pose = self.keyframes[newest].pose;
+        // drawing the map again takes every keyframe, which isn't worth it
+        // for a loop that was already closed
+        let threshold = self.grid.config.resolution / 2.0;
+        if self.keyframes.iter().any(|k| {
+            let moved = k.mapped.inverse().compose(&k.pose);
+            moved.x.hypot(moved.y) > threshold || moved.heading.abs() > REMAP_ANGLE
+        }) {
+            self.remap();
+        }
+        self.rebuild_local();
+        Some((candidate, newest))
+    }
+
+    fn remap(&mut self) {
+        self.grid.clear();
+        for k in &mut self.keyframes {
+            self.grid.insert_points(k.origin, &k.points, Some(k.pose));
+            k.mapped = k.pose;
+        }
+    }
+
+    /// gauss-newton over every keyframe pose with the first one held still.
+    /// the normal equations are sparse, one 3x3 block per keyframe and per
+    /// constraint, so they're solved with block jacobi preconditioned
+    /// conjugate gradients
+    fn optimize(&mut self) {
+        let n = self.keyframes.len();
+        if n < 2 {
+            return;
+        }
+        // x and y in millimeters, heading in radians while solving
+        let mut poses: Vec<[f64; 3]> = self
+            .keyframes
+            .iter()
+            .map(|k| {
+                [
+                    k.pose.x as f64,
+                    k.pose.y as f64,
+                    (k.pose.heading as f64).to_radians(),
+                ]
+            })
+            .collect();
+        for _ in 0..self.config.iterations {
+            let mut h: BTreeMap<(usize, usize), Block> = BTreeMap::new();
+            let mut b = vec![[0.0; 3]; n];
+            for c in &self.constraints {
+                let (e, a, jb) = edge(&poses[c.from], &poses[c.to], &c.measurement);
+                let omega = c.information;
+                let (i, j) = (c.from, c.to);
+                for (row, jr) in [(i, &a), (j, &jb)] {
+                    for (col, jc) in [(i, &a), (j, &jb)] {
+                        let block = h.entry((row, col)).or_insert([[0.0; 3]; 3]);
+                        for r in 0..3 {
+                            for s in 0..3 {
+                                block[r][s] +=
+                                    (0..3).map(|k| jr[k][r] * omega[k] * jc[k][s]).sum::<f64>();
+                            }
+                        }
+                    }
+                    for r in 0..3 {
+                        b[row][r] += (0..3).map(|k| jr[k][r] * omega[k] * e[k]).sum::<f64>();
+                    }
+                }
+            }
+            let Some(step) = conjugate_gradient(&h, &b, n) else {
+                return;
+            };
+            let mut largest = 0.0f64;
+            for (pose, delta) in poses.iter_mut().zip(&step).skip(1) {
+                for k in 0..3 {
+                    pose[k] -= delta[k];
+                }
+                largest = largest.max(delta[0].hypot(delta[1]));
+            }
+            if largest < 0.01 {
+                break;
+            }
+        }
+        for (k, pose) in self.keyframes.iter_mut().zip(&poses) {
+            k.pose = Pose::new(
+                pose[0] as f32,
+                pose[1] as f32,
+                normalize_degrees(pose[2].to_degrees() as f32),
+            );
+        }
+    }
+}
+
+type Block = [[f64; 3]; 3];
+
+fn information((distance, angle): (f32, f32)) -> [f64; 3] {
+    let distance = (distance as f64).max(1e-3);
+    let angle = (angle as f64).to_radians().max(1e-6);
+    [
+        1.0 / (distance * distance),
+        1.0 / (distance * distance),
+        1.0 / (angle * angle),
+    ]
+}
+
+// error of one constraint, how far xj is from where the measurement puts it
+// as seen from xi, and its jacobians with respect to xi and xj
+fn edge(xi: &[f64; 3], xj: &[f64; 3], z: &Pose) -> ([f64; 3], Block, Block) {
+    let (si, ci) = xi[2].sin_cos();
+    let (dx, dy) = (xj[0] - xi[0], xj[1] - xi[1]);
+    // xj in xi's frame
+    let (lx, ly) = (ci * dx + si * dy, -si * dx + ci * dy);
+    let (zx, zy) = (z.x as f64, z.y as f64);
+    let (sz, cz) = (z.heading as f64).to_radians().sin_cos();
+    let heading = normalize_degrees((xj[2] - xi[2]).to_degrees() as f32 - z.heading) as f64;
+    let e = [
+        cz * (lx - zx) + sz * (ly - zy),
+        -sz * (lx - zx) + cz * (ly - zy),
+        heading.to_radians(),
+    ];
+    let a = [
+        [-cz * ci + sz * si, -cz * si - sz * ci, cz * ly - sz * lx],
+        [sz * ci + cz * si, sz * si - cz * ci, -sz * ly - cz * lx],
+        [0.0, 0.0, -1.0],
+    ];
+    let b = [
+        [cz * ci - sz * si, cz * si + sz * ci, 0.0],
+        [-sz * ci - cz * si, -sz * si + cz * ci, 0.0],
+        [0.0, 0.0, 1.0],
+    ];
+    (e, a, b)
+}
+
+fn invert(m: &Block) -> Option<Block> {
+    let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+    if det.abs() < 1e-300 {
+        return None;
+    }
+    let mut inverse = [[0.0; 3]; 3];
+    for (i, row) in inverse.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            let (r0, r1) = ((j + 1) % 3, (j + 2) % 3);
+            let (c0, c1) = ((i + 1) % 3, (i + 2) % 3);
+            *value = (m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]) / det;
+        }
+    }
+    Some(inverse)
+}
+
+fn apply(m: &Block, v: &[f64; 3]) -> [f64; 3] {
+    [0, 1, 2].map(|r| (0..3).map(|c| m[r][c] * v[c]).sum())
+}
+
+fn dot(a: &[[f64; 3]], b: &[[f64; 3]]) -> f64 {
+    a.iter()
+        .zip(b)
+        .map(|(x, y)| (0..3).map(|k| x[k] * y[k]).sum::<f64>())
+        .sum()
+}
+
+// solve h x = b with node 0 held at zero
+fn conjugate_gradient(
+    h: &BTreeMap<(usize, usize), Block>,
+    b: &[[f64; 3]],
+    n: usize,
+) -> Option<Vec<[f64; 3]>> {
+    let multiply = |v: &[[f64; 3]]| {
+        let mut out = vec![[0.0; 3]; n];
+        for (&(i, j), block) in h {
+            if i == 0 || j == 0 {
+                continue;
+            }
+            let product = apply(block, &v[j]);
+            for k in 0..3 {
+                out[i][k] += product[k];
+            }
+        }
+        out
+    };
+    let preconditioner: Vec<Block> = (0..n)
+        .map(|i| h.get(&(i, i)).and_then(invert).unwrap_or([[0.0; 3]; 3]))
+        .collect();
+    let precondition = |r: &[[f64; 3]]| -> Vec<[f64; 3]> {
+        r.iter()
+            .zip(&preconditioner)
+            .map(|(v, m)| apply(m, v))
+            .collect()
+    };
+
+    let mut x = vec![[0.0; 3]; n];
+    let mut r: Vec<[f64; 3]> = b.to_vec();
+    r[0] = [0.0; 3];
+    let mut z = precondition(&r);
+    let mut p = z.clone();
+    let mut rz = dot(&r, &z);
+    let tolerance = dot(&r, &r) * 1e-20;
+    for _ in 0..3 * n {
+        if dot(&r, &r) <= tolerance {
+            break;
+        }
+        let hp = multiply(&p);
+        let php = dot(&p, &hp);
+        if php.abs() < 1e-300 {
+            break;
+        }
+        let alpha = rz / php;
+        for i in 1..n {
+            for k in 0..3 {
+                x[i][k] += alpha * p[i][k];
+                r[i][k] -= alpha * hp[i][k];
+            }
+        }
+        z = precondition(&r);
+        let next = dot(&r, &z);
+        let beta = next / rz;
+        rz = next;
+        for i in 1..n {
+            for k in 0..3 {
+                p[i][k] = z[i][k] + beta * p[i][k];
+            }
+        }
+    }
+    x.iter().flatten().all(|v| v.is_finite()).then_some(x)
+}
+
+// one point per cell, so dense bits of a scan don't outvote the rest. a
+// btreemap rather than a hashmap so the same scan always comes out the same
+fn thin(points: &[ScanPoint], resolution: f32) -> Vec<(f32, f32)> {
+    let mut seen = BTreeMap::new();
+    for p in points.iter().filter(|p| p.distance != 0) {
+        let cell = (
+            (p.x / resolution).floor() as i32,
+            (p.y / resolution).floor() as i32,
+        );
+        seen.entry(cell).or_insert((p.x, p.y));
+    }
+    seen.into_values().collect()
+}
+
+fn world_points(keyframes: &[Keyframe]) -> Vec<(f32, f32)> {
+    keyframes
+        .iter()
+        .flat_map(|k| k.sparse.iter().map(|&(x, y)| k.pose.apply(x, y)))
+        .collect()
+}
+
+/// how likely there's something at every spot, highest on the points and
+/// falling off over a couple of cells so near misses still score
+#[derive(Debug, Clone)]
+struct LikelihoodMap {
+    min: (f32, f32),
+    resolution: f32,
+    width: usize,
+    height: usize,
+    values: Vec<f32>,
+}
+
+// cells either way a point spreads into
+const SPREAD: i64 = 2;
+
+impl LikelihoodMap {
+    fn new(points: &[(f32, f32)], resolution: f32) -> Self {
+        // points are spread a standard deviation of one cell
+        let margin = (SPREAD + 1) as f32 * resolution;
+        let (mut min, mut max) = ((f32::MAX, f32::MAX), (f32::MIN, f32::MIN));
+        for &(x, y) in points {
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x), max.1.max(y));
+        }
+        if points.is_empty() {
+            (min, max) = ((0.0, 0.0), (0.0, 0.0));
+        }
+        let min = (min.0 - margin, min.1 - margin);
+        let width = ((max.0 + margin - min.0) / resolution).ceil() as usize + 1;
+        let height = ((max.1 + margin - min.1) / resolution).ceil() as usize + 1;
+        let mut values = vec![0.0f32; width * height];
+        let sigma = resolution;
+        for &(x, y) in points {
+            let col = ((x - min.0) / resolution).floor() as i64;
+            let row = ((y - min.1) / resolution).floor() as i64;
+            for r in row - SPREAD..=row + SPREAD {
+                for c in col - SPREAD..=col + SPREAD {
+                    if r < 0 || c < 0 || r as usize >= height || c as usize >= width {
+                        continue;
+                    }
+                    let cx = min.0 + (c as f32 + 0.5) * resolution;
+                    let cy = min.1 + (r as f32 + 0.5) * resolution;
+                    let d2 = (cx - x).powi(2) + (cy - y).powi(2);
+                    let value = (-d2 / (2.0 * sigma * sigma)).exp();
+                    let cell = &mut values[r as usize * width + c as usize];
+                    *cell = cell.max(value);
+                }
+            }
+        }
+        Self {
+            min,
+            resolution,
+            width,
+            height,
+            values,
+        }
+    }
+
+    fn value(&self, x: f32, y: f32) -> f32 {
+        let col = ((x - self.min.0) / self.resolution).floor();
+        let row = ((y - self.min.1) / self.resolution).floor();
+        if col < 0.0 || row < 0.0 || col as usize >= self.width || row as usize >= self.height {
+            return 0.0;
+        }
+        self.values[row as usize * self.width + col as usize]
+    }
+}
+
+/// the same points at two resolutions, so searches can go coarse to fine
+#[derive(Debug, Clone)]
+struct Matcher {
+    coarse: LikelihoodMap,
+    fine: LikelihoodMap,
+}
+
+impl Matcher {
+    fn new(points: &[(f32, f32)], resolution: f32) -> Self {
+        Self {
+            coarse: LikelihoodMap::new(points, resolution * 4.0),
+            fine: LikelihoodMap::new(points, resolution),
+        }
+    }
+
+    /// best pose for points within distance millimeters and angle degrees of
+    /// center, and how well it scored on the fine map
+    fn search(
+        &self,
+        points: &[(f32, f32)],
+        center: Pose,
+        distance: f32,
+        angle: f32,
+    ) -> (Pose, f32) {
+        // half a coarse cell and 2 degrees at a time over the whole window,
+        // then down to a quarter of a fine cell around the best of those
+        let step = self.coarse.resolution / 2.0;
+        let (coarse, _) = search(&self.coarse, points, center, (distance, step), (angle, 2.0));
+        let fine = self.fine.resolution;
+        let (medium, _) = search(&self.fine, points, coarse, (step, fine / 2.0), (2.0, 0.5));
+        search(
+            &self.fine,
+            points,
+            medium,
+            (fine / 2.0, fine / 4.0),
+            (0.5, 0.125),
+        )
+    }
+}
+
+// brute force every pose within (distance, step) and (angle, step) of
+// center, returns the best and its score
+fn search(
+    map: &LikelihoodMap,
+    points: &[(f32, f32)],
+    center: Pose,
+    (distance, linear_step): (f32, f32),
+    (angle, angular_step): (f32, f32),
+) -> (Pose, f32) {
+    if points.is_empty() {
+        return (center, 0.0);
+    }
+    let linear = (distance / linear_step).round() as i32;
+    let angular = (angle / angular_step).round() as i32;
+    let mut best = (center, f32::MIN);
+    let mut rotated = Vec::with_capacity(points.len());
+    for a in -angular..=angular {
+        let heading = center.heading + a as f32 * angular_step;
+        let (sin, cos) = heading.to_radians().sin_cos();
+        rotated.clear();
+        rotated.extend(
+            points
+                .iter()
+                .map(|&(x, y)| (x * cos - y * sin, x * sin + y * cos)),
+        );
+        for i in -linear..=linear {
+            for j in -linear..=linear {
+                let ox = center.x + i as f32 * linear_step;
+                let oy = center.y + j as f32 * linear_step;
+                let score: f32 = rotated
+                    .iter()
+                    .map(|&(x, y)| map.value(x + ox, y + oy))
+                    .sum();
+                if score > best.1 {
+                    best = (Pose::new(ox, oy, normalize_degrees(heading)), score);
+                }
+            }
+        }
+    }
+    (best.0, best.1 / points.len() as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::{self, Scene, SimConfig, Simulator};
+
+    // a lap and a bit of the simulated room at 10 scans a second. returns the
+    // worst distance and angle between any keyframe and where it really was,
+    // and how many loops were closed
+    fn drive(config: SlamConfig) -> (f32, f32, usize) {
+        let mut sim = Simulator::new(Scene::room(), SimConfig::default());
+        let mut slam = Slam::new(config);
+        let start = sim::lap(0.0);
+        let mut truths = Vec::new();
+        let mut loops = 0;
+        for i in 0..=330 {
+            let seconds = i as f32 * 0.1;
+            let truth = sim::lap(seconds);
+            let scan = sim.scan(truth, (seconds * 1000.0) as u64);
+            let update = slam.push(&scan, &Extrinsics::default());
+            if update.keyframe {
+                truths.push(start.inverse().compose(&truth));
+            }
+            loops += update.loop_closure.is_some() as usize;
+        }
+        let (mut distance, mut angle) = (0.0f32, 0.0f32);
+        for (pose, truth) in slam.keyframes().zip(&truths) {
+            distance = distance.max((pose.x - truth.x).hypot(pose.y - truth.y));
+            angle = angle.max(normalize_degrees(pose.heading - truth.heading).abs());
+        }
+        (distance, angle, loops)
+    }
+
+    #[test]
+    fn closes_the_loop_around_the_room() {
+        let (distance, angle, loops) = drive(SlamConfig::default());
+        assert!(loops > 0, "no loop closures");
+        assert!(
+            distance < 40.0 && angle < 1.0,
+            "off by {distance} mm, {angle} deg"
+        );
+        // the front end and the odometry constraints on their own
+        let (open, _, _) = drive(SlamConfig {
+            loop_distance: 0.0,
+            ..SlamConfig::default()
+        });
+        assert!(
+            distance < open * 0.8,
+            "{distance} mm with loops, {open} mm without"
+        );
+    }
+
+    fn as_vector(pose: Pose) -> [f64; 3] {
+        [
+            pose.x as f64,
+            pose.y as f64,
+            (pose.heading as f64).to_radians(),
+        ]
+    }
+
+    #[test]
+    fn edge_is_zero_at_the_measurement() {
+        let from = Pose::new(100.0, -50.0, 20.0);
+        let measurement = Pose::new(400.0, 200.0, 30.0);
+        let to = from.compose(&measurement);
+        let (e, _, _) = edge(&as_vector(from), &as_vector(to), &measurement);
+        assert!(
+            e[0].abs() < 1e-3 && e[1].abs() < 1e-3 && e[2].abs() < 1e-5,
+            "{e:?}"
+        );
+    }
+
+    #[test]
+    fn edge_jacobians_match_finite_differences() {
+        let xi = as_vector(Pose::new(100.0, -50.0, 20.0));
+        let xj = as_vector(Pose::new(600.0, 300.0, 65.0));
+        let measurement = Pose::new(400.0, 200.0, 30.0);
+        let (_, a, b) = edge(&xi, &xj, &measurement);
+        for (which, jacobian) in [(0, a), (1, b)] {
+            for k in 0..3 {
+                let h = if k == 2 { 1e-3 } else { 1e-2 };
+                let error = |sign: f64| {
+                    let (mut xi, mut xj) = (xi, xj);
+                    let x = if which == 0 { &mut xi } else { &mut xj };
+                    x[k] += sign * h;
+                    edge(&xi, &xj, &measurement).0
+                };
+                let (plus, minus) = (error(1.0), error(-1.0));
+                for r in 0..3 {
+                    let numeric = (plus[r] - minus[r]) / (2.0 * h);
+                    let analytic = jacobian[r][k];
+                    assert!(
+                        (numeric - analytic).abs() < 1e-3 * analytic.abs().max(1.0),
+                        "d e{r} / d x{which}[{k}]: {numeric} numerically, {analytic} analytically"
+                    );
+                }
+            }
+        }
+    }
+
+    // a 2 m square driven counterclockwise and back to the start
+    fn square() -> Vec<Pose> {
+        vec![
+            Pose::new(0.0, 0.0, 0.0),
+            Pose::new(2000.0, 0.0, 90.0),
+            Pose::new(2000.0, 2000.0, 180.0),
+            Pose::new(0.0, 2000.0, -90.0),
+        ]
+    }
+
+    // a graph over the square with odometry between neighbours and a loop
+    // closure from the first corner to the last, all measured from truth
+    // but with every move turned by bias degrees
+    fn graph(truth: &[Pose], bias: f32) -> Slam {
+        let mut slam = Slam::new(SlamConfig::default());
+        let mut pose = truth[0];
+        for (i, t) in truth.iter().enumerate() {
+            if i > 0 {
+                let mut measurement = truth[i - 1].inverse().compose(t);
+                measurement.heading += bias;
+                pose = pose.compose(&measurement);
+                slam.constraints.push(Constraint {
+                    from: i - 1,
+                    to: i,
+                    measurement,
+                    information: information(slam.config.odometry_sigma),
+                    closes_loop: false,
+                });
+            }
+            slam.keyframes.push(Keyframe {
+                pose,
+                mapped: pose,
+                origin: (0.0, 0.0),
+                points: Vec::new(),
+                sparse: Vec::new(),
+            });
+        }
+        let last = truth.len() - 1;
+        slam.constraints.push(Constraint {
+            from: 0,
+            to: last,
+            measurement: truth[0].inverse().compose(&truth[last]),
+            information: information(slam.config.loop_sigma),
+            closes_loop: true,
+        });
+        slam
+    }
+
+    fn worst(slam: &Slam, truth: &[Pose]) -> f32 {
+        slam.keyframes()
+            .zip(truth)
+            .map(|(pose, truth)| (pose.x - truth.x).hypot(pose.y - truth.y))
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn optimize_pulls_a_drifted_square_together() {
+        let truth = square();
+        let mut slam = graph(&truth, 3.0);
+        let before = worst(&slam, &truth);
+        slam.optimize();
+        let after = worst(&slam, &truth);
+        // the first corner is held still
+        assert_eq!(slam.keyframes[0].pose, truth[0]);
+        assert!(after < before / 3.0, "{before} mm before, {after} mm after");
+    }
+
+    #[test]
+    fn optimize_finds_the_square_from_a_bad_start() {
+        // consistent measurements, so the answer is exact however far off
+        // the starting poses are
+        let truth = square();
+        let mut slam = graph(&truth, 0.0);
+        for (i, k) in slam.keyframes.iter_mut().enumerate().skip(1) {
+            k.pose = k
+                .pose
+                .compose(&Pose::new(150.0 * i as f32, -80.0, 10.0 * i as f32));
+        }
+        slam.optimize();
+        for (pose, truth) in slam.keyframes().zip(&truth) {
+            let distance = (pose.x - truth.x).hypot(pose.y - truth.y);
+            let angle = normalize_degrees(pose.heading - truth.heading).abs();
+            assert!(
+                distance < 1.0 && angle < 0.01,
+                "off by {distance} mm, {angle} deg"
+            );
+        }
+    }
+}
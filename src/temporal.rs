@@ -0,0 +1,253 @@
+// temporal filtering across revolutions. every revolution is binned onto a
+// fixed angular grid by each point's own angle, which keeps revolutions lined
+// up even though packet start angles drift from one revolution to the next.
+// each bin then gets the median of its last few revolutions
+use crate::extrinsics::Extrinsics;
+use crate::ld19::point::polar_to_cartesian;
+use crate::profile::median;
+use crate::scan::{Scan, ScanPoint};
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy)]
+pub struct TemporalConfig {
+    // how many revolutions the median is taken over
+    pub window: usize,
+    // bin width in degrees, a little over the ld19's ~0.8 degree spacing
+    pub resolution: f32,
+    // how far a return can be from its bin's median before it's an outlier,
+    // millimeters plus a fraction of the median
+    pub outlier_jump: f32,
+    pub outlier_ratio: f32,
+}
+
+impl Default for TemporalConfig {
+    fn default() -> Self {
+        Self {
+            window: 5,
+            resolution: 1.0,
+            outlier_jump: 50.0,
+            outlier_ratio: 0.03,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Filtered {
+    pub sensor: u8,
+    pub revolution: u32,
+    pub timestamp: u64,
+    // median range per bin in millimeters, None unless at least half the
+    // revolutions in the window had a return there
+    pub ranges: Vec<Option<f32>>,
+    // one per point of the scan that was pushed, true if it's an outlier
+    pub outliers: Vec<bool>,
+    resolution: f32,
+}
+
+impl Filtered {
+    /// the raw sensor angle at the middle of a bin, degrees
+    pub fn bearing(&self, bin: usize) -> f32 {
+        bin as f32 * self.resolution
+    }
+
+    /// the median ranges as points in the robot frame
+    pub fn points(&self, extrinsics: &Extrinsics) -> Vec<ScanPoint> {
+        self.ranges
+            .iter()
+            .enumerate()
+            .filter_map(|(bin, range)| {
+                let range = (*range)?;
+                let angle = self.bearing(bin);
+                let (x, y) = polar_to_cartesian(range as u32, angle);
+                let (x, y) = extrinsics.apply(x, y);
+                Some(ScanPoint {
+                    x,
+                    y,
+                    angle,
+                    distance: range as u16,
+                    intensity: 0,
+                    sensor: self.sensor,
+                })
+            })
+            .collect()
+    }
+
+    pub fn outlier_count(&self) -> usize {
+        self.outliers.iter().filter(|o| **o).count()
+    }
+}
+
+/// per angle median over the last few revolutions of one sensor
+#[derive(Debug, Clone)]
+pub struct TemporalFilter {
+    pub config: TemporalConfig,
+    bins: usize,
+    // newest revolution at the back, one range per bin
+    history: VecDeque<Vec<Option<f32>>>,
+}
+
+impl TemporalFilter {
+    pub fn new(config: TemporalConfig) -> Self {
+        let resolution = config.resolution.clamp(0.1, 360.0);
+        Self {
+            config: TemporalConfig {
+                window: config.window.max(1),
+                resolution,
+                ..config
+            },
+            bins: (360.0 / resolution).round() as usize,
+            history: VecDeque::with_capacity(config.window.max(1)),
+        }
+    }
+
+    /// which bin a raw sensor angle lands in. bins are centered on multiples
+    /// of the resolution
+    pub fn bin_of(&self, angle: f32) -> usize {
+        (angle.rem_euclid(360.0) / self.config.resolution).round() as usize % self.bins
+    }
+
+    pub fn clear(&mut self) {
+        self.history.clear();
+    }
+
+    /// add a revolution and get the filtered ranges back, along with which of
+    /// its points are outliers
+    pub fn push(&mut self, scan: &Scan) -> Filtered {
+        // a bin can get more than one point in a revolution, take the median
+        let mut binned: Vec<Vec<f32>> = vec![Vec::new(); self.bins];
+        for p in scan.points.iter().filter(|p| p.distance != 0) {
+            binned[self.bin_of(p.angle)].push(p.distance as f32);
+        }
+        if self.history.len() >= self.config.window {
+            self.history.pop_front();
+        }
+        self.history
+            .push_back(binned.into_iter().map(median).collect());
+
+        let ranges: Vec<Option<f32>> = (0..self.bins)
+            .map(|bin| {
+                let returns: Vec<f32> = self.history.iter().filter_map(|r| r[bin]).collect();
+                // a return in only a few revolutions is more likely noise
+                // than something that's actually there
+                if returns.len() * 2 < self.history.len() {
+                    None
+                } else {
+                    median(returns)
+                }
+            })
+            .collect();
+
+        let outliers = scan
+            .points
+            .iter()
+            .map(|p| {
+                if p.distance == 0 {
+                    return false;
+                }
+                match ranges[self.bin_of(p.angle)] {
+                    Some(median) => {
+                        let tolerance =
+                            self.config.outlier_jump + median * self.config.outlier_ratio;
+                        (p.distance as f32 - median).abs() > tolerance
+                    }
+                    None => true,
+                }
+            })
+            .collect();
+
+        Filtered {
+            sensor: scan.sensor,
+            revolution: scan.revolution,
+            timestamp: scan.timestamp,
+            ranges,
+            outliers,
+            resolution: self.config.resolution,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(angle: f32, distance: u16) -> ScanPoint {
+        ScanPoint {
+            x: 0.0,
+            y: 0.0,
+            angle,
+            distance,
+            intensity: 200,
+            sensor: 0,
+        }
+    }
+
+    fn scan(revolution: u32, points: Vec<ScanPoint>) -> Scan {
+        Scan {
+            sensor: 0,
+            revolution,
+            timestamp: revolution as u64 * 100,
+            points,
+        }
+    }
+
+    #[test]
+    fn medians_over_the_window_and_flags_spikes() {
+        let mut filter = TemporalFilter::new(TemporalConfig::default());
+        let mut spiked = None;
+        for (i, range) in [1000, 1010, 5000, 990, 1005].into_iter().enumerate() {
+            let filtered = filter.push(&scan(i as u32, vec![point(10.1, range), point(20.0, 0)]));
+            if range == 5000 {
+                spiked = Some(filtered);
+            }
+        }
+        // 1000, 1010 and 5000 so far, the spike is well off the median
+        let spiked = spiked.unwrap();
+        assert_eq!(spiked.ranges[10], Some(1010.0));
+        assert_eq!(spiked.outliers, vec![true, false]);
+        assert_eq!(spiked.outlier_count(), 1);
+
+        let filtered = filter.push(&scan(5, vec![point(9.9, 1000)]));
+        // 1010, 5000, 990, 1005 and 1000
+        assert_eq!(filtered.ranges[10], Some(1005.0));
+        assert_eq!(filtered.outliers, vec![false]);
+        assert_eq!(filtered.ranges[20], None);
+    }
+
+    #[test]
+    fn old_revolutions_fall_out_of_the_window() {
+        let mut filter = TemporalFilter::new(TemporalConfig {
+            window: 3,
+            ..TemporalConfig::default()
+        });
+        let mut last = None;
+        for (i, range) in [1000, 1000, 1000, 2000, 2000].into_iter().enumerate() {
+            last = Some(filter.push(&scan(i as u32, vec![point(45.0, range)])));
+        }
+        assert_eq!(last.unwrap().ranges[45], Some(2000.0));
+        filter.clear();
+        let filtered = filter.push(&scan(5, vec![point(45.0, 3000)]));
+        assert_eq!(filtered.ranges[45], Some(3000.0));
+    }
+
+    #[test]
+    fn returns_in_only_a_few_revolutions_are_dropped() {
+        let mut filter = TemporalFilter::new(TemporalConfig::default());
+        let mut filtered = None;
+        for i in 0..5 {
+            let mut points = vec![point(90.0, 2000)];
+            if i == 4 {
+                points.push(point(180.0, 700));
+            }
+            filtered = Some(filter.push(&scan(i, points)));
+        }
+        let filtered = filtered.unwrap();
+        assert_eq!(filtered.ranges[180], None);
+        // with nothing steady to compare against, it's an outlier
+        assert_eq!(filtered.outliers, vec![false, true]);
+
+        let points = filtered.points(&Extrinsics::default());
+        assert_eq!(points.len(), 1);
+        assert_eq!((points[0].angle, points[0].distance), (90.0, 2000));
+        assert!((points[0].x.hypot(points[0].y) - 2000.0).abs() < 1.0);
+    }
+}
@@ -0,0 +1,374 @@
+// multi object tracking. every object gets a constant velocity kalman filter,
+// new detections are matched to the predicted tracks by gated nearest
+// neighbour, and tracks are born tentative and die after going unseen.
+// time comes from the sensor's own unwrapped timestamps rather than when the
+// data happened to arrive
+use crate::cluster::Cluster;
+
+#[derive(Debug, Clone, Copy)]
+pub struct TrackConfig {
+    // farthest a detection can be from a track's predicted position and
+    // still be matched to it, millimeters
+    pub gate: f32,
+    // how many updates a track has to be seen in before it's confirmed, and
+    // how many in a row it can go unseen before it's dropped. tentative
+    // tracks are dropped the first time they're missed
+    pub min_hits: u32,
+    pub max_misses: u32,
+    // how hard objects are expected to accelerate, mm/s^2. more lets tracks
+    // turn quicker but makes the velocity noisier
+    pub acceleration: f32,
+    // how far off a detection's position is expected to be, millimeters
+    pub measurement_noise: f32,
+}
+
+impl Default for TrackConfig {
+    fn default() -> Self {
+        Self {
+            gate: 600.0,
+            min_hits: 3,
+            max_misses: 10,
+            acceleration: 2000.0,
+            measurement_noise: 50.0,
+        }
+    }
+}
+
+type Matrix = [[f32; 4]; 4];
+
+// x, y, vx, vy
+#[derive(Debug, Clone, Copy)]
+struct Kalman {
+    state: [f32; 4],
+    covariance: Matrix,
+}
+
+impl Kalman {
+    fn new(x: f32, y: f32, config: &TrackConfig) -> Self {
+        let position = config.measurement_noise.powi(2);
+        // nothing is known about the velocity yet, walking pace is a fair
+        // guess at how big it could be
+        let velocity = 1500.0f32.powi(2);
+        let mut covariance = [[0.0; 4]; 4];
+        covariance[0][0] = position;
+        covariance[1][1] = position;
+        covariance[2][2] = velocity;
+        covariance[3][3] = velocity;
+        Self {
+            state: [x, y, 0.0, 0.0],
+            covariance,
+        }
+    }
+
+    /// move the state dt seconds forward
+    fn predict(&mut self, dt: f32, config: &TrackConfig) {
+        if dt <= 0.0 {
+            return;
+        }
+        let mut f: Matrix = identity();
+        f[0][2] = dt;
+        f[1][3] = dt;
+        self.state[0] += self.state[2] * dt;
+        self.state[1] += self.state[3] * dt;
+
+        // white noise acceleration
+        let q = config.acceleration.powi(2);
+        let (dt2, dt3, dt4) = (dt * dt, dt * dt * dt, dt * dt * dt * dt);
+        let mut noise = [[0.0; 4]; 4];
+        for axis in 0..2 {
+            let (p, v) = (axis, axis + 2);
+            noise[p][p] = dt4 / 4.0 * q;
+            noise[p][v] = dt3 / 2.0 * q;
+            noise[v][p] = dt3 / 2.0 * q;
+            noise[v][v] = dt2 * q;
+        }
+        self.covariance = add(
+            &multiply(&multiply(&f, &self.covariance), &transpose(&f)),
+            &noise,
+        );
+    }
+
+    /// fold in a measured position
+    fn update(&mut self, x: f32, y: f32, config: &TrackConfig) {
+        let r = config.measurement_noise.powi(2);
+        let p = &self.covariance;
+        // only the position is measured, so the innovation covariance is the
+        // top left 2x2 of p plus the measurement noise
+        let s = [[p[0][0] + r, p[0][1]], [p[1][0], p[1][1] + r]];
+        let det = s[0][0] * s[1][1] - s[0][1] * s[1][0];
+        if det.abs() < f32::EPSILON {
+            return;
+        }
+        let inverse = [
+            [s[1][1] / det, -s[0][1] / det],
+            [-s[1][0] / det, s[0][0] / det],
+        ];
+        // gain is p h^T s^-1, and p h^T is just the first two columns of p
+        let mut gain = [[0.0; 2]; 4];
+        for (i, row) in gain.iter_mut().enumerate() {
+            for (j, k) in row.iter_mut().enumerate() {
+                *k = p[i][0] * inverse[0][j] + p[i][1] * inverse[1][j];
+            }
+        }
+        let innovation = [x - self.state[0], y - self.state[1]];
+        for (i, row) in gain.iter().enumerate() {
+            self.state[i] += row[0] * innovation[0] + row[1] * innovation[1];
+        }
+        // p = (i - k h) p
+        let mut updated = *p;
+        for (i, row) in updated.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value -= gain[i][0] * p[0][j] + gain[i][1] * p[1][j];
+            }
+        }
+        self.covariance = updated;
+    }
+}
+
+fn identity() -> Matrix {
+    let mut m = [[0.0; 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+    m
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.0; 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+fn transpose(a: &Matrix) -> Matrix {
+    let mut m = [[0.0; 4]; 4];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = a[j][i];
+        }
+    }
+    m
+}
+
+fn add(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = *a;
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value += b[i][j];
+        }
+    }
+    m
+}
+
+#[derive(Debug, Clone)]
+pub struct Track {
+    pub id: u32,
+    // robot frame, millimeters and mm/s
+    pub position: (f32, f32),
+    pub velocity: (f32, f32),
+    pub hits: u32,
+    pub misses: u32,
+    // sensor time it was last seen, unwrapped milliseconds
+    pub last_seen: u64,
+    kalman: Kalman,
+}
+
+impl Track {
+    pub fn confirmed(&self, config: &TrackConfig) -> bool {
+        self.hits >= config.min_hits
+    }
+
+    /// mm/s
+    pub fn speed(&self) -> f32 {
+        self.velocity.0.hypot(self.velocity.1)
+    }
+
+    fn sync(&mut self) {
+        let s = self.kalman.state;
+        self.position = (s[0], s[1]);
+        self.velocity = (s[2], s[3]);
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Tracker {
+    pub config: TrackConfig,
+    tracks: Vec<Track>,
+    next_id: u32,
+    // unwrapped milliseconds of the last update
+    last_update: Option<u64>,
+}
+
+impl Tracker {
+    pub fn new(config: TrackConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// every track, tentative ones included
+    pub fn tracks(&self) -> &[Track] {
+        &self.tracks
+    }
+
+    pub fn confirmed(&self) -> impl Iterator<Item = &Track> {
+        self.tracks.iter().filter(|t| t.confirmed(&self.config))
+    }
+
+    /// track cluster centroids seen at timestamp, unwrapped sensor
+    /// milliseconds like Scan::timestamp
+    pub fn update(&mut self, timestamp: u64, clusters: &[Cluster]) {
+        let detections: Vec<(f32, f32)> = clusters.iter().map(|c| c.centroid).collect();
+        self.update_positions(timestamp, &detections);
+    }
+
+    /// same as update but with plain positions
+    pub fn update_positions(&mut self, timestamp: u64, detections: &[(f32, f32)]) {
+        let dt = self
+            .last_update
+            .map_or(0.0, |last| timestamp.saturating_sub(last) as f32 / 1000.0);
+        self.last_update = Some(timestamp);
+        for track in &mut self.tracks {
+            track.kalman.predict(dt, &self.config);
+            track.sync();
+        }
+
+        // gated nearest neighbour, closest pairs first
+        let mut pairs: Vec<(f32, usize, usize)> = Vec::new();
+        for (t, track) in self.tracks.iter().enumerate() {
+            for (d, detection) in detections.iter().enumerate() {
+                let distance =
+                    (track.position.0 - detection.0).hypot(track.position.1 - detection.1);
+                if distance <= self.config.gate {
+                    pairs.push((distance, t, d));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut track_matched = vec![false; self.tracks.len()];
+        let mut detection_matched = vec![false; detections.len()];
+        for (_, t, d) in pairs {
+            if track_matched[t] || detection_matched[d] {
+                continue;
+            }
+            track_matched[t] = true;
+            detection_matched[d] = true;
+            let track = &mut self.tracks[t];
+            track
+                .kalman
+                .update(detections[d].0, detections[d].1, &self.config);
+            track.sync();
+            track.hits += 1;
+            track.misses = 0;
+            track.last_seen = timestamp;
+        }
+
+        for (track, matched) in self.tracks.iter_mut().zip(&track_matched) {
+            if !matched {
+                track.misses += 1;
+            }
+        }
+        let config = self.config;
+        self.tracks.retain(|t| {
+            if t.confirmed(&config) {
+                t.misses <= config.max_misses
+            } else {
+                t.misses == 0
+            }
+        });
+
+        for (detection, _) in detections.iter().zip(detection_matched).filter(|(_, m)| !m) {
+            let mut track = Track {
+                id: self.next_id,
+                position: *detection,
+                velocity: (0.0, 0.0),
+                hits: 1,
+                misses: 0,
+                last_seen: timestamp,
+                kalman: Kalman::new(detection.0, detection.1, &self.config),
+            };
+            track.sync();
+            self.tracks.push(track);
+            self.next_id = self.next_id.wrapping_add(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 100 ms apart, like ld19 revolutions
+    fn walk(tracker: &mut Tracker, steps: u64, velocity: (f32, f32)) {
+        for i in 0..steps {
+            let t = i as f32 * 0.1;
+            let position = (1000.0 + velocity.0 * t, -500.0 + velocity.1 * t);
+            tracker.update_positions(i * 100, &[position]);
+        }
+    }
+
+    #[test]
+    fn estimates_a_constant_velocity() {
+        let mut tracker = Tracker::default();
+        walk(&mut tracker, 50, (800.0, -300.0));
+        let tracks: Vec<&Track> = tracker.confirmed().collect();
+        assert_eq!(tracks.len(), 1);
+        let track = tracks[0];
+        assert!(
+            (track.velocity.0 - 800.0).abs() < 20.0,
+            "{:?}",
+            track.velocity
+        );
+        assert!(
+            (track.velocity.1 + 300.0).abs() < 20.0,
+            "{:?}",
+            track.velocity
+        );
+        assert!((track.position.0 - (1000.0 + 800.0 * 4.9)).abs() < 10.0);
+        assert_eq!(track.id, 0);
+    }
+
+    #[test]
+    fn confirms_after_min_hits() {
+        let mut tracker = Tracker::default();
+        walk(&mut tracker, 2, (500.0, 0.0));
+        assert_eq!(tracker.tracks().len(), 1);
+        assert_eq!(tracker.confirmed().count(), 0);
+        tracker.update_positions(200, &[(1100.0, -500.0)]);
+        assert_eq!(tracker.confirmed().count(), 1);
+    }
+
+    #[test]
+    fn drops_tracks_after_missing_them() {
+        let mut tracker = Tracker::default();
+        // tentative ones go the first time they're missed
+        tracker.update_positions(0, &[(0.0, 0.0)]);
+        tracker.update_positions(100, &[]);
+        assert!(tracker.tracks().is_empty());
+
+        walk(&mut tracker, 5, (0.0, 0.0));
+        let max_misses = tracker.config.max_misses as u64;
+        for i in 0..max_misses {
+            tracker.update_positions(500 + i * 100, &[]);
+        }
+        assert_eq!(tracker.confirmed().count(), 1);
+        tracker.update_positions(500 + max_misses * 100, &[]);
+        assert!(tracker.tracks().is_empty());
+    }
+
+    #[test]
+    fn starts_a_new_track_outside_the_gate() {
+        let mut tracker = Tracker::default();
+        walk(&mut tracker, 5, (0.0, 0.0));
+        let gate = tracker.config.gate;
+        tracker.update_positions(500, &[(1000.0 + gate * 1.5, -500.0)]);
+        let ids: Vec<u32> = tracker.tracks().iter().map(|t| t.id).collect();
+        assert_eq!(ids, vec![0, 1]);
+        assert_eq!(tracker.tracks()[0].misses, 1);
+    }
+}
@@ -1,27 +1,86 @@
-use pixels::{Pixels, SurfaceTexture};
-use raqote::{DrawOptions, DrawTarget, PathBuilder, SolidSource, Source};
+use crate::colormap::{
+    ColorBy, ColorMap, AGE_MAX_SECS, INTENSITY_MAX, RANGE_MAX_MM, REVOLUTION_CYCLE,
+};
+use crate::compare::{CompareLayout, Comparison};
+use crate::inspector::Inspector;
+use crate::mapping::{Mapper, MappingUpdate};
+use crate::measure::{Measure, MeasurePoint};
+use crate::playback::{Playback, Step};
+use crate::screenshot::{self, Recorder};
+use core::fmt;
+use font_kit::family_name::FamilyName;
+use font_kit::font::Font;
+use font_kit::properties::Properties;
+use font_kit::source::SystemSource;
+use lidar::background::{BackgroundConfig, BackgroundModel};
+use lidar::cluster::{self, Cluster, ClusterConfig, ClusterMethod};
+use lidar::extrinsics::Extrinsics;
+use lidar::filter::CrosstalkStats;
+use lidar::geometry::{normalize_degrees, Polygon, Pose};
+use lidar::grid::{Cell, GridConfig, OccupancyGrid};
+use lidar::health::SensorHealth;
+use lidar::icp::{IcpConfig, Odometry};
+use lidar::ld19::decoder::Frame;
+use lidar::lines::{self, LineConfig, Segment};
+use lidar::people::{self, Circle, LegConfig, PeopleTracker, PersonTrack};
+use lidar::profile::RangeProfile;
+use lidar::query::{Gap, ScanIndex};
+use lidar::raster;
+use lidar::safety::FieldLevel;
+use lidar::scan::{Fusion, Scan, ScanPoint};
+use lidar::slam::SlamConfig;
+use lidar::temporal::{TemporalConfig, TemporalFilter};
+use lidar::track::{Track, Tracker};
+use lidar::zone::ZoneMonitor;
+use pixels::{wgpu, Pixels, PixelsBuilder, SurfaceTexture};
+use raqote::{
+    AntialiasMode, DrawOptions, DrawTarget, IntPoint, IntRect, PathBuilder, SolidSource, Source,
+    StrokeStyle,
+};
+use std::collections::VecDeque;
 use std::sync::Arc;
+use std::time::Instant;
 use winit::application::ApplicationHandler;
-use winit::dpi::PhysicalSize;
-use winit::event::{DeviceEvent, DeviceId, ElementState, KeyEvent, WindowEvent};
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::event::{DeviceEvent, DeviceId, ElementState, KeyEvent, MouseButton, WindowEvent};
 use winit::event_loop::ActiveEventLoop;
 use winit::keyboard::{KeyCode, PhysicalKey};
-use winit::window::{Window, WindowAttributes, WindowId};
+use winit::window::{Fullscreen, Window, WindowAttributes, WindowId};
 
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy)]
 pub struct DrawPoint {
     pub x: f32,
     pub y: f32,
+    // Color used when coloring by source
     pub r: u8,
     pub g: u8,
     pub b: u8,
+    // Raw measurement, used by the color maps and the polar view
+    pub angle: f32,
+    pub distance: u16,
+    pub intensity: u8,
+    pub revolution: u32,
+    pub sensor: u8,
 }
 
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub enum UserEvent {
     DrawPointBuffer(Vec<DrawPoint>),
+    // Everything off the wire and which sensor it came from, for the packet
+    // inspector and sensor health
+    Frame(u8, Frame),
+    // A full revolution from one sensor
+    Scan(Scan),
+    // Running interference filter totals for one sensor
+    Crosstalk(u8, CrosstalkStats),
+    // One sensor's safety field state changed
+    Safety(u8, FieldLevel),
+    // One sensor's safety state reached --exit-on
+    Exit(u8, FieldLevel),
+    // Where the simulated sensor really is, world frame
+    GroundTruth(Pose),
 }
 
 #[derive(Default)]
@@ -32,17 +91,328 @@ pub struct State<'win> {
     pub window: Option<Arc<Window>>,
     pub framebuffer: Option<Pixels<'win>>,
     pub surface: Option<Surface>,
+    // last known cursor position, in window pixels
+    pub cursor: Option<PhysicalPosition<f64>>,
+    // set while frames are being recorded
+    pub recorder: Option<Recorder>,
+    pub playback: Playback,
+    pub inspector: Inspector,
+    // One entry per sensor, in the same order as the sensor indices
+    pub sensor_names: Vec<String>,
+    pub health: Vec<SensorHealth>,
+    pub crosstalk: Vec<Option<CrosstalkStats>>,
+    // Median filtering across revolutions, one filter per sensor while on
+    pub temporal_config: TemporalConfig,
+    pub temporal: Option<Vec<TemporalFilter>>,
+    pub extrinsics: Vec<Extrinsics>,
+    // Line segments pulled out of every revolution while on
+    pub lines: Option<LineConfig>,
+    // Clusters of the fused scans while on
+    pub clusters: Option<ClusterConfig>,
+    // Legs and the people they belong to while on
+    pub people: Option<PeopleTracker>,
+    // Moving objects while on
+    pub tracker: Option<Tracker>,
+    // Background subtraction per sensor while on, and the zones it feeds
+    pub background_config: BackgroundConfig,
+    pub background: Option<Vec<BackgroundModel>>,
+    pub foreground: Vec<Vec<ScanPoint>>,
+    pub zones: ZoneMonitor,
+    // What a new occupancy grid is made with, the grid itself lives on the
+    // surface
+    pub grid_config: GridConfig,
+    // Lidar odometry on the first sensor while on. when simulating, the true
+    // pose and what it was when odometry started
+    pub icp_config: IcpConfig,
+    pub odometry: Option<Odometry>,
+    pub simulated: bool,
+    pub truth: Option<Pose>,
+    pub truth_origin: Option<Pose>,
+    // Mapping with loop closure on the first sensor while on, instead of
+    // odometry. the map and path it makes go to the surface
+    pub slam_config: SlamConfig,
+    pub slam: Option<Mapper>,
+    // Latest safety state from each sensor
+    pub safety: Vec<FieldLevel>,
+    // Show the closest obstacle ahead and the free directions
+    pub queries: bool,
+    pub fusion: Fusion,
+    // What the process exits with once the event loop is done, set when the
+    // safety state reaches --exit-on
+    pub exit_code: Option<i32>,
 }
 
 impl State<'_> {
+    fn print_inspected(&self) {
+        match self.inspector.selected() {
+            Some(inspected) => println!("[inspector]\n{inspected}"),
+            None => println!("[inspector] nothing received yet"),
+        }
+    }
+
+    fn print_backlog(&self) {
+        if !self.playback.paused {
+            println!("[playback] not paused, nothing to step through");
+            return;
+        }
+        println!(
+            "[playback] {} packets queued, {} dropped",
+            self.playback.backlog(),
+            self.playback.dropped()
+        );
+    }
+
+    fn step(&mut self, step: Step) {
+        self.surface.as_mut().unwrap().draw(step.points);
+        for scan in step.scans {
+            self.update_scan(scan);
+        }
+    }
+
+    // everything worked out from a full revolution: odometry, slam, the
+    // filters and the overlays
+    fn update_scan(&mut self, scan: Scan) {
+        let sensor = scan.sensor as usize;
+        if let Some(odometry) = self.odometry.as_mut().filter(|_| sensor == 0) {
+            match odometry.push(&scan) {
+                // the first scan is where the path starts
+                None => self.truth_origin = self.truth,
+                Some(step) => {
+                    if !step.matched {
+                        println!("[odometry] no match at {} ms, holding", step.timestamp);
+                    }
+                    let surface = self.surface.as_mut().unwrap();
+                    surface.trajectory.push(step.pose);
+                    if let (Some(origin), Some(truth)) = (self.truth_origin, self.truth) {
+                        surface.ground_truth.push(origin.inverse().compose(&truth));
+                    }
+                }
+            }
+        }
+        if let Some(mapper) = self.slam.as_ref() {
+            let surface = self.surface.as_mut().unwrap();
+            if sensor == 0 {
+                let extrinsics = self.extrinsics.first().copied().unwrap_or_default();
+                if mapper.push(scan.clone(), extrinsics) {
+                    // the first scan is where the path starts
+                    if self.truth_origin.is_none() {
+                        self.truth_origin = self.truth;
+                    }
+                    if let (Some(origin), Some(truth)) = (self.truth_origin, self.truth) {
+                        surface.ground_truth.push(origin.inverse().compose(&truth));
+                    }
+                } else {
+                    println!(
+                        "[slam] falling behind, dropped the scan at {} ms",
+                        scan.timestamp
+                    );
+                }
+            }
+            // whatever the mapping thread has finished since the last scan
+            for MappingUpdate { update, snapshot } in mapper.updates() {
+                if update.score < self.slam_config.min_score {
+                    println!(
+                        "[slam] poor match at {} ms, score {:.2}",
+                        update.timestamp, update.score
+                    );
+                }
+                if let Some((old, new)) = update.loop_closure {
+                    println!("[slam] loop closed between keyframes {old} and {new}");
+                }
+                // the path is every keyframe and then where it is now
+                match snapshot {
+                    Some(snapshot) => {
+                        surface.set_slam_map(Some(snapshot.grid));
+                        surface.loops = snapshot.loops;
+                        surface.trajectory = snapshot.keyframes;
+                    }
+                    None => {
+                        surface.trajectory.pop();
+                    }
+                }
+                surface.trajectory.push(update.pose);
+            }
+        }
+        if let Some(filter) = self.temporal.as_mut().and_then(|t| t.get_mut(sensor)) {
+            let filtered = filter.push(&scan);
+            let extrinsics = self.extrinsics.get(sensor).copied().unwrap_or_default();
+            let surface = self.surface.as_mut().unwrap();
+            if surface.filtered.len() <= sensor {
+                surface.filtered.resize(sensor + 1, Vec::new());
+                surface.outliers.resize(sensor + 1, Vec::new());
+            }
+            surface.filtered[sensor] = filtered
+                .points(&extrinsics)
+                .iter()
+                .map(|p| (p.x, p.y))
+                .collect();
+            surface.outliers[sensor] = scan
+                .points
+                .iter()
+                .zip(&filtered.outliers)
+                .filter(|(_, outlier)| **outlier)
+                .map(|(p, _)| (p.x, p.y))
+                .collect();
+        }
+        if let Some(model) = self.background.as_mut().and_then(|b| b.get_mut(sensor)) {
+            let learning = !model.is_learned();
+            let foreground = model.push(&scan);
+            if learning && model.is_learned() {
+                println!("[background] {} learned", self.sensor_names[sensor]);
+            }
+            if self.foreground.len() <= sensor {
+                self.foreground.resize(sensor + 1, Vec::new());
+            }
+            self.foreground[sensor] = foreground;
+            let points: Vec<ScanPoint> = self.foreground.iter().flatten().copied().collect();
+            let clusters = cluster::dbscan(
+                &points,
+                &ClusterConfig {
+                    method: ClusterMethod::Dbscan,
+                    ..ClusterConfig::default()
+                },
+            );
+            for event in self.zones.update(&clusters) {
+                println!("[zone] {}", self.zones.describe(&event));
+            }
+            let surface = self.surface.as_mut().unwrap();
+            surface.foreground = points.iter().map(|p| (p.x, p.y)).collect();
+            surface.triggered = (0..self.zones.zones.len())
+                .map(|i| self.zones.is_occupied(i))
+                .collect();
+        }
+        if let Some(config) = self.lines.as_ref() {
+            let surface = self.surface.as_mut().unwrap();
+            if surface.segments.len() <= sensor {
+                surface.segments.resize(sensor + 1, Vec::new());
+            }
+            surface.segments[sensor] = lines::extract(&scan, config);
+        }
+        if let Some(grid) = self.surface.as_mut().unwrap().grid.as_mut() {
+            let extrinsics = self.extrinsics.get(sensor).copied().unwrap_or_default();
+            grid.insert(&scan, &extrinsics, None);
+        }
+        self.fusion.push(scan);
+        if self.queries {
+            let index = ScanIndex::new(self.fusion.fused());
+            let surface = self.surface.as_mut().unwrap();
+            surface.closest = index
+                .closest_in_sector(0.0, QUERY_CONE)
+                .map(|(p, _)| (p.x, p.y));
+            surface.gaps = index.free_gaps(QUERY_RANGE, QUERY_MIN_GAP);
+        }
+        if let Some(config) = self.clusters.as_ref() {
+            let clusters = match config.method {
+                ClusterMethod::Breakpoint => self
+                    .fusion
+                    .scans()
+                    .flat_map(|scan| cluster::breakpoint(scan, config))
+                    .collect(),
+                ClusterMethod::Dbscan => cluster::dbscan(&self.fusion.fused(), config),
+            };
+            self.surface.as_mut().unwrap().clusters = clusters;
+        }
+        if let Some(tracker) = self.people.as_mut() {
+            let config = LegConfig::default();
+            let clusters: Vec<Cluster> = self
+                .fusion
+                .scans()
+                .flat_map(|scan| cluster::breakpoint(scan, &ClusterConfig::default()))
+                .collect();
+            let legs = people::detect_legs(&clusters, &config);
+            tracker.update(&people::pair_legs(&legs, &config));
+            let surface = self.surface.as_mut().unwrap();
+            surface.legs = legs.iter().map(|leg| leg.circle).collect();
+            surface.people = tracker.confirmed().cloned().collect();
+        }
+        // every ld19 keeps its own clock, so the tracker runs on the
+        // first sensor's revolutions and timestamps
+        if let Some(tracker) = self.tracker.as_mut().filter(|_| sensor == 0) {
+            let clusters = cluster::dbscan(
+                &self.fusion.fused(),
+                &ClusterConfig {
+                    method: ClusterMethod::Dbscan,
+                    ..ClusterConfig::default()
+                },
+            );
+            // walls make big clusters whose centroids wander around,
+            // only track things about the size of a person or smaller
+            let objects: Vec<Cluster> = clusters
+                .into_iter()
+                .filter(|c| (c.max.0 - c.min.0).hypot(c.max.1 - c.min.1) < 1000.0)
+                .collect();
+            let timestamp = self.fusion.scan(0).map_or(0, |scan| scan.timestamp);
+            tracker.update(timestamp, &objects);
+            let tracks: Vec<Track> = tracker.confirmed().cloned().collect();
+            if let Some(recorder) = self.recorder.as_mut() {
+                recorder.push_tracks(timestamp, &tracks);
+            }
+            self.surface.as_mut().unwrap().tracks = tracks;
+        }
+    }
+
+    // wrap up anything still being written and stop the event loop
+    fn shut_down(&mut self, event_loop: &ActiveEventLoop) {
+        for (name, crosstalk) in self.sensor_names.iter().zip(&self.crosstalk) {
+            if let Some(crosstalk) = crosstalk {
+                println!("[crosstalk] {name}: {crosstalk}");
+            }
+        }
+        if let Some(recorder) = self.recorder.take() {
+            let frames = recorder.finish();
+            println!("[record] stopped, {frames} frames");
+        }
+        event_loop.exit();
+    }
+
     pub fn with_size(size: PhysicalSize<f64>) -> Self {
         Self {
-            size: size,
+            size,
             window: None,
             framebuffer: None,
             surface: None,
+            cursor: None,
+            recorder: None,
+            playback: Playback::default(),
+            inspector: Inspector::default(),
+            sensor_names: Vec::new(),
+            health: Vec::new(),
+            crosstalk: Vec::new(),
+            temporal_config: TemporalConfig::default(),
+            temporal: None,
+            extrinsics: Vec::new(),
+            lines: None,
+            clusters: None,
+            people: None,
+            tracker: None,
+            background_config: BackgroundConfig::default(),
+            background: None,
+            foreground: Vec::new(),
+            zones: ZoneMonitor::default(),
+            grid_config: GridConfig::default(),
+            icp_config: IcpConfig::default(),
+            odometry: None,
+            simulated: false,
+            truth: None,
+            truth_origin: None,
+            slam_config: SlamConfig::default(),
+            slam: None,
+            safety: Vec::new(),
+            queries: false,
+            fusion: Fusion::default(),
+            exit_code: None,
         }
     }
+
+    /// Names and mounting of every sensor, by index
+    pub fn set_sensors(&mut self, sensors: Vec<(String, Extrinsics)>) {
+        self.health = vec![SensorHealth::default(); sensors.len()];
+        self.crosstalk = vec![None; sensors.len()];
+        self.safety = vec![FieldLevel::Clear; sensors.len()];
+        self.extrinsics = sensors.iter().map(|(_, e)| *e).collect();
+        self.inspector.extrinsics = self.extrinsics.clone();
+        self.sensor_names = sensors.into_iter().map(|(name, _)| name).collect();
+    }
 }
 
 impl ApplicationHandler<UserEvent> for State<'_> {
@@ -50,7 +420,7 @@ impl ApplicationHandler<UserEvent> for State<'_> {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         let window_size = self.size;
         let attributes = WindowAttributes::default()
-            .with_resizable(false)
+            .with_resizable(true)
             .with_title("lidar")
             .with_inner_size(window_size);
         let window = Arc::new(event_loop.create_window(attributes).unwrap());
@@ -59,11 +429,16 @@ impl ApplicationHandler<UserEvent> for State<'_> {
             window_size.height as u32,
             window.clone(),
         );
-        let pixels = Pixels::new(
+        // raqote keeps its pixels as 0xAARRGGBB words, which on a little
+        // endian machine is bgra in memory. matching the texture to that means
+        // a frame is just a straight copy
+        let pixels = PixelsBuilder::new(
             self.size.width as u32,
             self.size.height as u32,
             surface_texture,
-        );
+        )
+        .texture_format(wgpu::TextureFormat::Bgra8UnormSrgb)
+        .build();
         self.framebuffer = Some(pixels.expect("Error initializing framebuffer!"));
         self.window = Some(window)
     }
@@ -80,7 +455,7 @@ impl ApplicationHandler<UserEvent> for State<'_> {
         match event {
             WindowEvent::CloseRequested => {
                 println!("The close button was pressed; stopping");
-                event_loop.exit();
+                self.shut_down(event_loop);
             }
             WindowEvent::RedrawRequested => {
                 // Redraw the application.
@@ -90,26 +465,61 @@ impl ApplicationHandler<UserEvent> for State<'_> {
                 // the program to gracefully handle redraws requested by the OS.
 
                 //self.surface.as_mut().unwrap().draw(vec![]);
+                let surface = self.surface.as_mut().unwrap();
+                match self.inspector.selected().filter(|_| self.inspector.active) {
+                    Some(inspected) => {
+                        surface.highlight = inspected.positions(&self.inspector);
+                        surface.status_alert = inspected.frame.is_malformed();
+                        surface.status = Some(format!(
+                            "{} | {} bad crc, {} resyncs",
+                            inspected.summary(),
+                            self.inspector.bad_crc,
+                            self.inspector.resyncs
+                        ));
+                    }
+                    None => {
+                        surface.highlight.clear();
+                        surface.status = None;
+                    }
+                }
+                let now = Instant::now();
+                surface.sensor_lines = self
+                    .sensor_names
+                    .iter()
+                    .zip(&self.health)
+                    .zip(&self.crosstalk)
+                    .map(|((name, health), crosstalk)| {
+                        if self.simulated {
+                            return (format!("{name}: simulated"), false);
+                        }
+                        let mut line = format!("{name}: {health}");
+                        if let Some(crosstalk) = crosstalk {
+                            line.push_str(&format!(
+                                ", {} crosstalk ({:.1}%)",
+                                crosstalk.removed,
+                                crosstalk.removed_percent()
+                            ));
+                        }
+                        (line, health.is_stale(now))
+                    })
+                    .collect();
+                surface.present();
+                if let Some(recorder) = self.recorder.as_mut() {
+                    let surface = self.surface.as_ref().unwrap();
+                    let (width, height) = surface.size();
+                    recorder.push(width, height, surface.frame());
+                }
 
                 // Draw.
-                for (dst, &src) in self
-                    .framebuffer
+                self.framebuffer
                     .as_mut()
                     .unwrap()
                     .frame_mut()
-                    .chunks_exact_mut(4)
-                    .zip(self.surface.as_ref().unwrap().frame().iter())
-                {
-                    dst[0] = (src >> 16) as u8;
-                    dst[1] = (src >> 8) as u8;
-                    dst[2] = src as u8;
-                    dst[3] = (src >> 24) as u8;
-                }
+                    .copy_from_slice(bytemuck::cast_slice(self.surface.as_ref().unwrap().frame()));
 
                 if let Err(err) = self.framebuffer.as_ref().unwrap().render() {
                     println!("[render] pixels.render, {err}");
                     event_loop.exit();
-                    return;
                 }
 
                 // Queue a RedrawRequested event.
@@ -119,141 +529,2373 @@ impl ApplicationHandler<UserEvent> for State<'_> {
                 // can render here instead.
                 //self.window.as_ref().unwrap().request_redraw();
             }
+            WindowEvent::Resized(size) => {
+                // a minimized window reports a zero size, there's nothing to
+                // draw into so just wait for the next real size
+                if size.width == 0 || size.height == 0 {
+                    return;
+                }
+                self.size = PhysicalSize::new(size.width as f64, size.height as f64);
+                let framebuffer = self.framebuffer.as_mut().unwrap();
+                if let Err(err) = framebuffer.resize_surface(size.width, size.height) {
+                    println!("[render] pixels.resize_surface, {err}");
+                    event_loop.exit();
+                    return;
+                }
+                if let Err(err) = framebuffer.resize_buffer(size.width, size.height) {
+                    println!("[render] pixels.resize_buffer, {err}");
+                    event_loop.exit();
+                    return;
+                }
+                self.surface
+                    .as_mut()
+                    .unwrap()
+                    .resize(size.width, size.height);
+            }
             WindowEvent::KeyboardInput {
                 event:
                     KeyEvent {
-                        physical_key: PhysicalKey::Code(KeyCode::KeyR),
+                        physical_key: PhysicalKey::Code(KeyCode::F11),
                         state: ElementState::Pressed,
                         repeat: false,
                         ..
                     },
                 ..
             } => {
-                self.surface.as_mut().unwrap().init();
+                let window = self.window.as_ref().unwrap();
+                if window.fullscreen().is_some() {
+                    window.set_fullscreen(None);
+                } else {
+                    window.set_fullscreen(Some(Fullscreen::Borderless(None)));
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = Some(position);
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor = None;
+            }
+            WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                let surface = self.surface.as_mut().unwrap();
+                if surface.view_mode != ViewMode::Cartesian {
+                    return;
+                }
+                if let (true, Some(cursor)) = (surface.measure.active, self.cursor) {
+                    let extrinsics = self.extrinsics.first().copied().unwrap_or_default();
+                    let point =
+                        surface.measure_point_at(cursor.x as f32, cursor.y as f32, &extrinsics);
+                    surface.measure.push(point);
+                    println!("[measure]\n{}", surface.measure);
+                }
             }
             WindowEvent::KeyboardInput {
                 event:
                     KeyEvent {
-                        physical_key: PhysicalKey::Code(KeyCode::Equal),
+                        physical_key: PhysicalKey::Code(KeyCode::KeyM),
                         state: ElementState::Pressed,
                         repeat: false,
                         ..
                     },
                 ..
             } => {
-                self.surface.as_mut().unwrap().init();
-                self.surface.as_mut().unwrap().draw_scale -= 1.0;
+                let measure = &mut self.surface.as_mut().unwrap().measure;
+                measure.toggle();
+                println!(
+                    "[measure] {}",
+                    if measure.active {
+                        "click two points to measure"
+                    } else {
+                        "off"
+                    }
+                );
             }
             WindowEvent::KeyboardInput {
                 event:
                     KeyEvent {
-                        physical_key: PhysicalKey::Code(KeyCode::Minus),
+                        physical_key: PhysicalKey::Code(KeyCode::KeyC),
                         state: ElementState::Pressed,
                         repeat: false,
                         ..
                     },
                 ..
             } => {
-                self.surface.as_mut().unwrap().init();
-                self.surface.as_mut().unwrap().draw_scale += 1.0;
+                let surface = self.surface.as_mut().unwrap();
+                surface.color_map = surface.color_map.next();
+                surface.redraw();
+                println!("[view] color map: {}", surface.color_map);
             }
-            _ => (),
-        }
-    }
-    fn device_event(
-        &mut self,
-        _event_loop: &ActiveEventLoop,
-        _device_id: DeviceId,
-        _event: DeviceEvent,
-    ) {
-        // Handle window event.
-    }
-    fn user_event(&mut self, _event_loop: &ActiveEventLoop, event: UserEvent) {
-        // Handle user event
-        match event {
-            UserEvent::DrawPointBuffer(buffer) => {
-                //println!("recv draw event: {buffer:?}");
-                self.surface.as_mut().unwrap().draw(buffer);
-            } //_ => (),
-        }
-    }
-    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
-        if let Some(window) = self.window.as_ref() {
-            window.request_redraw();
-        }
-    }
-}
-
-#[allow(dead_code)]
-pub struct Surface {
-    // The main draw target
-    dt: Option<DrawTarget>,
-    draw_scale: f32,
-
-    // Center
-    cx: f32,
-    cy: f32,
-
-    // Background color
-    r: f32,
-    g: f32,
-    b: f32,
-}
-
-impl Surface {
-    /// Create a new Shapes
-    pub fn new(width: u32, height: u32) -> Self {
-        Self {
-            dt: Some(DrawTarget::new(width as i32, height as i32)),
-            draw_scale: 1.0,
-            cx: (width / 2) as f32,
-            cy: (width / 2) as f32,
-            r: 0.0,
-            g: 0.0,
-            b: 0.0,
-        }
-    }
-
-    /// Gain access to the underlying pixels
-    pub fn frame(&self) -> &[u32] {
-        self.dt.as_ref().unwrap().get_data()
-    }
-
-    pub fn init(&mut self) {
-        let dt = self.dt.as_mut().unwrap();
-
-        dt.clear(SolidSource {
-            r: self.r as u8,
-            g: self.g as u8,
-            b: self.b as u8,
-            a: 0xff,
-        });
-    }
-
-    /// Draw all of the shapes
-    pub fn draw(&mut self, command_buffer: Vec<DrawPoint>) {
-        let dt = self.dt.as_mut().unwrap();
-
-        for command in command_buffer {
-            let mut path = PathBuilder::new();
-            //path.rect(command.x + self.cx, command.y + self.cy, 1.0, 1.0);
-            path.rect(
-                (command.x / self.draw_scale) + self.cx,
-                (command.y / self.draw_scale) + self.cy,
-                1.0,
-                1.0,
-            );
-            let path = path.finish();
-            dt.fill(
-                &path,
-                &Source::Solid(SolidSource {
-                    r: command.r,
-                    g: command.g,
-                    b: command.b,
-                    a: 0xff,
-                }),
-                &DrawOptions::new(),
-            );
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyV),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                let surface = self.surface.as_mut().unwrap();
+                surface.color_by = surface.color_by.next();
+                surface.redraw();
+                println!("[view] coloring by: {}", surface.color_by);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyF),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                let surface = self.surface.as_mut().unwrap();
+                surface.show_shapes = !surface.show_shapes;
+                println!("[view] shapes: {}", surface.show_shapes);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyN),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                let surface = self.surface.as_mut().unwrap();
+                surface.filtered.clear();
+                surface.outliers.clear();
+                if self.temporal.take().is_some() {
+                    println!("[temporal] off");
+                } else {
+                    let config = self.temporal_config;
+                    let filters = vec![TemporalFilter::new(config); self.sensor_names.len()];
+                    self.temporal = Some(filters);
+                    println!(
+                        "[temporal] median over {} revolutions, {} degree bins",
+                        config.window, config.resolution
+                    );
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyL),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                self.surface.as_mut().unwrap().segments.clear();
+                if self.lines.take().is_some() {
+                    println!("[lines] off");
+                } else {
+                    self.lines = Some(LineConfig::default());
+                    println!("[lines] extracting line segments");
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyK),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                self.surface.as_mut().unwrap().clusters.clear();
+                // off, breakpoint, dbscan, off
+                self.clusters = match self.clusters.map(|c| c.method) {
+                    None => Some(ClusterConfig::default()),
+                    Some(ClusterMethod::Breakpoint) => Some(ClusterConfig {
+                        method: ClusterMethod::Dbscan,
+                        ..ClusterConfig::default()
+                    }),
+                    Some(ClusterMethod::Dbscan) => None,
+                };
+                match self.clusters {
+                    Some(config) => println!("[clusters] {}", config.method),
+                    None => println!("[clusters] off"),
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyU),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                let surface = self.surface.as_mut().unwrap();
+                surface.legs.clear();
+                surface.people.clear();
+                if self.people.take().is_some() {
+                    println!("[people] off");
+                } else {
+                    self.people = Some(PeopleTracker::default());
+                    println!("[people] tracking legs");
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyT),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                self.surface.as_mut().unwrap().tracks.clear();
+                if self.tracker.take().is_some() {
+                    println!("[tracks] off");
+                } else {
+                    self.tracker = Some(Tracker::default());
+                    println!("[tracks] tracking moving objects");
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyY),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                let surface = self.surface.as_mut().unwrap();
+                if let Some(odometry) = self.odometry.take() {
+                    let pose = odometry.pose();
+                    println!(
+                        "[odometry] off at ({:.0}, {:.0}) mm heading {:.1} deg",
+                        pose.x, pose.y, pose.heading
+                    );
+                    if let Some(truth) = surface.ground_truth.last() {
+                        println!(
+                            "[odometry] {:.0} mm and {:.1} deg from the ground truth",
+                            (pose.x - truth.x).hypot(pose.y - truth.y),
+                            normalize_degrees(pose.heading - truth.heading)
+                        );
+                    }
+                } else {
+                    if let Some(mapper) = self.slam.take() {
+                        mapper.finish();
+                        println!("[slam] off");
+                        surface.set_slam_map(None);
+                        surface.loops.clear();
+                    }
+                    self.odometry = Some(Odometry::new(self.icp_config));
+                    println!(
+                        "[odometry] {} icp on {}",
+                        self.icp_config.method,
+                        self.sensor_names.first().map_or("lidar0", |n| n.as_str())
+                    );
+                }
+                surface.trajectory.clear();
+                surface.ground_truth.clear();
+                self.truth_origin = None;
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyW),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                let surface = self.surface.as_mut().unwrap();
+                if let Some(mapper) = self.slam.take() {
+                    // waits for the scans still queued, so the numbers are for
+                    // everything it was given
+                    let Some(slam) = mapper.finish() else {
+                        println!("[slam] off, the mapping thread died");
+                        surface.set_slam_map(None);
+                        surface.loops.clear();
+                        return;
+                    };
+                    let pose = slam.pose();
+                    println!(
+                        "[slam] off at ({:.0}, {:.0}) mm heading {:.1} deg, {} keyframes and {} \
+                         loop closures",
+                        pose.x,
+                        pose.y,
+                        pose.heading,
+                        slam.keyframes().count(),
+                        slam.loops().count()
+                    );
+                    if let Some(truth) = surface.ground_truth.last() {
+                        println!(
+                            "[slam] {:.0} mm and {:.1} deg from the ground truth",
+                            (pose.x - truth.x).hypot(pose.y - truth.y),
+                            normalize_degrees(pose.heading - truth.heading)
+                        );
+                    }
+                    surface.set_slam_map(None);
+                    surface.loops.clear();
+                } else {
+                    if self.odometry.take().is_some() {
+                        println!("[odometry] off");
+                    }
+                    let grid = self.slam_config.grid;
+                    match Mapper::start(self.slam_config) {
+                        Ok(mapper) => {
+                            println!(
+                                "[slam] mapping {:.1}x{:.1} m in cells of {} mm from {}",
+                                (grid.max.0 - grid.min.0) / 1000.0,
+                                (grid.max.1 - grid.min.1) / 1000.0,
+                                grid.resolution,
+                                self.sensor_names.first().map_or("lidar0", |n| n.as_str())
+                            );
+                            self.slam = Some(mapper);
+                        }
+                        Err(err) => println!("[slam] unable to start, {err}"),
+                    }
+                }
+                surface.trajectory.clear();
+                surface.ground_truth.clear();
+                self.truth_origin = None;
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyZ),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                let surface = self.surface.as_mut().unwrap();
+                surface.foreground.clear();
+                surface.triggered.clear();
+                self.foreground.clear();
+                self.zones.clear();
+                if self.background.take().is_some() {
+                    println!("[background] off");
+                } else {
+                    let config = self.background_config;
+                    let models = vec![BackgroundModel::new(config); self.sensor_names.len()];
+                    self.background = Some(models);
+                    println!(
+                        "[background] learning over {} revolutions, keep the area clear",
+                        config.revolutions
+                    );
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyO),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                let surface = self.surface.as_mut().unwrap();
+                if surface.grid.take().is_some() {
+                    println!("[grid] off");
+                } else {
+                    let grid = OccupancyGrid::new(self.grid_config);
+                    println!(
+                        "[grid] mapping {}x{} cells of {} mm",
+                        grid.width(),
+                        grid.height(),
+                        grid.config.resolution
+                    );
+                    surface.grid = Some(grid);
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyE),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                // the slam map if there is one, it's the better of the two
+                let surface = self.surface.as_ref().unwrap();
+                let (tag, grid) = match (surface.slam_map(), surface.grid.as_ref()) {
+                    (Some(slam), _) => ("slam", slam),
+                    (None, Some(grid)) => ("grid", grid),
+                    (None, None) if self.slam.is_some() => {
+                        println!("[slam] nothing mapped yet");
+                        return;
+                    }
+                    (None, None) => {
+                        println!("[grid] not mapping, O or W turns it on");
+                        return;
+                    }
+                };
+                match screenshot::save_map(grid) {
+                    Ok(path) => println!("[{tag}] saved {}", path.display()),
+                    Err(err) => println!("[{tag}] unable to save, {err}"),
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyQ),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                self.queries = !self.queries;
+                let surface = self.surface.as_mut().unwrap();
+                surface.closest = None;
+                surface.gaps.clear();
+                println!(
+                    "[query] closest ahead and free directions: {}",
+                    self.queries
+                );
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyX),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                if let Some(compare) = self.surface.as_mut().unwrap().compare.as_mut() {
+                    compare.layout = compare.layout.next();
+                    println!("[compare] layout: {}", compare.layout);
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyD),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                if let Some(compare) = self.surface.as_ref().unwrap().compare.as_ref() {
+                    println!("[compare]\n{compare}");
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyI),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                self.inspector.toggle();
+                if self.inspector.active {
+                    self.print_inspected();
+                } else {
+                    println!("[inspector] off");
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(code),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } if self.inspector.active
+                && matches!(
+                    code,
+                    KeyCode::ArrowUp
+                        | KeyCode::ArrowDown
+                        | KeyCode::Home
+                        | KeyCode::End
+                        | KeyCode::PageUp
+                        | KeyCode::PageDown
+                ) =>
+            {
+                match code {
+                    KeyCode::ArrowUp => self.inspector.older(),
+                    KeyCode::ArrowDown => self.inspector.newer(),
+                    KeyCode::Home => self.inspector.oldest(),
+                    KeyCode::End => self.inspector.newest(),
+                    KeyCode::PageUp => self.inspector.previous_malformed(),
+                    _ => self.inspector.next_malformed(),
+                }
+                self.print_inspected();
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::Space),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                let (skipped, scans) = self.playback.toggle();
+                if self.playback.paused {
+                    println!("[playback] paused");
+                } else {
+                    println!("[playback] live, skipped {skipped} packets");
+                }
+                for scan in scans {
+                    self.update_scan(scan);
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::Period),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                if let Some(step) = self.playback.step_packet() {
+                    self.step(step);
+                }
+                self.print_backlog();
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::Comma),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                if let Some(step) = self.playback.step_revolution() {
+                    self.step(step);
+                }
+                self.print_backlog();
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyH),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                let surface = self.surface.as_mut().unwrap();
+                surface.hold = match surface.hold {
+                    None => Some(1),
+                    Some(1) => Some(3),
+                    Some(3) => Some(10),
+                    Some(_) => None,
+                };
+                surface.redraw();
+                match surface.hold {
+                    Some(n) => println!("[view] holding the last {n} revolutions"),
+                    None => println!("[view] hold off"),
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyS),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                let surface = self.surface.as_mut().unwrap();
+                // make sure the overlays are up to date before saving
+                surface.present();
+                let (width, height) = surface.size();
+                match screenshot::save(width, height, surface.frame()) {
+                    Ok(path) => println!("[screenshot] saved {}", path.display()),
+                    Err(err) => println!("[screenshot] unable to save, {err}"),
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::F9),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => match self.recorder.take() {
+                Some(recorder) => {
+                    let dir = recorder.dir.clone();
+                    let frames = recorder.finish();
+                    println!("[record] stopped, {frames} frames in {}", dir.display());
+                }
+                None => match Recorder::start() {
+                    Ok(recorder) => {
+                        println!("[record] recording to {}", recorder.dir.display());
+                        self.recorder = Some(recorder);
+                    }
+                    Err(err) => println!("[record] unable to start, {err}"),
+                },
+            },
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyP),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                let surface = self.surface.as_mut().unwrap();
+                surface.view_mode = surface.view_mode.next();
+                surface.redraw();
+                println!("[view] view: {}", surface.view_mode);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyB),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                let surface = self.surface.as_mut().unwrap();
+                surface.point_style.backend = surface.point_style.backend.next();
+                surface.redraw();
+                println!("[view] render backend: {}", surface.point_style.backend);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyA),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                let surface = self.surface.as_mut().unwrap();
+                surface.point_style.antialias = !surface.point_style.antialias;
+                surface.redraw();
+                println!("[view] antialiasing: {}", surface.point_style.antialias);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::BracketLeft),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                let surface = self.surface.as_mut().unwrap();
+                surface.point_style.size = (surface.point_style.size - 1.0).max(1.0);
+                surface.redraw();
+                println!("[view] point size: {}", surface.point_style.size);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::BracketRight),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                let surface = self.surface.as_mut().unwrap();
+                surface.point_style.size = (surface.point_style.size + 1.0).min(MAX_POINT_SIZE);
+                surface.redraw();
+                println!("[view] point size: {}", surface.point_style.size);
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::KeyR),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                self.surface.as_mut().unwrap().init();
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::Equal),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                self.surface.as_mut().unwrap().init();
+                self.surface.as_mut().unwrap().draw_scale -= 1.0;
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::Minus),
+                        state: ElementState::Pressed,
+                        repeat: false,
+                        ..
+                    },
+                ..
+            } => {
+                self.surface.as_mut().unwrap().init();
+                self.surface.as_mut().unwrap().draw_scale += 1.0;
+            }
+            _ => (),
+        }
+    }
+    fn device_event(
+        &mut self,
+        _event_loop: &ActiveEventLoop,
+        _device_id: DeviceId,
+        _event: DeviceEvent,
+    ) {
+        // Handle window event.
+    }
+    fn user_event(&mut self, event_loop: &ActiveEventLoop, event: UserEvent) {
+        // Handle user event
+        match event {
+            UserEvent::DrawPointBuffer(buffer) => {
+                //println!("recv draw event: {buffer:?}");
+                if let Some(buffer) = self.playback.push(buffer) {
+                    let surface = self.surface.as_mut().unwrap();
+                    if let Some(compare) = surface.compare.as_mut() {
+                        compare.push_live(&buffer);
+                    }
+                    surface.draw(buffer);
+                }
+            }
+            UserEvent::Frame(sensor, frame) => {
+                if let Some(health) = self.health.get_mut(sensor as usize) {
+                    health.push(&frame, Instant::now());
+                }
+                self.inspector.push(sensor, frame);
+            }
+            UserEvent::GroundTruth(pose) => self.truth = Some(pose),
+            UserEvent::Scan(scan) => {
+                if let Some(scan) = self.playback.push_scan(scan) {
+                    self.update_scan(scan);
+                }
+            }
+            UserEvent::Safety(sensor, level) => {
+                if let Some(state) = self.safety.get_mut(sensor as usize) {
+                    *state = level;
+                }
+                let name = self.sensor_names.get(sensor as usize);
+                println!("[safety] {}: {level}", name.map_or("?", |n| n.as_str()));
+                // the robot is as unsafe as its least safe sensor says
+                let level = self.safety.iter().max().copied().unwrap_or_default();
+                self.surface.as_mut().unwrap().safety_level = level;
+            }
+            UserEvent::Exit(sensor, level) => {
+                // only the first sensor to get there counts
+                if self.exit_code.is_none() {
+                    let name = self.sensor_names.get(sensor as usize);
+                    println!(
+                        "[safety] {} reached {level}, exiting",
+                        name.map_or("?", |n| n.as_str())
+                    );
+                    self.exit_code = Some(level.index() as i32);
+                    self.shut_down(event_loop);
+                }
+            }
+            UserEvent::Crosstalk(sensor, stats) => {
+                if let Some(crosstalk) = self.crosstalk.get_mut(sensor as usize) {
+                    *crosstalk = Some(stats);
+                }
+            } //_ => (),
+        }
+    }
+    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
+        if let Some(window) = self.window.as_ref() {
+            window.request_redraw();
+        }
+    }
+}
+
+// how many of the most recently drawn points are kept around for snapping and
+// for redrawing when the colors change. two seconds worth at 4500 points/s,
+// which matches the age color map
+const POINT_HISTORY: usize = 9000;
+// an ld19 makes about 450 points a revolution at 10 Hz, with some room for it
+// spinning a little slow. holding keeps this many per held revolution per
+// sensor when that's more than POINT_HISTORY
+const REVOLUTION_POINTS: usize = 500;
+// how close (in screen pixels) a click has to be to a scan point to snap to it
+const SNAP_RADIUS: f32 = 8.0;
+
+const MAX_POINT_SIZE: f32 = 8.0;
+
+// What the query overlay asks about: the closest point within this many
+// degrees of straight ahead, and gaps at least QUERY_MIN_GAP degrees wide with
+// nothing closer than QUERY_RANGE millimeters
+const QUERY_CONE: f32 = 30.0;
+const QUERY_RANGE: f32 = 1500.0;
+const QUERY_MIN_GAP: f32 = 10.0;
+
+// Intrusion zones are drawn in this until something is inside them
+pub const ZONE_COLOR: (u8, u8, u8) = (0xff, 0xc0, 0x40);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RenderBackend {
+    // write points straight into the pixel buffer
+    #[default]
+    Direct,
+    // build and fill a raqote path for every point
+    Raqote,
+}
+
+impl fmt::Display for RenderBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderBackend::Direct => write!(f, "direct"),
+            RenderBackend::Raqote => write!(f, "raqote"),
+        }
+    }
+}
+
+impl RenderBackend {
+    pub fn next(self) -> Self {
+        match self {
+            RenderBackend::Direct => RenderBackend::Raqote,
+            RenderBackend::Raqote => RenderBackend::Direct,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PointStyle {
+    pub backend: RenderBackend,
+    // Width and height of a point in pixels
+    pub size: f32,
+    pub antialias: bool,
+}
+
+impl Default for PointStyle {
+    fn default() -> Self {
+        Self {
+            backend: RenderBackend::default(),
+            size: 1.0,
+            antialias: false,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ViewMode {
+    // top down, robot origin in the middle
+    #[default]
+    Cartesian,
+    // angle along the x axis, range up the y axis
+    Polar,
+}
+
+impl fmt::Display for ViewMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ViewMode::Cartesian => write!(f, "cartesian"),
+            ViewMode::Polar => write!(f, "polar"),
+        }
+    }
+}
+
+impl ViewMode {
+    pub fn next(self) -> Self {
+        match self {
+            ViewMode::Cartesian => ViewMode::Polar,
+            ViewMode::Polar => ViewMode::Cartesian,
+        }
+    }
+}
+
+// everything needed to place a point on screen, split out of the surface so
+// it can be used while the draw target is borrowed
+#[derive(Debug, Clone, Copy)]
+struct Projection {
+    mode: ViewMode,
+    draw_scale: f32,
+    width: f32,
+    height: f32,
+    cx: f32,
+    cy: f32,
+}
+
+impl Projection {
+    fn project(&self, point: &DrawPoint) -> (f32, f32) {
+        match self.mode {
+            ViewMode::Cartesian => (
+                (point.x / self.draw_scale) + self.cx,
+                (point.y / self.draw_scale) + self.cy,
+            ),
+            ViewMode::Polar => (
+                (point.angle / 360.0) * self.width,
+                self.height - 1.0 - (point.distance as f32 / self.draw_scale),
+            ),
+        }
+    }
+}
+
+// The occupancy grids rendered at some zoom, pan and size, kept until the
+// live grid or the view changes. 0 pixels are transparent
+struct GridLayer {
+    scans: Option<u32>,
+    draw_scale: f32,
+    center: (f32, f32),
+    size: (u32, u32),
+    background: u32,
+    pixels: Vec<u32>,
+}
+
+impl GridLayer {
+    fn same_view(&self, other: &GridLayer) -> bool {
+        self.scans == other.scans
+            && self.draw_scale == other.draw_scale
+            && self.center == other.center
+            && self.size == other.size
+            && self.background == other.background
+    }
+}
+
+// The slam map drawn once, one pixel per cell, whenever it or the background
+// changes. it's only turned and scaled onto the screen as the robot moves
+struct SlamLayer {
+    background: u32,
+    texture: Vec<u32>,
+    // where the robot was on it and the view it was last put on screen for,
+    // and what that looked like. 0 pixels are transparent
+    pose: Pose,
+    draw_scale: f32,
+    center: (f32, f32),
+    size: (u32, u32),
+    pixels: Vec<u32>,
+}
+
+// Static geometry drawn over the points, in robot coordinates
+#[derive(Debug, Clone)]
+pub struct Shape {
+    pub name: String,
+    pub polygon: Polygon,
+    pub color: (u8, u8, u8),
+    // Shade the inside as well as drawing the outline
+    pub fill: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct HistoryPoint {
+    point: DrawPoint,
+    at: Instant,
+}
+
+#[allow(dead_code)]
+pub struct Surface {
+    // The main draw target
+    dt: Option<DrawTarget>,
+    // What actually gets presented, the points plus any overlays
+    view: Option<DrawTarget>,
+    draw_scale: f32,
+
+    // Recently drawn points
+    history: VecDeque<HistoryPoint>,
+    // Only show this many of the most recent revolutions. every sensor counts
+    // its own, so the newest is kept per sensor
+    pub hold: Option<u32>,
+    newest_revolution: Vec<u32>,
+
+    // Point rendering
+    pub view_mode: ViewMode,
+    pub point_style: PointStyle,
+    pub color_map: ColorMap,
+    pub color_by: ColorBy,
+    // Used for labels, not every system has one
+    font: Option<Font>,
+
+    // Replaces the live points when comparing captures
+    pub compare: Option<Comparison>,
+
+    // Overlays
+    pub shapes: Vec<Shape>,
+    pub show_shapes: bool,
+    pub measure: Measure,
+    // Points to call out, in robot coordinates
+    pub highlight: Vec<(f32, f32)>,
+    // Median filtered ranges and the outliers from the latest revolution, per
+    // sensor, in robot coordinates
+    pub filtered: Vec<Vec<(f32, f32)>>,
+    pub outliers: Vec<Vec<(f32, f32)>>,
+    // Extracted line segments per sensor, robot coordinates
+    pub segments: Vec<Vec<Segment>>,
+    // Drawn in a different color each
+    pub clusters: Vec<Cluster>,
+    // Leg circles and confirmed people, robot coordinates
+    pub legs: Vec<Circle>,
+    pub people: Vec<PersonTrack>,
+    // Confirmed moving object tracks, robot coordinates
+    pub tracks: Vec<Track>,
+    // Foreground points, robot coordinates, and the intrusion zones along
+    // with whether each one has something in it
+    pub foreground: Vec<(f32, f32)>,
+    pub zones: Vec<Shape>,
+    pub triggered: Vec<bool>,
+    // Protective fields and the state across all sensors
+    pub safety_fields: Vec<(FieldLevel, Polygon)>,
+    pub safety_level: FieldLevel,
+    // Closest point in the forward cone and the free directions out to
+    // QUERY_RANGE, robot coordinates
+    pub closest: Option<(f32, f32)>,
+    pub gaps: Vec<Gap>,
+    // Occupancy grid drawn underneath the points while mapping, a saved map
+    // to go under that, and what they looked like on screen last time
+    pub grid: Option<OccupancyGrid>,
+    pub map: Option<OccupancyGrid>,
+    // The slam map, in the frame slam started in. set_slam_map replaces it
+    slam_map: Option<OccupancyGrid>,
+    // Poses from odometry or slam and, when simulating, where it really went,
+    // all relative to where it started. drawn around the latest pose, along
+    // with the keyframes slam tied together on coming back somewhere
+    pub trajectory: Vec<Pose>,
+    pub ground_truth: Vec<Pose>,
+    pub loops: Vec<(Pose, Pose)>,
+    grid_layer: Option<GridLayer>,
+    slam_layer: Option<SlamLayer>,
+    // A line of text along the bottom, drawn red when alerting
+    pub status: Option<String>,
+    pub status_alert: bool,
+    // One line per sensor along the top right, drawn red when alerting
+    pub sensor_lines: Vec<(String, bool)>,
+
+    // Size
+    width: u32,
+    height: u32,
+
+    // Center
+    cx: f32,
+    cy: f32,
+
+    // Background color
+    r: f32,
+    g: f32,
+    b: f32,
+}
+
+impl Surface {
+    /// Create a new Shapes
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            dt: Some(DrawTarget::new(width as i32, height as i32)),
+            view: Some(DrawTarget::new(width as i32, height as i32)),
+            draw_scale: 1.0,
+            history: VecDeque::with_capacity(POINT_HISTORY),
+            hold: None,
+            newest_revolution: Vec::new(),
+            view_mode: ViewMode::default(),
+            point_style: PointStyle::default(),
+            color_map: ColorMap::default(),
+            color_by: ColorBy::default(),
+            font: SystemSource::new()
+                .select_best_match(&[FamilyName::SansSerif], &Properties::new())
+                .ok()
+                .and_then(|handle| handle.load().ok()),
+            compare: None,
+            shapes: Vec::new(),
+            show_shapes: true,
+            measure: Measure::default(),
+            highlight: Vec::new(),
+            filtered: Vec::new(),
+            outliers: Vec::new(),
+            segments: Vec::new(),
+            clusters: Vec::new(),
+            legs: Vec::new(),
+            people: Vec::new(),
+            tracks: Vec::new(),
+            foreground: Vec::new(),
+            zones: Vec::new(),
+            triggered: Vec::new(),
+            safety_fields: Vec::new(),
+            safety_level: FieldLevel::Clear,
+            closest: None,
+            gaps: Vec::new(),
+            grid: None,
+            map: None,
+            slam_map: None,
+            trajectory: Vec::new(),
+            ground_truth: Vec::new(),
+            loops: Vec::new(),
+            grid_layer: None,
+            slam_layer: None,
+            status: None,
+            status_alert: false,
+            sensor_lines: Vec::new(),
+            width,
+            height,
+            cx: (width / 2) as f32,
+            cy: (height / 2) as f32,
+            r: 0.0,
+            g: 0.0,
+            b: 0.0,
+        }
+    }
+
+    /// Width and height in pixels
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Gain access to the underlying pixels
+    pub fn frame(&self) -> &[u32] {
+        self.view.as_ref().unwrap().get_data()
+    }
+
+    pub fn init(&mut self) {
+        self.history.clear();
+        let dt = self.dt.as_mut().unwrap();
+
+        dt.clear(SolidSource {
+            r: self.r as u8,
+            g: self.g as u8,
+            b: self.b as u8,
+            a: 0xff,
+        });
+    }
+
+    /// Resize the draw target, keeping whatever has already been drawn
+    /// centered on the origin. draw_scale is left alone so a millimeter on
+    /// screen stays the same size no matter how big the window gets
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == self.width && height == self.height {
+            return;
+        }
+        let old = self.dt.take().unwrap();
+        let (old_cx, old_cy) = (self.cx, self.cy);
+
+        self.width = width;
+        self.height = height;
+        self.cx = (width / 2) as f32;
+        self.cy = (height / 2) as f32;
+        self.dt = Some(DrawTarget::new(width as i32, height as i32));
+        self.view = Some(DrawTarget::new(width as i32, height as i32));
+        let dt = self.dt.as_mut().unwrap();
+        dt.clear(SolidSource {
+            r: self.r as u8,
+            g: self.g as u8,
+            b: self.b as u8,
+            a: 0xff,
+        });
+
+        // the polar view stretches with the window, so there's nothing to
+        // keep
+        if self.view_mode == ViewMode::Polar {
+            self.redraw();
+            return;
+        }
+
+        // shift the old contents so the origin lands on the new center
+        let dx = (self.cx - old_cx) as i32;
+        let dy = (self.cy - old_cy) as i32;
+        self.dt.as_mut().unwrap().copy_surface(
+            &old,
+            IntRect::new(
+                IntPoint::new(0, 0),
+                IntPoint::new(old.width(), old.height()),
+            ),
+            IntPoint::new(dx, dy),
+        );
+    }
+
+    fn projection(&self) -> Projection {
+        Projection {
+            mode: self.view_mode,
+            draw_scale: self.draw_scale,
+            width: self.width as f32,
+            height: self.height as f32,
+            cx: self.cx,
+            cy: self.cy,
+        }
+    }
+
+    /// Convert a position in screen pixels to robot coordinates (mm)
+    pub fn to_robot(&self, px: f32, py: f32) -> (f32, f32) {
+        (
+            (px - self.cx) * self.draw_scale,
+            (py - self.cy) * self.draw_scale,
+        )
+    }
+
+    /// Convert a position in robot coordinates (mm) to screen pixels
+    pub fn to_screen(&self, x: f32, y: f32) -> (f32, f32) {
+        (
+            (x / self.draw_scale) + self.cx,
+            (y / self.draw_scale) + self.cy,
+        )
+    }
+
+    /// The slam map as of the latest keyframe
+    pub fn slam_map(&self) -> Option<&OccupancyGrid> {
+        self.slam_map.as_ref()
+    }
+
+    /// Replace the slam map, it's drawn again next frame
+    pub fn set_slam_map(&mut self, map: Option<OccupancyGrid>) {
+        self.slam_map = map;
+        self.slam_layer = None;
+    }
+
+    /// Build a measurement point for a click at the given screen position,
+    /// snapping to the nearest recently drawn scan point if one is close.
+    /// the sensor's range and bearing to anywhere else are worked out with
+    /// extrinsics
+    pub fn measure_point_at(&self, px: f32, py: f32, extrinsics: &Extrinsics) -> MeasurePoint {
+        let snap_radius = SNAP_RADIUS * self.draw_scale;
+        let (x, y) = self.to_robot(px, py);
+        let nearest = self
+            .history
+            .iter()
+            .map(|h| &h.point)
+            .map(|p| (p, (p.x - x).hypot(p.y - y)))
+            .filter(|(_, d)| *d <= snap_radius)
+            .min_by(|(_, a), (_, b)| a.total_cmp(b));
+        match nearest {
+            Some((p, _)) => MeasurePoint {
+                x: p.x,
+                y: p.y,
+                sensor: p.sensor,
+                sensor_range: p.distance as f32,
+                sensor_bearing: p.angle,
+                snapped: true,
+            },
+            None => {
+                let (sx, sy) = extrinsics.to_sensor(x, y);
+                MeasurePoint {
+                    x,
+                    y,
+                    sensor: 0,
+                    sensor_range: sx.hypot(sy),
+                    sensor_bearing: sy.atan2(sx).to_degrees().rem_euclid(360.0),
+                    snapped: false,
+                }
+            }
+        }
+    }
+
+    /// Compose the points and overlays into the view that gets presented
+    pub fn present(&mut self) {
+        // age changes every frame even if no new points came in
+        if self.color_by == ColorBy::Age {
+            self.redraw();
+        }
+        let mut view = self.view.take().unwrap();
+        if self.compare.is_some() {
+            view.clear(SolidSource {
+                r: self.r as u8,
+                g: self.g as u8,
+                b: self.b as u8,
+                a: 0xff,
+            });
+            self.draw_compare(&mut view);
+        } else {
+            view.get_data_mut()
+                .copy_from_slice(self.dt.as_ref().unwrap().get_data());
+            if self.view_mode == ViewMode::Cartesian {
+                self.draw_grid(&mut view);
+            }
+        }
+        if self.view_mode == ViewMode::Polar {
+            self.draw_polar_grid(&mut view);
+        } else {
+            self.draw_shapes(&mut view);
+            self.draw_safety(&mut view);
+            self.draw_queries(&mut view);
+            self.draw_zones(&mut view);
+            self.draw_filtered(&mut view);
+            self.draw_segments(&mut view);
+            self.draw_clusters(&mut view);
+            self.draw_people(&mut view);
+            self.draw_tracks(&mut view);
+            self.draw_trajectory(&mut view);
+            self.draw_measure(&mut view);
+            self.draw_highlight(&mut view);
+        }
+        self.draw_legend(&mut view);
+        self.draw_status(&mut view);
+        self.draw_sensor_lines(&mut view);
+        self.view = Some(view);
+    }
+
+    /// Clear the points and draw the history again, e.g. after the colors
+    /// changed. anything older than the history is lost
+    pub fn redraw(&mut self) {
+        let now = Instant::now();
+        let projection = self.projection();
+        let dt = self.dt.as_mut().unwrap();
+        dt.clear(SolidSource {
+            r: self.r as u8,
+            g: self.g as u8,
+            b: self.b as u8,
+            a: 0xff,
+        });
+        for h in &self.history {
+            let newest = self
+                .newest_revolution
+                .get(h.point.sensor as usize)
+                .copied()
+                .unwrap_or(h.point.revolution);
+            if self
+                .hold
+                .is_some_and(|n| newest.wrapping_sub(h.point.revolution) >= n)
+            {
+                continue;
+            }
+            let age = now.duration_since(h.at).as_secs_f32();
+            let (x, y) = projection.project(&h.point);
+            fill_point(
+                dt,
+                self.point_style,
+                x,
+                y,
+                point_color(self.color_map, self.color_by, &h.point, age),
+            );
+        }
+    }
+
+    fn draw_polar_grid(&self, view: &mut DrawTarget) {
+        let (width, height) = (self.width as f32, self.height as f32);
+        let source = Source::Solid(SolidSource {
+            r: 0x50,
+            g: 0x50,
+            b: 0x50,
+            a: 0xff,
+        });
+        let style = StrokeStyle {
+            width: 1.0,
+            ..StrokeStyle::default()
+        };
+        let options = DrawOptions::new();
+
+        // a vertical line every 30 degrees
+        let mut path = PathBuilder::new();
+        for degrees in (0..360).step_by(30) {
+            let x = (degrees as f32 / 360.0) * width + 0.5;
+            path.move_to(x, 0.0);
+            path.line_to(x, height);
+        }
+        // and a horizontal one every whole number of meters that keeps them
+        // at least 40px apart
+        let meters = ((40.0 * self.draw_scale) / 1000.0).ceil().max(1.0);
+        let step = meters * 1000.0 / self.draw_scale;
+        let mut y = height - 1.0 - step;
+        while y > 0.0 {
+            path.move_to(0.0, y + 0.5);
+            path.line_to(width, y + 0.5);
+            y -= step;
+        }
+        view.stroke(&path.finish(), &source, &style, &options);
+
+        let Some(font) = self.font.as_ref() else {
+            return;
+        };
+        for degrees in (0..360).step_by(30) {
+            let x = (degrees as f32 / 360.0) * width + 3.0;
+            view.draw_text(
+                font,
+                12.0,
+                &format!("{degrees}"),
+                raqote::Point::new(x, height - 4.0),
+                &source,
+                &options,
+            );
+        }
+        let mut y = height - 1.0 - step;
+        let mut label = meters;
+        while y > 0.0 {
+            view.draw_text(
+                font,
+                12.0,
+                &format!("{label} m"),
+                raqote::Point::new(width - 40.0, y - 3.0),
+                &source,
+                &options,
+            );
+            y -= step;
+            label += meters;
+        }
+    }
+
+    fn draw_shapes(&self, view: &mut DrawTarget) {
+        if !self.show_shapes {
+            return;
+        }
+        for shape in &self.shapes {
+            self.draw_shape(view, shape);
+        }
+    }
+
+    // the grids go wherever the points left the background showing, so they
+    // end up underneath them
+    fn draw_grid(&mut self, view: &mut DrawTarget) {
+        let background = raster::pack(self.r as u8, self.g as u8, self.b as u8);
+        if self.grid.is_none() && self.map.is_none() {
+            self.grid_layer = None;
+        } else {
+            let layer = GridLayer {
+                scans: self.grid.as_ref().map(|grid| grid.scans()),
+                draw_scale: self.draw_scale,
+                center: (self.cx, self.cy),
+                size: (self.width, self.height),
+                background,
+                pixels: Vec::new(),
+            };
+            if self
+                .grid_layer
+                .as_ref()
+                .is_none_or(|old| !old.same_view(&layer))
+            {
+                self.grid_layer = Some(self.render_grid(layer));
+            }
+            underlay(view, &self.grid_layer.as_ref().unwrap().pixels, background);
+        }
+        // the slam map goes under those
+        self.draw_slam_map(view, background);
+    }
+
+    fn render_grid(&self, mut layer: GridLayer) -> GridLayer {
+        let width = self.width as usize;
+        layer.pixels = vec![0; width * self.height as usize];
+        let (free, occupied) = self.cell_colors();
+        // the live grid on top, it's more up to date
+        for grid in [self.map.as_ref(), self.grid.as_ref()]
+            .into_iter()
+            .flatten()
+        {
+            let size = grid.config.resolution / self.draw_scale;
+            for row in 0..grid.height() {
+                for col in 0..grid.width() {
+                    let color = match grid.cell(col, row) {
+                        Cell::Unknown => continue,
+                        Cell::Free => free,
+                        Cell::Occupied => occupied,
+                    };
+                    let (x, y) = grid.center_of(col, row);
+                    let (sx, sy) = self.to_screen(x, y);
+                    let (sx, sy) = (sx - size / 2.0, sy - size / 2.0);
+                    raster::plot(&mut layer.pixels, width, sx, sy, size, color);
+                }
+            }
+        }
+        layer
+    }
+
+    // the slam map is in the frame slam started in, so it's turned around to
+    // the robot's. only the turning happens when the robot moves, the cells
+    // are only gone through again when the map changes
+    fn draw_slam_map(&mut self, view: &mut DrawTarget, background: u32) {
+        let Some(map) = self.slam_map.as_ref() else {
+            self.slam_layer = None;
+            return;
+        };
+        let mut layer = match self.slam_layer.take() {
+            Some(layer) if layer.background == background => layer,
+            _ => {
+                let (free, occupied) = self.cell_colors();
+                let mut texture = vec![0; map.width() * map.height()];
+                for row in 0..map.height() {
+                    for col in 0..map.width() {
+                        texture[row * map.width() + col] = match map.cell(col, row) {
+                            Cell::Unknown => 0,
+                            Cell::Free => free,
+                            Cell::Occupied => occupied,
+                        };
+                    }
+                }
+                SlamLayer {
+                    background,
+                    texture,
+                    pose: Pose::default(),
+                    // NaN so the first view never matches
+                    draw_scale: f32::NAN,
+                    center: (self.cx, self.cy),
+                    size: (self.width, self.height),
+                    pixels: Vec::new(),
+                }
+            }
+        };
+        let pose = self.slam_pose();
+        if layer.pose != pose
+            || layer.draw_scale != self.draw_scale
+            || layer.center != (self.cx, self.cy)
+            || layer.size != (self.width, self.height)
+        {
+            // where the map's corner and its first cell either way end up
+            let turn = pose.inverse();
+            let (min, resolution) = (map.config.min, map.config.resolution);
+            let corner = |x: f32, y: f32| {
+                let (x, y) = turn.apply(x, y);
+                self.to_screen(x, y)
+            };
+            let origin = corner(min.0, min.1);
+            let along = corner(min.0 + resolution, min.1);
+            let up = corner(min.0, min.1 + resolution);
+            let width = self.width as usize;
+            layer.pixels.clear();
+            layer.pixels.resize(width * self.height as usize, 0);
+            raster::blit(
+                &mut layer.pixels,
+                width,
+                &layer.texture,
+                map.width(),
+                origin,
+                (along.0 - origin.0, along.1 - origin.1),
+                (up.0 - origin.0, up.1 - origin.1),
+            );
+            layer.pose = pose;
+            layer.draw_scale = self.draw_scale;
+            layer.center = (self.cx, self.cy);
+            layer.size = (self.width, self.height);
+        }
+        underlay(view, &layer.pixels, background);
+        self.slam_layer = Some(layer);
+    }
+
+    // free cells a little lighter than the background, occupied ones grey
+    fn cell_colors(&self) -> (u32, u32) {
+        let lighten = |c: f32| (c as u8).saturating_add(0x18);
+        (
+            raster::pack(lighten(self.r), lighten(self.g), lighten(self.b)),
+            raster::pack(0x90, 0x90, 0x98),
+        )
+    }
+
+    // where the robot is on the slam map, the newest pose of the path
+    fn slam_pose(&self) -> Pose {
+        self.trajectory.last().copied().unwrap_or_default()
+    }
+
+    fn draw_queries(&self, view: &mut DrawTarget) {
+        let options = DrawOptions::new();
+        let (ox, oy) = self.to_screen(0.0, 0.0);
+        let radius = QUERY_RANGE / self.draw_scale;
+        if !self.gaps.is_empty() {
+            let mut path = PathBuilder::new();
+            for gap in &self.gaps {
+                // screen y is the same way up as robot y, so angles carry over
+                let from = gap.from.to_radians();
+                path.move_to(ox + radius * from.cos(), oy + radius * from.sin());
+                path.arc(ox, oy, radius, from, gap.width.to_radians());
+            }
+            let style = StrokeStyle {
+                width: 3.0,
+                ..StrokeStyle::default()
+            };
+            view.stroke(
+                &path.finish(),
+                &Source::Solid(SolidSource {
+                    r: 0x40,
+                    g: 0xff,
+                    b: 0x40,
+                    a: 0xff,
+                }),
+                &style,
+                &options,
+            );
+        }
+        if let Some((x, y)) = self.closest {
+            let (px, py) = self.to_screen(x, y);
+            let mut path = PathBuilder::new();
+            path.move_to(ox, oy);
+            path.line_to(px, py);
+            path.move_to(px + 5.0, py);
+            path.arc(px, py, 5.0, 0.0, std::f32::consts::TAU);
+            let source = Source::Solid(SolidSource {
+                r: 0xff,
+                g: 0x40,
+                b: 0x40,
+                a: 0xff,
+            });
+            view.stroke(&path.finish(), &source, &StrokeStyle::default(), &options);
+            if let Some(font) = self.font.as_ref() {
+                view.draw_text(
+                    font,
+                    12.0,
+                    &format!("{:.0} mm", x.hypot(y)),
+                    raqote::Point::new(px + 8.0, py + 4.0),
+                    &source,
+                    &options,
+                );
+            }
+        }
+    }
+
+    fn draw_safety(&self, view: &mut DrawTarget) {
+        if self.safety_fields.is_empty() {
+            return;
+        }
+        let color = |level: FieldLevel| match level {
+            FieldLevel::Clear => (0x40, 0xff, 0x40),
+            FieldLevel::Warning => (0xff, 0xe0, 0x00),
+            FieldLevel::Slow => (0xff, 0x90, 0x00),
+            FieldLevel::Stop => (0xff, 0x20, 0x20),
+        };
+        if self.show_shapes {
+            for (level, polygon) in &self.safety_fields {
+                let shape = Shape {
+                    name: String::new(),
+                    polygon: polygon.clone(),
+                    color: color(*level),
+                    // the field that set the current state is shaded
+                    fill: *level == self.safety_level,
+                };
+                self.draw_shape(view, &shape);
+            }
+        }
+        if let Some(font) = self.font.as_ref() {
+            let (r, g, b) = color(self.safety_level);
+            view.draw_text(
+                font,
+                16.0,
+                &format!("safety: {}", self.safety_level),
+                raqote::Point::new(self.width as f32 / 2.0 - 40.0, 24.0),
+                &Source::Solid(SolidSource { r, g, b, a: 0xff }),
+                &DrawOptions::new(),
+            );
+        }
+    }
+
+    fn draw_zones(&self, view: &mut DrawTarget) {
+        for (i, zone) in self.zones.iter().enumerate() {
+            if self.triggered.get(i).copied().unwrap_or(false) {
+                self.draw_shape(
+                    view,
+                    &Shape {
+                        color: (0xff, 0x40, 0x40),
+                        fill: true,
+                        ..zone.clone()
+                    },
+                );
+            } else {
+                self.draw_shape(view, zone);
+            }
+        }
+
+        let source = Source::Solid(SolidSource {
+            r: 0xff,
+            g: 0x40,
+            b: 0x40,
+            a: 0xff,
+        });
+        let options = DrawOptions::new();
+        for (x, y) in &self.foreground {
+            let (px, py) = self.to_screen(*x, *y);
+            view.fill_rect(px - 1.5, py - 1.5, 3.0, 3.0, &source, &options);
+        }
+    }
+
+    fn draw_shape(&self, view: &mut DrawTarget, shape: &Shape) {
+        if shape.polygon.points.is_empty() {
+            return;
+        }
+        let style = StrokeStyle {
+            width: 1.0,
+            ..StrokeStyle::default()
+        };
+        let options = DrawOptions::new();
+        let (r, g, b) = shape.color;
+        let mut path = PathBuilder::new();
+        for (i, &(x, y)) in shape.polygon.points.iter().enumerate() {
+            let (px, py) = self.to_screen(x, y);
+            if i == 0 {
+                path.move_to(px, py);
+            } else {
+                path.line_to(px, py);
+            }
+        }
+        path.close();
+        let path = path.finish();
+        if shape.fill {
+            // solid sources are premultiplied
+            let alpha = 0x40;
+            let scale = |c: u8| (c as u16 * alpha as u16 / 0xff) as u8;
+            view.fill(
+                &path,
+                &Source::Solid(SolidSource {
+                    r: scale(r),
+                    g: scale(g),
+                    b: scale(b),
+                    a: alpha,
+                }),
+                &options,
+            );
+        }
+        view.stroke(
+            &path,
+            &Source::Solid(SolidSource { r, g, b, a: 0xff }),
+            &style,
+            &options,
+        );
+        if let (Some(font), Some(&(x, y))) = (self.font.as_ref(), shape.polygon.points.first()) {
+            let (px, py) = self.to_screen(x, y);
+            view.draw_text(
+                font,
+                12.0,
+                &shape.name,
+                raqote::Point::new(px + 3.0, py - 3.0),
+                &Source::Solid(SolidSource { r, g, b, a: 0xff }),
+                &options,
+            );
+        }
+    }
+
+    fn draw_compare(&self, view: &mut DrawTarget) {
+        let Some(compare) = self.compare.as_ref() else {
+            return;
+        };
+        let a_color = Source::Solid(SolidSource {
+            r: 0x00,
+            g: 0xc0,
+            b: 0xff,
+            a: 0xff,
+        });
+        let b_color = Source::Solid(SolidSource {
+            r: 0xff,
+            g: 0x40,
+            b: 0xff,
+            a: 0xff,
+        });
+        let options = DrawOptions::new();
+
+        // side by side splits the window in two, each with its own origin
+        let (a_center, b_center) = match compare.layout {
+            CompareLayout::Overlay => ((self.cx, self.cy), (self.cx, self.cy)),
+            CompareLayout::SideBySide => (
+                (self.width as f32 * 0.25, self.cy),
+                (self.width as f32 * 0.75, self.cy),
+            ),
+        };
+        let screen = |(cx, cy): (f32, f32), bin: usize, range: f32| {
+            let theta = RangeProfile::bearing(bin).to_radians();
+            (
+                cx + range * theta.cos() / self.draw_scale,
+                cy + range * theta.sin() / self.draw_scale,
+            )
+        };
+
+        if let (CompareLayout::Overlay, Some(b), Some(diff)) =
+            (compare.layout, compare.b.as_ref(), compare.diff.as_ref())
+        {
+            // a line from a to b at every bearing that changed, orange where
+            // b is farther and green where it's closer
+            let farther = Source::Solid(SolidSource {
+                r: 0xff,
+                g: 0xa0,
+                b: 0x00,
+                a: 0xff,
+            });
+            let closer = Source::Solid(SolidSource {
+                r: 0x40,
+                g: 0xff,
+                b: 0x40,
+                a: 0xff,
+            });
+            let style = StrokeStyle {
+                width: 1.0,
+                ..StrokeStyle::default()
+            };
+            let (mut far_path, mut close_path) = (PathBuilder::new(), PathBuilder::new());
+            for (bin, d) in diff.per_angle.iter().enumerate() {
+                let (Some(d), Some(ra), Some(rb)) = (d, compare.a.ranges[bin], b.ranges[bin])
+                else {
+                    continue;
+                };
+                let path = if *d > 0.0 {
+                    &mut far_path
+                } else {
+                    &mut close_path
+                };
+                let (ax, ay) = screen(a_center, bin, ra);
+                let (bx, by) = screen(b_center, bin, rb);
+                path.move_to(ax, ay);
+                path.line_to(bx, by);
+            }
+            view.stroke(&far_path.finish(), &farther, &style, &options);
+            view.stroke(&close_path.finish(), &closer, &style, &options);
+        }
+
+        let profiles = [
+            (a_center, Some(&compare.a), &a_color),
+            (b_center, compare.b.as_ref(), &b_color),
+        ];
+        for (center, profile, color) in profiles {
+            let Some(profile) = profile else {
+                continue;
+            };
+            for (bin, range) in profile.ranges.iter().enumerate() {
+                if let Some(range) = range {
+                    let (x, y) = screen(center, bin, *range);
+                    view.fill_rect(x - 1.0, y - 1.0, 3.0, 3.0, color, &options);
+                }
+            }
+        }
+
+        if let Some(font) = self.font.as_ref() {
+            view.draw_text(
+                font,
+                12.0,
+                &compare.summary(),
+                raqote::Point::new(12.0, self.height as f32 - 40.0),
+                &Source::Solid(SolidSource {
+                    r: 0xff,
+                    g: 0xff,
+                    b: 0xff,
+                    a: 0xff,
+                }),
+                &options,
+            );
+        }
+    }
+
+    fn draw_filtered(&self, view: &mut DrawTarget) {
+        let options = DrawOptions::new();
+        let filtered = Source::Solid(SolidSource {
+            r: 0x40,
+            g: 0xff,
+            b: 0x80,
+            a: 0xff,
+        });
+        for (x, y) in self.filtered.iter().flatten() {
+            let (px, py) = self.to_screen(*x, *y);
+            view.fill_rect(px - 1.5, py - 1.5, 3.0, 3.0, &filtered, &options);
+        }
+
+        let outlier = Source::Solid(SolidSource {
+            r: 0xff,
+            g: 0x40,
+            b: 0x40,
+            a: 0xff,
+        });
+        let mut path = PathBuilder::new();
+        for (x, y) in self.outliers.iter().flatten() {
+            let (px, py) = self.to_screen(*x, *y);
+            path.move_to(px - 2.5, py - 2.5);
+            path.line_to(px + 2.5, py + 2.5);
+            path.move_to(px + 2.5, py - 2.5);
+            path.line_to(px - 2.5, py + 2.5);
+        }
+        view.stroke(&path.finish(), &outlier, &StrokeStyle::default(), &options);
+    }
+
+    fn draw_trajectory(&self, view: &mut DrawTarget) {
+        let Some(current) = self.trajectory.last() else {
+            return;
+        };
+        // the view is the robot frame, so everything's drawn as seen from
+        // where odometry thinks the robot is now
+        let inverse = current.inverse();
+        let options = DrawOptions::new();
+        let style = StrokeStyle {
+            width: 2.0,
+            ..StrokeStyle::default()
+        };
+        for (poses, (r, g, b)) in [
+            (&self.ground_truth, (0xa0, 0x60, 0xa0)),
+            (&self.trajectory, (0x40, 0xff, 0xc0)),
+        ] {
+            let mut path = PathBuilder::new();
+            for (i, pose) in poses.iter().enumerate() {
+                let (x, y) = inverse.apply(pose.x, pose.y);
+                let (sx, sy) = self.to_screen(x, y);
+                if i == 0 {
+                    path.move_to(sx, sy);
+                } else {
+                    path.line_to(sx, sy);
+                }
+            }
+            let source = Source::Solid(SolidSource { r, g, b, a: 0xff });
+            view.stroke(&path.finish(), &source, &style, &options);
+        }
+        if self.loops.is_empty() {
+            return;
+        }
+        let mut path = PathBuilder::new();
+        for (from, to) in &self.loops {
+            let (fx, fy) = inverse.apply(from.x, from.y);
+            let (tx, ty) = inverse.apply(to.x, to.y);
+            let (fx, fy) = self.to_screen(fx, fy);
+            let (tx, ty) = self.to_screen(tx, ty);
+            path.move_to(fx, fy);
+            path.line_to(tx, ty);
+        }
+        let source = Source::Solid(SolidSource {
+            r: 0xff,
+            g: 0xe0,
+            b: 0x40,
+            a: 0xff,
+        });
+        view.stroke(&path.finish(), &source, &StrokeStyle::default(), &options);
+    }
+
+    fn draw_tracks(&self, view: &mut DrawTarget) {
+        if self.tracks.is_empty() {
+            return;
+        }
+        let options = DrawOptions::new();
+        let source = Source::Solid(SolidSource {
+            r: 0x40,
+            g: 0xc0,
+            b: 0xff,
+            a: 0xff,
+        });
+        let mut path = PathBuilder::new();
+        for track in &self.tracks {
+            let (px, py) = self.to_screen(track.position.0, track.position.1);
+            path.rect(px - 3.0, py - 3.0, 6.0, 6.0);
+            // arrows show where the object will be in a second
+            let (vx, vy) = track.velocity;
+            let (ex, ey) = self.to_screen(track.position.0 + vx, track.position.1 + vy);
+            let length = (ex - px).hypot(ey - py);
+            if length < 2.0 {
+                continue;
+            }
+            path.move_to(px, py);
+            path.line_to(ex, ey);
+            let (ux, uy) = ((ex - px) / length, (ey - py) / length);
+            let head = length.min(8.0);
+            path.move_to(ex - head * (ux - uy * 0.5), ey - head * (uy + ux * 0.5));
+            path.line_to(ex, ey);
+            path.line_to(ex - head * (ux + uy * 0.5), ey - head * (uy - ux * 0.5));
+        }
+        let style = StrokeStyle {
+            width: 1.5,
+            ..StrokeStyle::default()
+        };
+        view.stroke(&path.finish(), &source, &style, &options);
+
+        let Some(font) = self.font.as_ref() else {
+            return;
+        };
+        for track in &self.tracks {
+            let (px, py) = self.to_screen(track.position.0, track.position.1);
+            view.draw_text(
+                font,
+                12.0,
+                &format!("{} {:.1} m/s", track.id, track.speed() / 1000.0),
+                raqote::Point::new(px + 6.0, py - 6.0),
+                &source,
+                &options,
+            );
+        }
+    }
+
+    fn draw_people(&self, view: &mut DrawTarget) {
+        if self.legs.is_empty() && self.people.is_empty() {
+            return;
+        }
+        let options = DrawOptions::new();
+        let source = Source::Solid(SolidSource {
+            r: 0xff,
+            g: 0x60,
+            b: 0xc0,
+            a: 0xff,
+        });
+        let mut path = PathBuilder::new();
+        for leg in &self.legs {
+            let (px, py) = self.to_screen(leg.center.0, leg.center.1);
+            let radius = (leg.radius / self.draw_scale).max(2.0);
+            path.move_to(px + radius, py);
+            path.arc(px, py, radius, 0.0, std::f32::consts::TAU);
+        }
+        // a person is about shoulder width across
+        let radius = (250.0 / self.draw_scale).max(6.0);
+        for person in &self.people {
+            let (px, py) = self.to_screen(person.position.0, person.position.1);
+            path.move_to(px + radius, py);
+            path.arc(px, py, radius, 0.0, std::f32::consts::TAU);
+        }
+        view.stroke(&path.finish(), &source, &StrokeStyle::default(), &options);
+
+        let Some(font) = self.font.as_ref() else {
+            return;
+        };
+        for person in &self.people {
+            let (px, py) = self.to_screen(person.position.0, person.position.1);
+            view.draw_text(
+                font,
+                12.0,
+                &format!("#{}", person.id),
+                raqote::Point::new(px + radius + 2.0, py),
+                &source,
+                &options,
+            );
+        }
+    }
+
+    fn draw_clusters(&self, view: &mut DrawTarget) {
+        let options = DrawOptions::new();
+        for (i, cluster) in self.clusters.iter().enumerate() {
+            // stepping by the golden ratio keeps neighbouring clusters far
+            // apart on the map
+            let (r, g, b) = ColorMap::Turbo.sample((i as f32 * 0.618_034).fract());
+            let source = Source::Solid(SolidSource { r, g, b, a: 0xff });
+            for p in &cluster.points {
+                let (px, py) = self.to_screen(p.x, p.y);
+                view.fill_rect(px - 1.5, py - 1.5, 3.0, 3.0, &source, &options);
+            }
+            let (x0, y0) = self.to_screen(cluster.min.0, cluster.min.1);
+            let (x1, y1) = self.to_screen(cluster.max.0, cluster.max.1);
+            let mut path = PathBuilder::new();
+            path.rect(x0.min(x1), y0.min(y1), (x1 - x0).abs(), (y1 - y0).abs());
+            view.stroke(&path.finish(), &source, &StrokeStyle::default(), &options);
+        }
+    }
+
+    fn draw_segments(&self, view: &mut DrawTarget) {
+        if self.segments.is_empty() {
+            return;
+        }
+        let mut path = PathBuilder::new();
+        let mut ends = PathBuilder::new();
+        for segment in self.segments.iter().flatten() {
+            let (sx, sy) = self.to_screen(segment.start.0, segment.start.1);
+            let (ex, ey) = self.to_screen(segment.end.0, segment.end.1);
+            path.move_to(sx, sy);
+            path.line_to(ex, ey);
+            ends.rect(sx - 2.0, sy - 2.0, 4.0, 4.0);
+            ends.rect(ex - 2.0, ey - 2.0, 4.0, 4.0);
+        }
+        let source = Source::Solid(SolidSource {
+            r: 0xff,
+            g: 0xa0,
+            b: 0x20,
+            a: 0xff,
+        });
+        let style = StrokeStyle {
+            width: 2.0,
+            ..StrokeStyle::default()
+        };
+        view.stroke(&path.finish(), &source, &style, &DrawOptions::new());
+        view.fill(&ends.finish(), &source, &DrawOptions::new());
+    }
+
+    fn draw_highlight(&self, view: &mut DrawTarget) {
+        if self.highlight.is_empty() {
+            return;
+        }
+        let source = Source::Solid(SolidSource {
+            r: 0xff,
+            g: 0xff,
+            b: 0x00,
+            a: 0xff,
+        });
+        let style = StrokeStyle {
+            width: 1.0,
+            ..StrokeStyle::default()
+        };
+        let mut path = PathBuilder::new();
+        for &(x, y) in &self.highlight {
+            let (px, py) = self.to_screen(x, y);
+            path.rect(px - 2.5, py - 2.5, 5.0, 5.0);
+        }
+        view.stroke(&path.finish(), &source, &style, &DrawOptions::new());
+    }
+
+    fn draw_status(&self, view: &mut DrawTarget) {
+        let (Some(status), Some(font)) = (self.status.as_ref(), self.font.as_ref()) else {
+            return;
+        };
+        let source = Source::Solid(if self.status_alert {
+            SolidSource {
+                r: 0xff,
+                g: 0x40,
+                b: 0x40,
+                a: 0xff,
+            }
+        } else {
+            SolidSource {
+                r: 0xff,
+                g: 0xff,
+                b: 0xff,
+                a: 0xff,
+            }
+        });
+        view.draw_text(
+            font,
+            12.0,
+            status,
+            raqote::Point::new(12.0, self.height as f32 - 24.0),
+            &source,
+            &DrawOptions::new(),
+        );
+    }
+
+    fn draw_sensor_lines(&self, view: &mut DrawTarget) {
+        let Some(font) = self.font.as_ref() else {
+            return;
+        };
+        // there's no text measuring, so leave room for a typical line
+        let x = self.width as f32 - 300.0;
+        for (i, (line, alert)) in self.sensor_lines.iter().enumerate() {
+            let source = Source::Solid(if *alert {
+                SolidSource {
+                    r: 0xff,
+                    g: 0x40,
+                    b: 0x40,
+                    a: 0xff,
+                }
+            } else {
+                SolidSource {
+                    r: 0xff,
+                    g: 0xff,
+                    b: 0xff,
+                    a: 0xff,
+                }
+            });
+            view.draw_text(
+                font,
+                12.0,
+                line,
+                raqote::Point::new(x, 18.0 + i as f32 * 16.0),
+                &source,
+                &DrawOptions::new(),
+            );
+        }
+    }
+
+    fn draw_legend(&self, view: &mut DrawTarget) {
+        let Some((bottom, top)) = self.color_by.legend_labels() else {
+            return;
+        };
+        const X: f32 = 12.0;
+        const Y: f32 = 28.0;
+        const BAR_WIDTH: f32 = 12.0;
+        const BAR_HEIGHT: f32 = 160.0;
+
+        // top of the bar is the top of the map
+        for i in 0..BAR_HEIGHT as u32 {
+            let t = 1.0 - (i as f32 / (BAR_HEIGHT - 1.0));
+            let (r, g, b) = self.color_map.sample(t);
+            view.fill_rect(
+                X,
+                Y + i as f32,
+                BAR_WIDTH,
+                1.0,
+                &Source::Solid(SolidSource { r, g, b, a: 0xff }),
+                &DrawOptions::new(),
+            );
+        }
+
+        let Some(font) = self.font.as_ref() else {
+            return;
+        };
+        let text = Source::Solid(SolidSource {
+            r: 0xff,
+            g: 0xff,
+            b: 0xff,
+            a: 0xff,
+        });
+        let options = DrawOptions::new();
+        let title = format!("{} ({})", self.color_by, self.color_map);
+        view.draw_text(
+            font,
+            12.0,
+            &title,
+            raqote::Point::new(X, Y - 10.0),
+            &text,
+            &options,
+        );
+        view.draw_text(
+            font,
+            12.0,
+            &top,
+            raqote::Point::new(X + BAR_WIDTH + 6.0, Y + 10.0),
+            &text,
+            &options,
+        );
+        view.draw_text(
+            font,
+            12.0,
+            &bottom,
+            raqote::Point::new(X + BAR_WIDTH + 6.0, Y + BAR_HEIGHT),
+            &text,
+            &options,
+        );
+    }
+
+    fn draw_measure(&self, view: &mut DrawTarget) {
+        if !self.measure.active || self.measure.points.is_empty() {
+            return;
+        }
+        let source = Source::Solid(SolidSource {
+            r: 0x00,
+            g: 0xc0,
+            b: 0xff,
+            a: 0xff,
+        });
+        let style = StrokeStyle {
+            width: 1.0,
+            ..StrokeStyle::default()
+        };
+
+        let mut path = PathBuilder::new();
+        for (i, point) in self.measure.points.iter().enumerate() {
+            let (px, py) = self.to_screen(point.x, point.y);
+            // line from the origin to the point, plus a little marker box
+            path.move_to(self.cx, self.cy);
+            path.line_to(px, py);
+            path.rect(px - 3.0, py - 3.0, 6.0, 6.0);
+            if i > 0 {
+                let previous = self.measure.points[i - 1];
+                let (qx, qy) = self.to_screen(previous.x, previous.y);
+                path.move_to(qx, qy);
+                path.line_to(px, py);
+            }
+        }
+        view.stroke(&path.finish(), &source, &style, &DrawOptions::new());
+    }
+
+    // enough points for every held revolution from every sensor
+    fn history_limit(&self) -> usize {
+        let sensors = self.newest_revolution.len().max(1);
+        let held = self.hold.unwrap_or(0) as usize * sensors * REVOLUTION_POINTS;
+        POINT_HISTORY.max(held)
+    }
+
+    /// Draw all of the shapes
+    pub fn draw(&mut self, command_buffer: Vec<DrawPoint>) {
+        let now = Instant::now();
+        let limit = self.history_limit();
+        while self.history.len() + command_buffer.len() > limit {
+            if self.history.pop_front().is_none() {
+                break;
+            }
+        }
+
+        // holding drops a sensor's oldest revolution whenever a new one
+        // starts, so the whole thing has to be drawn again
+        let mut newest: Vec<Option<u32>> = Vec::new();
+        for point in &command_buffer {
+            let sensor = point.sensor as usize;
+            if newest.len() <= sensor {
+                newest.resize(sensor + 1, None);
+            }
+            newest[sensor] = newest[sensor].max(Some(point.revolution));
+        }
+        if self.newest_revolution.len() < newest.len() {
+            self.newest_revolution.resize(newest.len(), 0);
+        }
+        let mut started = false;
+        for (old, new) in self.newest_revolution.iter_mut().zip(newest) {
+            if let Some(new) = new.filter(|n| n != old) {
+                *old = new;
+                started = true;
+            }
+        }
+        if started && self.hold.is_some() {
+            self.history.extend(
+                command_buffer
+                    .into_iter()
+                    .map(|point| HistoryPoint { point, at: now }),
+            );
+            self.redraw();
+            return;
+        }
+
+        let projection = self.projection();
+        let dt = self.dt.as_mut().unwrap();
+        for command in &command_buffer {
+            let (x, y) = projection.project(command);
+            fill_point(
+                dt,
+                self.point_style,
+                x,
+                y,
+                point_color(self.color_map, self.color_by, command, 0.0),
+            );
+        }
+
+        self.history.extend(
+            command_buffer
+                .into_iter()
+                .map(|point| HistoryPoint { point, at: now }),
+        );
+    }
+}
+
+fn point_color(map: ColorMap, by: ColorBy, point: &DrawPoint, age: f32) -> SolidSource {
+    let t = match by {
+        ColorBy::Intensity => point.intensity as f32 / INTENSITY_MAX,
+        ColorBy::Range => point.distance as f32 / RANGE_MAX_MM,
+        // newest points at the top of the map
+        ColorBy::Age => 1.0 - (age / AGE_MAX_SECS),
+        ColorBy::Revolution => {
+            (point.revolution % REVOLUTION_CYCLE) as f32 / (REVOLUTION_CYCLE - 1) as f32
+        }
+        ColorBy::Source => {
+            return SolidSource {
+                r: point.r,
+                g: point.g,
+                b: point.b,
+                a: 0xff,
+            }
+        }
+    };
+    let (r, g, b) = map.sample(t);
+    SolidSource { r, g, b, a: 0xff }
+}
+
+fn fill_point(dt: &mut DrawTarget, style: PointStyle, x: f32, y: f32, color: SolidSource) {
+    match style.backend {
+        RenderBackend::Direct => {
+            let width = dt.width() as usize;
+            let color = raster::pack(color.r, color.g, color.b);
+            if style.antialias {
+                raster::plot_aa(dt.get_data_mut(), width, x, y, style.size, color);
+            } else {
+                raster::plot(dt.get_data_mut(), width, x, y, style.size, color);
+            }
+        }
+        RenderBackend::Raqote => {
+            let mut path = PathBuilder::new();
+            path.rect(x, y, style.size, style.size);
+            let path = path.finish();
+            let options = DrawOptions {
+                antialias: if style.antialias {
+                    AntialiasMode::Gray
+                } else {
+                    AntialiasMode::None
+                },
+                ..DrawOptions::new()
+            };
+            dt.fill(&path, &Source::Solid(color), &options);
+        }
+    }
+}
+
+// copy a layer's pixels in wherever the background still shows, 0 is
+// transparent
+fn underlay(view: &mut DrawTarget, pixels: &[u32], background: u32) {
+    for (dst, src) in view.get_data_mut().iter_mut().zip(pixels) {
+        if *dst == background && *src != 0 {
+            *dst = *src;
         }
     }
 }
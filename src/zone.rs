@@ -0,0 +1,188 @@
+// intrusion zones. a zone is occupied while any foreground cluster's centroid
+// is inside it, and the monitor reports when that changes
+use crate::cluster::Cluster;
+use crate::geometry::Polygon;
+use core::fmt;
+
+#[derive(Debug, Clone)]
+pub struct Zone {
+    pub name: String,
+    // robot frame millimeters
+    pub polygon: Polygon,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ZoneEvent {
+    // index into the monitor's zones, and how many clusters are in it
+    Enter { zone: usize, clusters: usize },
+    Leave { zone: usize },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ZoneMonitor {
+    pub zones: Vec<Zone>,
+    // clusters inside each zone as of the last update
+    occupants: Vec<usize>,
+}
+
+impl ZoneMonitor {
+    pub fn new(zones: Vec<Zone>) -> Self {
+        Self {
+            occupants: vec![0; zones.len()],
+            zones,
+        }
+    }
+
+    pub fn is_occupied(&self, zone: usize) -> bool {
+        self.occupants.get(zone).is_some_and(|n| *n > 0)
+    }
+
+    /// clusters inside each zone, in the same order as the zones
+    pub fn occupants(&self) -> &[usize] {
+        &self.occupants
+    }
+
+    /// check the foreground clusters against every zone and return what
+    /// changed since last time
+    pub fn update(&mut self, clusters: &[Cluster]) -> Vec<ZoneEvent> {
+        let mut events = Vec::new();
+        for (i, zone) in self.zones.iter().enumerate() {
+            let inside = clusters
+                .iter()
+                .filter(|c| zone.polygon.contains(c.centroid.0, c.centroid.1))
+                .count();
+            match (self.occupants[i] > 0, inside > 0) {
+                (false, true) => events.push(ZoneEvent::Enter {
+                    zone: i,
+                    clusters: inside,
+                }),
+                (true, false) => events.push(ZoneEvent::Leave { zone: i }),
+                _ => (),
+            }
+            self.occupants[i] = inside;
+        }
+        events
+    }
+
+    /// forget who's where, e.g. after the background is relearned
+    pub fn clear(&mut self) {
+        self.occupants.iter_mut().for_each(|n| *n = 0);
+    }
+
+    /// human readable version of an event
+    pub fn describe(&self, event: &ZoneEvent) -> String {
+        match event {
+            ZoneEvent::Enter { zone, clusters } => {
+                format!("{} entered ({} objects)", self.name(*zone), clusters)
+            }
+            ZoneEvent::Leave { zone } => format!("{} clear", self.name(*zone)),
+        }
+    }
+
+    fn name(&self, zone: usize) -> &str {
+        self.zones.get(zone).map_or("?", |z| z.name.as_str())
+    }
+}
+
+impl fmt::Display for ZoneEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ZoneEvent::Enter { zone, clusters } => {
+                write!(f, "zone {zone} entered ({clusters} objects)")
+            }
+            ZoneEvent::Leave { zone } => write!(f, "zone {zone} clear"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::ScanPoint;
+
+    fn blob(x: f32, y: f32) -> Cluster {
+        let point = ScanPoint {
+            x,
+            y,
+            angle: 0.0,
+            distance: 1000,
+            intensity: 200,
+            sensor: 0,
+        };
+        Cluster::new(vec![point; 3])
+    }
+
+    fn monitor() -> ZoneMonitor {
+        let square = |x: f32| {
+            Polygon::new(vec![
+                (x, 0.0),
+                (x + 1000.0, 0.0),
+                (x + 1000.0, 1000.0),
+                (x, 1000.0),
+            ])
+        };
+        ZoneMonitor::new(vec![
+            Zone {
+                name: String::from("door"),
+                polygon: square(0.0),
+            },
+            Zone {
+                name: String::from("desk"),
+                polygon: square(2000.0),
+            },
+        ])
+    }
+
+    #[test]
+    fn reports_entering_and_leaving() {
+        let mut monitor = monitor();
+        assert!(monitor.update(&[blob(5000.0, 5000.0)]).is_empty());
+
+        let events = monitor.update(&[blob(500.0, 500.0), blob(600.0, 200.0)]);
+        assert_eq!(
+            events,
+            vec![ZoneEvent::Enter {
+                zone: 0,
+                clusters: 2
+            }]
+        );
+        assert_eq!(monitor.describe(&events[0]), "door entered (2 objects)");
+        assert!(monitor.is_occupied(0));
+        assert!(!monitor.is_occupied(1));
+
+        // still there is no news, moving across is
+        assert!(monitor.update(&[blob(500.0, 500.0)]).is_empty());
+        assert_eq!(monitor.occupants(), &[1, 0]);
+        let events = monitor.update(&[blob(2500.0, 500.0)]);
+        assert_eq!(
+            events,
+            vec![
+                ZoneEvent::Leave { zone: 0 },
+                ZoneEvent::Enter {
+                    zone: 1,
+                    clusters: 1
+                }
+            ]
+        );
+        assert_eq!(monitor.describe(&events[0]), "door clear");
+
+        let events = monitor.update(&[]);
+        assert_eq!(events, vec![ZoneEvent::Leave { zone: 1 }]);
+    }
+
+    #[test]
+    fn clearing_forgets_occupants() {
+        let mut monitor = monitor();
+        monitor.update(&[blob(500.0, 500.0)]);
+        monitor.clear();
+        assert!(!monitor.is_occupied(0));
+        let events = monitor.update(&[blob(500.0, 500.0)]);
+        assert_eq!(
+            events,
+            vec![ZoneEvent::Enter {
+                zone: 0,
+                clusters: 1
+            }]
+        );
+    }
+}